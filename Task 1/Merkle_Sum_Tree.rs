@@ -1,4 +1,139 @@
-use sha2::{Digest, Sha256};
+// Builds without `std` for embedded verifiers; the `std` feature (on by default)
+// adds the modules that need I/O or hash maps
+#![no_std]
+
+extern crate alloc;
+#[cfg(feature = "std")]
+extern crate std;
+
+use alloc::vec;
+use alloc::vec::Vec;
+use core::fmt;
+use core::marker::PhantomData;
+
+use rand::{CryptoRng, RngCore};
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+use sha2::digest::consts::U32;
+use sha2::digest::Digest;
+use sha2::Sha256;
+use subtle::ConstantTimeEq;
+#[cfg(feature = "zeroize")]
+use zeroize::{Zeroize, ZeroizeOnDrop};
+
+mod aggregate;
+mod async_store;
+mod audit;
+#[cfg(feature = "std")]
+mod batch;
+mod builder;
+mod cache;
+#[cfg(feature = "cli")]
+mod cli;
+mod codec;
+mod diff;
+#[cfg(feature = "ffi")]
+mod ffi;
+mod iter;
+mod ledger;
+mod levels;
+#[cfg(feature = "std")]
+mod liabilities;
+#[cfg(feature = "std")]
+mod map;
+#[cfg(feature = "mmap")]
+mod mapped;
+mod mmr;
+mod mss;
+mod multiproof;
+#[cfg(feature = "parallel")]
+mod parallel;
+mod patricia;
+#[cfg(feature = "pedersen")]
+mod pedersen;
+#[cfg(feature = "poseidon")]
+mod poseidon;
+mod positioned;
+#[cfg(feature = "python")]
+mod python;
+#[cfg(feature = "r1cs")]
+mod r1cs;
+mod records;
+#[cfg(feature = "sled")]
+mod sled_store;
+mod signed;
+mod skiplist;
+#[cfg(feature = "std")]
+mod snapshot;
+mod solvency;
+mod sorted;
+mod span;
+mod sparse;
+mod storage;
+mod subtree;
+#[cfg(feature = "testing")]
+pub mod testing;
+#[cfg(feature = "ed25519")]
+mod tree_head;
+mod truncated;
+#[cfg(feature = "cli")]
+mod vectors;
+#[cfg(feature = "verkle")]
+mod verkle;
+mod versioned;
+#[cfg(feature = "wasm")]
+mod wasm;
+#[cfg(feature = "zeroize")]
+mod wipe;
+
+pub use aggregate::{AggregatedLeaf, AggregatedProof};
+pub use async_store::{AsyncNodeStore, AsyncStoredTree};
+pub use audit::{AuditReport, Check, CheckResult, Discrepancy, NamedDigest};
+#[cfg(feature = "std")]
+pub use batch::verify_batch;
+pub use builder::TreeBuilder;
+pub use cache::CachedProver;
+#[cfg(feature = "cli")]
+pub use cli::{run_cli, CliError};
+pub use codec::DecodeError;
+pub use diff::LeafChange;
+pub use iter::DepthFirst;
+pub use ledger::{ConsistencyProof, Ledger};
+pub use levels::{LevelArrays, TreeLevels};
+#[cfg(feature = "std")]
+pub use liabilities::{BundleError, Liabilities, UserBundle};
+#[cfg(feature = "std")]
+pub use map::{MapError, MerkleSumMap};
+#[cfg(feature = "mmap")]
+pub use mapped::MappedTree;
+pub use mmr::{MmrProof, MountainRange};
+pub use mss::{MerkleSignature, SigningKey};
+pub use multiproof::MultiProof;
+pub use patricia::{PatriciaTrie, TrieProof, TrieStep};
+#[cfg(feature = "pedersen")]
+pub use pedersen::{PedersenProof, PedersenRoot, PedersenTree};
+#[cfg(feature = "poseidon")]
+pub use poseidon::{pack_bytes, poseidon_config, Poseidon};
+pub use positioned::{PositionedProof, PositionedTree};
+#[cfg(feature = "r1cs")]
+pub use r1cs::{enforce_proof, AllotmentCircuit};
+pub use records::{RecordProof, RecordTree, SummableLeaf};
+#[cfg(feature = "sled")]
+pub use sled_store::SledStore;
+pub use signed::{SignedError, SignedPolicy, SignedProof, SignedTree};
+pub use skiplist::{SkipLevel, SkipList, SkipProof};
+pub use solvency::{ReserveAttestation, SolvencyError, SolvencyStatement};
+pub use sorted::{ExclusionProof, SortedError, SortedProof, SortedTree};
+pub use span::SpanProof;
+pub use sparse::{SparseMerkleSumTree, SparseProof};
+pub use storage::{NodeStore, StoreError, StoredTree};
+pub use subtree::{NestedProof, SubtreeProof};
+#[cfg(feature = "ed25519")]
+pub use tree_head::{load_signing_key, load_verifying_key, SignedRoot, TreeHeadError};
+pub use truncated::Truncated;
+#[cfg(feature = "verkle")]
+pub use verkle::{KzgSetup, VerkleOpening, VerkleProof, VerkleTree};
+pub use versioned::VersionedTree;
 
 pub trait SumCommitment {
     fn amount(&self) -> u64;
@@ -12,26 +147,243 @@ pub trait ExclusiveAllotmentProof<C: SumCommitment> {
 }
 
 pub trait MerkleTree<C: SumCommitment, P: ExclusiveAllotmentProof<C>> {
+    // Panics on bad input, servers should use `try_new`
     fn new(values: Vec<u64>) -> Self;
+    // Falls back to `new` for implementors written before it existed, so those
+    // still panic on bad input until they override it
+    fn try_new(values: Vec<u64>) -> Result<Self, BuildError>
+    where
+        Self: Sized,
+    {
+        Ok(Self::new(values))
+    }
     fn commit(&self) -> C;
     fn prove(&self, position: usize) -> P;
 }
 
-fn hash_bytes(slice: &[u8]) -> [u8; 32] {
-    let mut hasher = Sha256::new();
+// Any 256-bit `Digest` can back a tree, e.g. SHA-256, SHA3-256, Keccak-256 or BLAKE3
+pub trait TreeDigest: Digest<OutputSize = U32> {}
+
+impl<D: Digest<OutputSize = U32>> TreeDigest for D {}
+
+fn hash_bytes<D: TreeDigest>(slice: &[u8]) -> [u8; 32] {
+    let mut hasher = D::new();
     hasher.update(slice);
     hasher.finalize().into()
 }
 
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug, Default)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum Encoding {
+    // Untagged leaves and branches, kept so roots built before domain separation still validate
+    V0,
+    // Leaves prefixed with LEAF_TAG and branches with BRANCH_TAG. Like V0 it hashes
+    // heights as a native usize, so roots differ between 32- and 64-bit targets.
+    V1,
+    // As V1 with the encoding version after the tag and heights always as u64
+    #[default]
+    V2,
+}
+
+const LEAF_TAG: u8 = 0x00;
+const BRANCH_TAG: u8 = 0x01;
+const TOMBSTONE_TAG: u8 = 0x02;
+// Follows the tag in V2 leaves and branches
+const V2_VERSION: u8 = 0x02;
+// Bagging the peaks of a mountain range; 0x03 is the sparse tree's empty leaf
+const BAG_TAG: u8 = 0x04;
+
+// Secret per-leaf randomness, handed to the leaf owner inside their proof so that
+// nobody else can brute-force the balance from the leaf hash. With `zeroize` it is
+// wiped when dropped.
+#[derive(Clone, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "zeroize", derive(Zeroize, ZeroizeOnDrop))]
+pub struct Blinding {
+    pub salt: [u8; 32],
+    // Hash of the account identifier, binding the leaf to its owner
+    pub user_id: Option<[u8; 32]>,
+}
+
+impl Blinding {
+    pub fn random<R: RngCore + CryptoRng>(rng: &mut R, user_id: Option<[u8; 32]>) -> Self {
+        let mut salt = [0u8; 32];
+        rng.fill_bytes(&mut salt);
+        Self { salt, user_id }
+    }
+}
+
+fn leaf_digest<D: TreeDigest>(encoding: Encoding, value: u64, blinding: Option<&Blinding>) -> [u8; 32] {
+    let tag: &[u8] = match encoding {
+        Encoding::V0 => &[],
+        Encoding::V1 => &[LEAF_TAG],
+        Encoding::V2 => &[LEAF_TAG, V2_VERSION],
+    };
+    let (salt, user_id): (&[u8], &[u8]) = match blinding {
+        Some(Blinding { salt, user_id }) => (salt, user_id.as_ref().map_or(&[], |id| id.as_slice())),
+        None => (&[], &[]),
+    };
+    let serialized = [tag, value.to_be_bytes().as_slice(), salt, user_id].concat();
+    // Balance and salt side by side are exactly what blinding keeps secret
+    #[cfg(feature = "zeroize")]
+    let serialized = zeroize::Zeroizing::new(serialized);
+    hash_bytes::<D>(&serialized)
+}
+
+fn branch_digest<D: TreeDigest>(encoding: Encoding, height: usize, sum: u64, left: &[u8; 32], right: &[u8; 32]) -> [u8; 32] {
+    let tag: &[u8] = match encoding {
+        Encoding::V0 => &[],
+        Encoding::V1 => &[BRANCH_TAG],
+        Encoding::V2 => &[BRANCH_TAG, V2_VERSION],
+    };
+    let (native, fixed) = (height.to_be_bytes(), (height as u64).to_be_bytes());
+    let height: &[u8] = match encoding {
+        Encoding::V0 | Encoding::V1 => &native,
+        Encoding::V2 => &fixed,
+    };
+    let serialized = [
+        tag,
+        height,
+        sum.to_be_bytes().as_slice(),
+        left.as_slice(),
+        right.as_slice(),
+    ]
+    .concat();
+    hash_bytes::<D>(&serialized)
+}
+
+// Shorter than any leaf or branch serialization under either encoding
+fn tombstone_digest<D: TreeDigest>() -> [u8; 32] {
+    hash_bytes::<D>(&[TOMBSTONE_TAG])
+}
+
 // ------------------------------------------------------------------------
 
 #[derive(Copy, Clone, PartialEq, Eq, Debug)]
-struct Commitment {
+pub struct SumOverflow;
+
+impl fmt::Display for SumOverflow {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "sum of leaf values exceeds u64::MAX")
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for SumOverflow {}
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum BuildError {
+    SumOverflow,
+    // Trees hold 2^n leaves, padding has to be asked for
+    NotPowerOfTwo { len: usize },
+    // Heights of the two subtrees joined into a branch
+    HeightMismatch { left: usize, right: usize },
+    EncodingMismatch,
+}
+
+impl fmt::Display for BuildError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BuildError::SumOverflow => write!(f, "{}", SumOverflow),
+            BuildError::NotPowerOfTwo { len } => write!(f, "{} leaves is not a power of two", len),
+            BuildError::HeightMismatch { left, right } => {
+                write!(f, "cannot join subtrees of heights {} and {}", left, right)
+            }
+            BuildError::EncodingMismatch => write!(f, "cannot join subtrees with different encodings"),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for BuildError {}
+
+impl From<SumOverflow> for BuildError {
+    fn from(_: SumOverflow) -> Self {
+        BuildError::SumOverflow
+    }
+}
+
+impl BuildError {
+    // For trees whose shape is valid by construction, where only the sum can fail
+    fn into_overflow(self) -> SumOverflow {
+        assert!(self == BuildError::SumOverflow, "{}", self);
+        SumOverflow
+    }
+}
+
+// Where a proof stopped matching; heights count up from the leaf at 0
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum VerifyError {
+    // The index needs more levels than the proof has siblings
+    LengthMismatch,
+    // Adding the sibling at this height overflows u64
+    SumOverflow { height: usize },
+    SumMismatch { height: usize },
+    // At height 0 the leaf hash does not match its value and blinding
+    HashMismatch { height: usize },
+}
+
+impl fmt::Display for VerifyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            VerifyError::LengthMismatch => write!(f, "proof is too short for its leaf index"),
+            VerifyError::SumOverflow { height } => write!(f, "sum overflows at height {}", height),
+            VerifyError::SumMismatch { height } => write!(f, "sum does not match the root at height {}", height),
+            VerifyError::HashMismatch { height } => write!(f, "hash does not match at height {}", height),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for VerifyError {}
+
+// The digest parameter only tags which hash produced `hash`, so the usual
+// traits are implemented by hand rather than derived with a bound on `D`
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize), serde(bound = ""))]
+pub struct Commitment<D = Sha256> {
     pub sum: u64,
     pub hash: [u8; 32],
+    #[cfg_attr(feature = "serde", serde(skip))]
+    hasher: PhantomData<fn() -> D>,
+}
+
+impl<D> Commitment<D> {
+    pub fn new(sum: u64, hash: [u8; 32]) -> Self {
+        Self {
+            sum,
+            hash,
+            hasher: PhantomData,
+        }
+    }
+}
+
+impl<D> Copy for Commitment<D> {}
+
+impl<D> Clone for Commitment<D> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+// Constant time, as verifiers compare commitments they were handed against the root
+impl<D> PartialEq for Commitment<D> {
+    fn eq(&self, other: &Self) -> bool {
+        (self.sum.ct_eq(&other.sum) & self.hash.ct_eq(&other.hash)).into()
+    }
 }
 
-impl SumCommitment for Commitment {
+impl<D> Eq for Commitment<D> {}
+
+impl<D> fmt::Debug for Commitment<D> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Commitment")
+            .field("sum", &self.sum)
+            .field("hash", &self.hash)
+            .finish()
+    }
+}
+
+impl<D> SumCommitment for Commitment<D> {
     fn amount(&self) -> u64 {
         self.sum
     }
@@ -40,208 +392,879 @@ impl SumCommitment for Commitment {
     }
 }
 
+// The whole tree lives in one `Vec` in post-order, so children are referenced by
+// index instead of boxed and the root is always the last entry. Every builder
+// produces the same layout, which lets equal trees compare entry by entry.
+#[derive(Clone, Debug)]
+pub struct Node<D = Sha256> {
+    entries: Vec<Entry>,
+    hasher: PhantomData<fn() -> D>,
+}
+
+// One node as `NodeStore` backends persist it, children referenced by index
 #[derive(Clone, PartialEq, Eq, Debug)]
-enum Node {
+pub enum Entry {
     Branch {
         height: usize,
         sum: u64,
-        left: Box<Node>,
-        right: Box<Node>,
+        left: usize,
+        right: usize,
         commitment: [u8; 32],
+        encoding: Encoding,
     },
     Leaf {
         value: u64,
         commitment: [u8; 32],
+        encoding: Encoding,
+        blinding: Option<Blinding>,
     },
 }
 
-impl Node {
-    pub fn height(&self) -> usize {
+impl<D> PartialEq for Node<D> {
+    fn eq(&self, other: &Self) -> bool {
+        self.entries == other.entries
+    }
+}
+
+impl<D> Eq for Node<D> {}
+
+impl Entry {
+    fn height(&self) -> usize {
         match self {
-            Node::Branch { height, .. } => *height,
-            Node::Leaf { .. } => 0,
-        }
-    }
-
-    pub fn new_branch(left: Node, right: Node) -> Self {
-        // We only deal with balanced trees
-        assert!(left.height() == right.height());
-        // Own height is one level above
-        let height = left.height() + 1;
-        let sum = left.amount() + right.amount();
-        let serialized = [
-            height.to_be_bytes().as_slice(),
-            sum.to_be_bytes().as_slice(),
-            left.digest().as_slice(),
-            right.digest().as_slice(),
-        ]
-        .concat();
-
-        let left = Box::new(left);
-        let right = Box::new(right);
-        let commitment = hash_bytes(&serialized);
-        Self::Branch {
-            height,
-            sum,
-            left,
-            right,
-            commitment,
+            Entry::Branch { height, .. } => *height,
+            Entry::Leaf { .. } => 0,
         }
     }
 
-    pub fn new_leaf(value: u64) -> Self {
-        let serialized = value.to_be_bytes();
-        let commitment = hash_bytes(&serialized);
+    fn encoding(&self) -> Encoding {
+        match self {
+            Entry::Branch { encoding, .. } => *encoding,
+            Entry::Leaf { encoding, .. } => *encoding,
+        }
+    }
+
+    // The same entry once every index in the tree has moved up by `offset`
+    fn shifted(self, offset: usize) -> Self {
+        match self {
+            Entry::Branch {
+                height,
+                sum,
+                left,
+                right,
+                commitment,
+                encoding,
+            } => Entry::Branch {
+                height,
+                sum,
+                left: left + offset,
+                right: right + offset,
+                commitment,
+                encoding,
+            },
+            leaf => leaf,
+        }
+    }
+}
 
-        Self::Leaf { value, commitment }
+fn leaf_entry<D: TreeDigest>(value: u64, blinding: Option<Blinding>, encoding: Encoding) -> Entry {
+    let commitment = leaf_digest::<D>(encoding, value, blinding.as_ref());
+    Entry::Leaf {
+        value,
+        commitment,
+        encoding,
+        blinding,
     }
 }
 
-impl From<&Node> for Commitment {
-    fn from(node: &Node) -> Commitment {
+fn branch_entry<D: TreeDigest>(entries: &[Entry], left: usize, right: usize) -> Result<Entry, SumOverflow> {
+    join_entries::<D>((left, &entries[left]), (right, &entries[right]))
+}
+
+// The branch over two sibling entries, each given with its index in the store
+fn join_entries<D: TreeDigest>(
+    (left, left_entry): (usize, &Entry),
+    (right, right_entry): (usize, &Entry),
+) -> Result<Entry, SumOverflow> {
+    // We only deal with balanced trees
+    assert!(left_entry.height() == right_entry.height());
+    // Mixing encodings would make the root unverifiable
+    assert!(left_entry.encoding() == right_entry.encoding());
+    let encoding = left_entry.encoding();
+    // Own height is one level above
+    let height = left_entry.height() + 1;
+    let sum = left_entry
+        .amount()
+        .checked_add(right_entry.amount())
+        .ok_or(SumOverflow)?;
+    let commitment = branch_digest::<D>(encoding, height, sum, &left_entry.digest(), &right_entry.digest());
+
+    Ok(Entry::Branch {
+        height,
+        sum,
+        left,
+        right,
+        commitment,
+        encoding,
+    })
+}
+
+impl<D> Node<D> {
+    pub fn height(&self) -> usize {
+        self.root().height()
+    }
+
+    pub fn encoding(&self) -> Encoding {
+        self.root().encoding()
+    }
+
+    fn root(&self) -> &Entry {
+        self.entries.last().expect("a tree has at least one leaf")
+    }
+}
+
+impl<D: TreeDigest> Node<D> {
+    #[deprecated(note = "panics on bad input, use `try_new_branch`")]
+    pub fn new_branch(left: Node<D>, right: Node<D>) -> Self {
+        Self::try_new_branch(left, right).expect("cannot join subtrees")
+    }
+
+    pub fn try_new_branch(left: Node<D>, right: Node<D>) -> Result<Self, BuildError> {
+        if left.height() != right.height() {
+            return Err(BuildError::HeightMismatch {
+                left: left.height(),
+                right: right.height(),
+            });
+        }
+        if left.encoding() != right.encoding() {
+            return Err(BuildError::EncodingMismatch);
+        }
+        // Right's entries follow left's, then the new root
+        let mut entries = left.entries;
+        let offset = entries.len();
+        entries.extend(right.entries.into_iter().map(|entry| entry.shifted(offset)));
+        let branch = branch_entry::<D>(&entries, offset - 1, entries.len() - 1)?;
+        entries.push(branch);
+        Ok(Self {
+            entries,
+            hasher: PhantomData,
+        })
+    }
+
+    pub fn new_leaf(value: u64) -> Self {
+        Self::new_leaf_with_encoding(value, Encoding::default())
+    }
+
+    pub fn new_leaf_with_encoding(value: u64, encoding: Encoding) -> Self {
+        Self::new_blinded_leaf(value, None, encoding)
+    }
+
+    pub fn new_blinded_leaf(value: u64, blinding: Option<Blinding>, encoding: Encoding) -> Self {
         Self {
-            sum: node.amount(),
-            hash: node.digest(),
+            entries: vec![leaf_entry::<D>(value, blinding, encoding)],
+            hasher: PhantomData,
         }
     }
+
+    pub fn try_new(values: Vec<u64>) -> Result<Self, BuildError> {
+        Self::try_new_with_encoding(values, Encoding::default())
+    }
+
+    pub fn try_new_with_encoding(values: Vec<u64>, encoding: Encoding) -> Result<Self, BuildError> {
+        Self::try_from_iter_with_encoding(values, encoding)
+    }
+
+    // Folds values into the tree as they arrive, so they never have to be collected
+    // first, e.g. when streaming balances from a database cursor
+    pub fn try_from_iter(values: impl IntoIterator<Item = u64>) -> Result<Self, BuildError> {
+        Self::try_from_iter_with_encoding(values, Encoding::default())
+    }
+
+    pub fn try_from_iter_with_encoding(
+        values: impl IntoIterator<Item = u64>,
+        encoding: Encoding,
+    ) -> Result<Self, BuildError> {
+        Self::try_from_leaves(values.into_iter().map(|value| leaf_entry::<D>(value, None, encoding)))
+    }
+
+    pub fn try_new_blinded(values: Vec<(u64, Blinding)>) -> Result<Self, BuildError> {
+        let leaves = values
+            .into_iter()
+            .map(|(value, blinding)| leaf_entry::<D>(value, Some(blinding), Encoding::default()));
+        Self::try_from_leaves(leaves)
+    }
+
+    fn try_from_leaves(leaves: impl IntoIterator<Item = Entry>) -> Result<Self, BuildError> {
+        let leaves = leaves.into_iter();
+        let mut entries = Vec::with_capacity(2 * leaves.size_hint().0);
+        // Indices of the complete subtrees still waiting for a sibling
+        let mut roots: Vec<usize> = Vec::new();
+
+        for leaf in leaves {
+            entries.push(leaf);
+            let mut node = entries.len() - 1;
+            // bubble up new leaf
+            while roots
+                .last()
+                .is_some_and(|&root| entries[root].height() == entries[node].height())
+            {
+                let sibling = roots.pop().unwrap();
+                let branch = branch_entry::<D>(&entries, sibling, node)?;
+                entries.push(branch);
+                node = entries.len() - 1;
+            }
+            roots.push(node);
+        }
+
+        // We only deal with 2^n values
+        if roots.len() != 1 {
+            let len = roots.iter().map(|&root| 1usize << entries[root].height()).sum();
+            return Err(BuildError::NotPowerOfTwo { len });
+        }
+        // Return tree
+        Ok(Self {
+            entries,
+            hasher: PhantomData,
+        })
+    }
+
+    #[deprecated(note = "panics on bad input, use `try_new_padded`")]
+    pub fn new_padded(values: Vec<u64>) -> Self {
+        Self::try_new_padded(values).expect("sum of leaf values overflows u64")
+    }
+
+    pub fn try_new_padded(mut values: Vec<u64>) -> Result<Self, BuildError> {
+        // Fill up with zero leaves to the next power of two, which adds
+        // nothing to the sum and keeps every real position provable
+        let len = values.len().max(1).next_power_of_two();
+        values.resize(len, 0);
+        Self::try_new(values)
+    }
+
+    pub fn update(&mut self, position: usize, new_value: u64) -> Result<(), SumOverflow> {
+        assert!(position >> self.height() == 0, "position {} out of range", position);
+        // Every sum on the path is bounded by the root's, so checking it up front
+        // means the tree is never left half-updated
+        let old_value = self.leaf(position).amount();
+        (self.amount() - old_value).checked_add(new_value).ok_or(SumOverflow)?;
+        self.update_path(position, |leaf| {
+            if let Entry::Leaf {
+                value,
+                commitment,
+                encoding,
+                blinding,
+            } = leaf
+            {
+                *value = new_value;
+                *commitment = leaf_digest::<D>(*encoding, new_value, blinding.as_ref());
+            }
+        });
+        Ok(())
+    }
+
+    // Zeroes the leaf under a tombstone hash, which no balance can produce, so the
+    // removed account can no longer prove inclusion and earlier proofs stop verifying
+    pub fn remove(&mut self, position: usize) {
+        assert!(position >> self.height() == 0, "position {} out of range", position);
+        self.update_path(position, |leaf| {
+            if let Entry::Leaf {
+                value,
+                commitment,
+                blinding,
+                ..
+            } = leaf
+            {
+                *value = 0;
+                *commitment = tombstone_digest::<D>();
+                *blinding = None;
+            }
+        });
+    }
+
+    pub fn is_removed(&self, position: usize) -> bool {
+        self.leaf(position).digest() == tombstone_digest::<D>()
+    }
+
+    fn leaf(&self, position: usize) -> &Entry {
+        self.descendant(0, position)
+    }
+
+    // The node at `index` among those of height `level`, counting from the left
+    fn descendant(&self, level: usize, index: usize) -> &Entry {
+        let mut current = self.root();
+        while let Entry::Branch { height, left, right, .. } = current {
+            if *height == level {
+                break;
+            }
+            let child = if (index & (1usize << (height - 1 - level))) == 0 { left } else { right };
+            current = &self.entries[*child];
+        }
+        current
+    }
+
+    fn update_path(&mut self, position: usize, set_leaf: impl FnOnce(&mut Entry)) {
+        self.update_entry(self.entries.len() - 1, position, set_leaf);
+    }
+
+    fn update_entry(&mut self, index: usize, position: usize, set_leaf: impl FnOnce(&mut Entry)) {
+        match self.entries[index] {
+            Entry::Branch {
+                height, left, right, ..
+            } => {
+                let child = if (position & (1usize << (height - 1))) == 0 { left } else { right };
+                self.update_entry(child, position, set_leaf);
+                // The caller has already checked the new root sum
+                self.entries[index] =
+                    branch_entry::<D>(&self.entries, left, right).expect("sum checked before the update");
+            }
+            Entry::Leaf { .. } => set_leaf(&mut self.entries[index]),
+        }
+    }
+}
+
+impl<D> From<&Node<D>> for Commitment<D> {
+    fn from(node: &Node<D>) -> Commitment<D> {
+        node.root().into()
+    }
+}
+
+impl<D> From<&Entry> for Commitment<D> {
+    fn from(entry: &Entry) -> Commitment<D> {
+        Self::new(entry.amount(), entry.digest())
+    }
+}
+
+impl<D> SumCommitment for Node<D> {
+    fn amount(&self) -> u64 {
+        self.root().amount()
+    }
+
+    fn digest(&self) -> [u8; 32] {
+        self.root().digest()
+    }
 }
 
-impl SumCommitment for Node {
+impl SumCommitment for Entry {
     fn amount(&self) -> u64 {
         match self {
-            Node::Branch { sum, .. } => *sum,
-            Node::Leaf { value, .. } => *value,
+            Entry::Branch { sum, .. } => *sum,
+            Entry::Leaf { value, .. } => *value,
         }
     }
 
     fn digest(&self) -> [u8; 32] {
         match self {
-            Node::Branch { commitment, .. } => *commitment,
-            Node::Leaf { commitment, .. } => *commitment,
+            Entry::Branch { commitment, .. } => *commitment,
+            Entry::Leaf { commitment, .. } => *commitment,
         }
     }
 }
 
-#[derive(Clone, PartialEq, Eq, Debug)]
-struct Proof {
-    pub node: Commitment,
-    pub siblings: Vec<Commitment>,
+// Implemented by hand for the same reason as `Commitment`
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize), serde(bound = ""))]
+pub struct Proof<D = Sha256> {
+    pub node: Commitment<D>,
+    pub siblings: Vec<Commitment<D>>,
     pub index: usize,
+    pub encoding: Encoding,
+    pub blinding: Option<Blinding>,
+}
+
+impl<D> Clone for Proof<D> {
+    fn clone(&self) -> Self {
+        Proof {
+            node: self.node,
+            siblings: self.siblings.clone(),
+            index: self.index,
+            encoding: self.encoding,
+            blinding: self.blinding.clone(),
+        }
+    }
 }
 
-impl ExclusiveAllotmentProof<Commitment> for Proof {
+impl<D> PartialEq for Proof<D> {
+    fn eq(&self, other: &Self) -> bool {
+        self.node == other.node
+            && self.siblings == other.siblings
+            && self.index == other.index
+            && self.encoding == other.encoding
+            && self.blinding == other.blinding
+    }
+}
+
+impl<D> Eq for Proof<D> {}
+
+impl<D> fmt::Debug for Proof<D> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Proof")
+            .field("node", &self.node)
+            .field("siblings", &self.siblings)
+            .field("index", &self.index)
+            .field("encoding", &self.encoding)
+            .field("blinding", &self.blinding)
+            .finish()
+    }
+}
+
+impl<D: TreeDigest> ExclusiveAllotmentProof<Commitment<D>> for Proof<D> {
     fn position(&self) -> usize {
         self.index
     }
-    fn sibling(&self, height: u8) -> Option<Commitment> {
+    fn sibling(&self, height: u8) -> Option<Commitment<D>> {
         self.siblings.get(height as usize).copied()
     }
 
-    fn verify(&self, root_commitment: &Commitment) -> bool {
-        let mut commitment = self.node;
-        let mut height = 0usize;
-        let mut key = self.index;
-        for sibling_commitment in &self.siblings {
-            let (left, right) = if (key & 1) == 0 {
-                (&commitment, sibling_commitment)
-            } else {
-                (sibling_commitment, &commitment)
-            };
-            let sum = commitment.amount() + sibling_commitment.amount();
-            height += 1;
-            key >>= 1;
-
-            let serialized = [
-                height.to_be_bytes().as_slice(),
-                sum.to_be_bytes().as_slice(),
-                left.digest().as_slice(),
-                right.digest().as_slice(),
-            ]
-            .concat();
+    fn verify(&self, root_commitment: &Commitment<D>) -> bool {
+        self.verify_detailed(root_commitment).is_ok()
+    }
+}
 
-            let hash = hash_bytes(&serialized);
+impl<D: TreeDigest> Proof<D> {
+    // Like `verify`, but says where the proof diverges
+    pub fn verify_detailed(&self, root_commitment: &Commitment<D>) -> Result<(), VerifyError> {
+        // Recomputing the leaf keeps a branch from being passed off as a leaf
+        self.verify_path(root_commitment, |node| {
+            bool::from(node.hash.ct_eq(&leaf_digest::<D>(self.encoding, node.sum, self.blinding.as_ref())))
+        })
+    }
 
-            commitment = Commitment { sum, hash }
+    // Leaf kinds other than plain balances bring their own leaf check
+    fn verify_path(
+        &self,
+        root_commitment: &Commitment<D>,
+        leaf_matches: impl FnOnce(&Commitment<D>) -> bool,
+    ) -> Result<(), VerifyError> {
+        // Two indices that agree below the depth would otherwise share one proof
+        if self.siblings.len() < usize::BITS as usize && self.index >> self.siblings.len() != 0 {
+            return Err(VerifyError::LengthMismatch);
+        }
+        if !leaf_matches(&self.node) {
+            return Err(VerifyError::HashMismatch { height: 0 });
         }
+        check_path(self.encoding, self.node, 0, self.index, &self.siblings, root_commitment)
+    }
+}
 
-        &commitment == root_commitment
+// Hashes `node`, at `height` and `key` among the nodes of that height, up through
+// `siblings` and compares the result with the root
+fn check_path<D: TreeDigest>(
+    encoding: Encoding,
+    node: Commitment<D>,
+    mut height: usize,
+    mut key: usize,
+    siblings: &[Commitment<D>],
+    root_commitment: &Commitment<D>,
+) -> Result<(), VerifyError> {
+    let mut commitment = node;
+    for sibling_commitment in siblings {
+        let (left, right) = if (key & 1) == 0 {
+            (&commitment, sibling_commitment)
+        } else {
+            (sibling_commitment, &commitment)
+        };
+        // A wrapped sum could be made to match any root, so reject it outright
+        height += 1;
+        let Some(sum) = commitment.amount().checked_add(sibling_commitment.amount()) else {
+            return Err(VerifyError::SumOverflow { height });
+        };
+        key >>= 1;
+
+        let hash = branch_digest::<D>(encoding, height, sum, &left.digest(), &right.digest());
+
+        commitment = Commitment::new(sum, hash)
     }
+
+    if commitment.sum != root_commitment.sum {
+        return Err(VerifyError::SumMismatch { height });
+    }
+    // An early exit would tell a forger how many leading bytes of the root they matched
+    if !bool::from(commitment.hash.ct_eq(&root_commitment.hash)) {
+        return Err(VerifyError::HashMismatch { height });
+    }
+    Ok(())
 }
 
-impl MerkleTree<Commitment, Proof> for Node {
+impl<D: TreeDigest> MerkleTree<Commitment<D>, Proof<D>> for Node<D> {
     fn new(values: Vec<u64>) -> Self {
-        let mut roots: Vec<(usize, Node)> = Vec::new();
-
-        for val in values {
-            let mut node = Node::new_leaf(val);
-            let mut height = 0usize;
-            // bubble up new leaf
-            while roots
-                .last()
-                .is_some_and(|(range_height, _)| &height == range_height)
-            {
-                let (_, sibling) = roots.pop().unwrap();
-                node = Node::new_branch(sibling, node);
-                height += 1;
-            }
-            roots.push((height, node));
-        }
+        Node::try_new(values).expect("cannot build tree")
+    }
 
-        // We only deal with 2^n values
-        assert!(roots.len() == 1);
-        // Return tree
-        roots.pop().unwrap().1
+    fn try_new(values: Vec<u64>) -> Result<Self, BuildError> {
+        Node::try_new(values)
     }
 
-    fn commit(&self) -> Commitment {
+    fn commit(&self) -> Commitment<D> {
         self.into()
     }
 
-    fn prove(&self, position: usize) -> Proof {
-        let mut siblings = Vec::new();
-
-        let mut current = self;
-        let node = loop {
-            match current {
-                Node::Branch { left, right, .. } => {
-                    let mask = 1usize << (current.height() - 1);
-                    if (position & mask) == 0 {
-                        // descend left, taking right sibling
-                        siblings.push(Commitment::from(right.as_ref()));
-                        current = left.as_ref()
-                    } else {
-                        // descend right, taking left sibling
-                        siblings.push(Commitment::from(left.as_ref()));
-                        current = right.as_ref()
-                    }
+    fn prove(&self, position: usize) -> Proof<D> {
+        prove_in(&self.entries, self.entries.len() - 1, position)
+    }
+}
+
+// Proof for `position` in the tree whose root is `entries[root]`
+fn prove_in<D>(entries: &[Entry], root: usize, position: usize) -> Proof<D> {
+    let mut siblings = Vec::new();
+
+    let mut current = &entries[root];
+    let (node, blinding) = loop {
+        match current {
+            Entry::Branch {
+                height, left, right, ..
+            } => {
+                let (left, right) = (&entries[*left], &entries[*right]);
+                let mask = 1usize << (height - 1);
+                if (position & mask) == 0 {
+                    // descend left, taking right sibling
+                    siblings.push(Commitment::from(right));
+                    current = left
+                } else {
+                    // descend right, taking left sibling
+                    siblings.push(Commitment::from(left));
+                    current = right
                 }
-                Node::Leaf { .. } => break Commitment::from(current),
             }
-        };
+            Entry::Leaf { blinding, .. } => break (Commitment::from(current), blinding.clone()),
+        }
+    };
 
-        siblings.reverse();
+    siblings.reverse();
 
-        Proof {
-            node,
-            siblings,
-            index: position,
-        }
+    Proof {
+        node,
+        siblings,
+        index: position,
+        encoding: entries[root].encoding(),
+        blinding,
+    }
+}
+
+impl<D: TreeDigest> FromIterator<u64> for Node<D> {
+    fn from_iter<I: IntoIterator<Item = u64>>(values: I) -> Self {
+        Node::try_from_iter(values).expect("cannot build tree")
     }
 }
 
 #[cfg(test)]
 pub mod tests {
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+    use sha3::Sha3_256;
+
     use super::*;
 
     #[test]
     fn test_happy() {
         let values = vec![1, 2, 3, 4, 5, 6u64, 7, 8];
-        let tree_root = Node::new(values);
+        let tree_root: Node = Node::new(values);
         let root_commitment = tree_root.commit();
         for i in 0..8 {
             let proof = tree_root.prove(i);
             assert!(proof.verify(&root_commitment), "Failed Iteration {}", i);
         }
     }
+
+    #[test]
+    fn test_padded() {
+        for len in 1..=9usize {
+            let values: Vec<u64> = (1..=len as u64).collect();
+            let tree_root: Node = Node::try_new_padded(values.clone()).unwrap();
+            let root_commitment = tree_root.commit();
+            assert_eq!(root_commitment.amount(), values.iter().sum::<u64>());
+            for i in 0..len {
+                let proof = tree_root.prove(i);
+                assert!(proof.verify(&root_commitment), "Failed length {} position {}", len, i);
+            }
+        }
+    }
+
+    #[test]
+    fn test_sum_overflow() {
+        assert_eq!(Node::<Sha256>::try_new(vec![u64::MAX, 1]), Err(BuildError::SumOverflow));
+        assert_eq!(Node::<Sha256>::try_new(vec![u64::MAX - 1, 1]).unwrap().commit().amount(), u64::MAX);
+
+        // A sibling claiming a huge amount must not wrap around to a valid-looking sum
+        let tree_root: Node = Node::new(vec![1, 2]);
+        let mut proof = tree_root.prove(0);
+        proof.siblings[0].sum = u64::MAX;
+        assert!(!proof.verify(&tree_root.commit()));
+    }
+
+    #[test]
+    fn test_pluggable_digest() {
+        let values = vec![1, 2, 3, 4];
+        let sha2_root: Node<Sha256> = Node::new(values.clone());
+        let sha3_root: Node<Sha3_256> = Node::new(values);
+        assert_ne!(sha2_root.digest(), sha3_root.digest());
+
+        let root_commitment = sha3_root.commit();
+        for i in 0..4 {
+            let proof = sha3_root.prove(i);
+            assert!(proof.verify(&root_commitment), "Failed Iteration {}", i);
+        }
+
+        // The leaf hash is plain SHA3-256 over the tagged big-endian value
+        let leaf_hash = Sha3_256::new()
+            .chain_update([LEAF_TAG, V2_VERSION])
+            .chain_update(1u64.to_be_bytes())
+            .finalize();
+        let leaf = Commitment::<Sha3_256>::new(1, leaf_hash.into());
+        assert_eq!(sha3_root.prove(0).node, leaf);
+    }
+
+    #[test]
+    fn test_domain_separation() {
+        let values = vec![1, 2, 3, 4];
+        let tagged: Node = Node::new(values.clone());
+        let legacy: Node = Node::try_new_with_encoding(values, Encoding::V0).unwrap();
+        assert_ne!(tagged.digest(), legacy.digest());
+
+        // Legacy roots are still reproducible from the untagged encoding
+        let leaf = |value: u64| hash_bytes::<Sha256>(&value.to_be_bytes());
+        let branch = |height: usize, sum: u64, left: [u8; 32], right: [u8; 32]| {
+            let serialized = [
+                height.to_be_bytes().as_slice(),
+                sum.to_be_bytes().as_slice(),
+                left.as_slice(),
+                right.as_slice(),
+            ]
+            .concat();
+            hash_bytes::<Sha256>(&serialized)
+        };
+        let root = branch(2, 10, branch(1, 3, leaf(1), leaf(2)), branch(1, 7, leaf(3), leaf(4)));
+        assert_eq!(legacy.digest(), root);
+
+        for tree_root in [&tagged, &legacy] {
+            let root_commitment = tree_root.commit();
+            for i in 0..4 {
+                let proof = tree_root.prove(i);
+                assert!(proof.verify(&root_commitment), "Failed Iteration {}", i);
+            }
+        }
+
+        // A height-one branch presented as a leaf must not verify
+        let (left, right) = (tagged.descendant(1, 0), tagged.descendant(1, 1));
+        let forged = Proof {
+            node: Commitment::from(left),
+            siblings: vec![Commitment::from(right)],
+            index: 0,
+            encoding: tagged.encoding(),
+            blinding: None,
+        };
+        assert!(!forged.verify(&tagged.commit()));
+    }
+
+    #[test]
+    fn test_blinded_leaves() {
+        let mut rng = StdRng::seed_from_u64(7);
+        let values = [10, 20, 30, 40];
+        let leaves: Vec<(u64, Blinding)> = values
+            .iter()
+            .enumerate()
+            .map(|(i, value)| (*value, Blinding::random(&mut rng, Some(hash_bytes::<Sha256>(&i.to_be_bytes())))))
+            .collect();
+
+        let tree_root: Node = Node::try_new_blinded(leaves.clone()).unwrap();
+        let root_commitment = tree_root.commit();
+        assert_eq!(root_commitment.amount(), 100);
+
+        for (i, (value, blinding)) in leaves.iter().enumerate() {
+            let proof = tree_root.prove(i);
+            assert_eq!(proof.blinding.as_ref(), Some(blinding));
+            assert!(proof.verify(&root_commitment), "Failed Iteration {}", i);
+
+            // Without the salt the leaf hash cannot be recomputed from the balance alone
+            assert_ne!(proof.node.hash, leaf_digest::<Sha256>(Encoding::V2, *value, None));
+
+            let mut wrong_salt = proof.clone();
+            wrong_salt.blinding.as_mut().unwrap().salt[0] ^= 1;
+            assert!(!wrong_salt.verify(&root_commitment));
+        }
+    }
+
+    #[test]
+    fn test_update() {
+        let mut values = vec![1, 2, 3, 4, 5, 6, 7, 8u64];
+        let mut tree_root: Node = Node::new(values.clone());
+
+        for (position, new_value) in [(0, 10), (5, 0), (7, 100), (5, 42)] {
+            tree_root.update(position, new_value).unwrap();
+            values[position] = new_value;
+            let rebuilt: Node = Node::new(values.clone());
+            assert_eq!(tree_root, rebuilt);
+
+            let root_commitment = tree_root.commit();
+            for i in 0..8 {
+                assert!(tree_root.prove(i).verify(&root_commitment), "Failed position {}", i);
+            }
+        }
+
+        // An overflowing update is rejected and leaves the tree untouched
+        let before = tree_root.clone();
+        assert_eq!(tree_root.update(1, u64::MAX), Err(SumOverflow));
+        assert_eq!(tree_root, before);
+    }
+
+    #[test]
+    fn test_remove() {
+        let mut tree_root: Node = Node::new(vec![1, 2, 3, 4]);
+        let old_commitment = tree_root.commit();
+        let old_proof = tree_root.prove(1);
+
+        tree_root.remove(2);
+        assert!(tree_root.is_removed(2));
+        assert!(!tree_root.is_removed(1));
+        let root_commitment = tree_root.commit();
+        assert_eq!(root_commitment.amount(), 7);
+
+        // A tombstone is not the same as a zero balance
+        let zeroed: Node = Node::new(vec![1, 2, 0, 4]);
+        assert_ne!(root_commitment, zeroed.commit());
+
+        assert!(!tree_root.prove(2).verify(&root_commitment));
+        for i in [0, 1, 3] {
+            assert!(tree_root.prove(i).verify(&root_commitment), "Failed position {}", i);
+        }
+        assert!(old_proof.verify(&old_commitment));
+        assert!(!old_proof.verify(&root_commitment));
+    }
+
+    #[test]
+    fn test_from_iter() {
+        let values = vec![1, 2, 3, 4, 5, 6, 7, 8u64];
+        let expected: Node = Node::new(values.clone());
+
+        // Nothing is collected up front when streaming from an iterator
+        let streamed: Node = (1..=8u64).collect();
+        assert!(streamed == expected);
+        assert!(Node::<Sha256>::try_from_iter(values.iter().copied()).unwrap() == expected);
+        assert_eq!(
+            Node::<Sha256>::try_from_iter_with_encoding(1..=8, Encoding::V0).unwrap().commit(),
+            Node::<Sha256>::try_new_with_encoding(values, Encoding::V0).unwrap().commit()
+        );
+
+        assert_eq!(Node::<Sha256>::try_from_iter([u64::MAX, 1]), Err(BuildError::SumOverflow));
+    }
+
+    #[test]
+    fn test_verify_detailed() {
+        let tree_root: Node = Node::new(vec![1, 2, 3, 4, 5, 6, 7, 8]);
+        let root_commitment = tree_root.commit();
+        let proof = tree_root.prove(5);
+        assert_eq!(proof.verify_detailed(&root_commitment), Ok(()));
+
+        let mut wrong_leaf = proof.clone();
+        wrong_leaf.node.sum += 1;
+        assert_eq!(
+            wrong_leaf.verify_detailed(&root_commitment),
+            Err(VerifyError::HashMismatch { height: 0 })
+        );
+
+        let mut overflowing = proof.clone();
+        overflowing.siblings[1].sum = u64::MAX;
+        assert_eq!(
+            overflowing.verify_detailed(&root_commitment),
+            Err(VerifyError::SumOverflow { height: 2 })
+        );
+
+        let mut wrong_sum = proof.clone();
+        wrong_sum.siblings[2].sum += 1;
+        assert_eq!(
+            wrong_sum.verify_detailed(&root_commitment),
+            Err(VerifyError::SumMismatch { height: 3 })
+        );
+
+        let mut wrong_hash = proof.clone();
+        wrong_hash.siblings[0].hash[0] ^= 1;
+        assert_eq!(
+            wrong_hash.verify_detailed(&root_commitment),
+            Err(VerifyError::HashMismatch { height: 3 })
+        );
+
+        // The same leaf cannot be claimed again under an index past the tree
+        let mut aliased = proof.clone();
+        aliased.index += 8;
+        assert_eq!(aliased.verify_detailed(&root_commitment), Err(VerifyError::LengthMismatch));
+        let mut truncated = proof;
+        truncated.siblings.pop();
+        assert_eq!(truncated.verify_detailed(&root_commitment), Err(VerifyError::LengthMismatch));
+        assert!(!truncated.verify(&root_commitment));
+    }
+
+    #[test]
+    fn test_build_errors() {
+        assert_eq!(
+            Node::<Sha256>::try_new(vec![1, 2, 3]),
+            Err(BuildError::NotPowerOfTwo { len: 3 })
+        );
+        assert_eq!(Node::<Sha256>::try_new(vec![]), Err(BuildError::NotPowerOfTwo { len: 0 }));
+        assert!(<Node as MerkleTree<_, _>>::try_new(vec![1, 2, 3, 4]).is_ok());
+
+        let pair: Node = Node::new(vec![1, 2]);
+        assert_eq!(
+            Node::try_new_branch(pair.clone(), Node::new_leaf(3)),
+            Err(BuildError::HeightMismatch { left: 1, right: 0 })
+        );
+        let legacy: Node = Node::try_new_with_encoding(vec![1, 2], Encoding::V0).unwrap();
+        assert_eq!(Node::try_new_branch(pair.clone(), legacy), Err(BuildError::EncodingMismatch));
+        assert!(Node::try_new_branch(pair.clone(), pair).unwrap() == Node::new(vec![1, 2, 1, 2]));
+
+        // Implementors that only provide `new` still get a `try_new`
+        struct Wrapped(Node);
+        impl MerkleTree<Commitment, Proof> for Wrapped {
+            fn new(values: Vec<u64>) -> Self {
+                Wrapped(Node::new(values))
+            }
+            fn commit(&self) -> Commitment {
+                self.0.commit()
+            }
+            fn prove(&self, position: usize) -> Proof {
+                self.0.prove(position)
+            }
+        }
+        let wrapped = Wrapped::try_new(vec![1, 2]).unwrap();
+        assert!(wrapped.prove(1).verify(&wrapped.commit()));
+    }
+
+    #[test]
+    #[allow(deprecated)]
+    fn test_deprecated_constructors() {
+        let joined: Node = Node::new_branch(Node::new(vec![1, 2]), Node::new(vec![3, 4]));
+        assert!(joined == Node::new(vec![1, 2, 3, 4]));
+        let padded: Node = Node::new_padded(vec![1, 2, 3]);
+        assert!(padded == Node::try_new_padded(vec![1, 2, 3]).unwrap());
+    }
+
+    #[test]
+    fn test_fixed_width_heights() {
+        let values = vec![1, 2, 3, 4];
+        let canonical: Node = Node::new(values.clone());
+        assert_eq!(canonical.encoding(), Encoding::V2);
+
+        // Every height is hashed as eight bytes, whatever the pointer width
+        let leaf = |value: u64| {
+            hash_bytes::<Sha256>(&[[LEAF_TAG, V2_VERSION].as_slice(), &value.to_be_bytes()].concat())
+        };
+        let branch = |height: u64, sum: u64, left: [u8; 32], right: [u8; 32]| {
+            let serialized = [
+                [BRANCH_TAG, V2_VERSION].as_slice(),
+                height.to_be_bytes().as_slice(),
+                sum.to_be_bytes().as_slice(),
+                left.as_slice(),
+                right.as_slice(),
+            ]
+            .concat();
+            hash_bytes::<Sha256>(&serialized)
+        };
+        let root = branch(2, 10, branch(1, 3, leaf(1), leaf(2)), branch(1, 7, leaf(3), leaf(4)));
+        assert_eq!(canonical.digest(), root);
+
+        // Roots built with the native-width V1 encoding still verify when asked for
+        let legacy: Node = Node::try_new_with_encoding(values, Encoding::V1).unwrap();
+        assert_ne!(legacy.digest(), canonical.digest());
+        for i in 0..4 {
+            assert!(legacy.prove(i).verify(&legacy.commit()));
+            assert!(!legacy.prove(i).verify(&canonical.commit()));
+        }
+    }
 }
 
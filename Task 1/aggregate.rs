@@ -0,0 +1,313 @@
+// Proofs for many leaves handed out at once, e.g. to every account of an exchange.
+// Leaves in the same subtree of height `split` have the same siblings from that
+// height up, so those are stored once per subtree and only the siblings below
+// `split` once per leaf. Each leaf's own proof can be taken back out to send to
+// its owner.
+//
+// Layout: version (u8) | encoding (u8) | height (u8) | split (u8)
+//         | subtree count (varint) | per subtree: index (varint) | height - split siblings
+//         | leaf count (varint) | per leaf: position (varint) | value (varint) | split siblings | blinding
+// Siblings are sum (varint) | hash (32 bytes) and blindings are as in proofs. Leaf
+// hashes are left out since verification recomputes them anyway.
+
+use alloc::vec;
+use alloc::vec::Vec;
+
+use super::codec::{write_blinding, write_varint, DecodeError, Reader};
+use super::{
+    branch_digest, check_path, leaf_digest, Blinding, Commitment, Encoding, MerkleTree, Node, Proof, Sha256, TreeDigest,
+};
+
+const AGGREGATED_PROOF_FORMAT_VERSION: u8 = 3;
+
+pub struct AggregatedLeaf<D = Sha256> {
+    pub position: usize,
+    pub node: Commitment<D>,
+    pub blinding: Option<Blinding>,
+    // The levels below the split, lowest first
+    pub siblings: Vec<Commitment<D>>,
+}
+
+pub struct AggregatedProof<D = Sha256> {
+    pub height: usize,
+    pub split: usize,
+    pub encoding: Encoding,
+    // Siblings from `split` up to the root for each subtree holding a proven leaf,
+    // sorted by subtree index
+    pub upper: Vec<(usize, Vec<Commitment<D>>)>,
+    // Sorted by position, without duplicates
+    pub leaves: Vec<AggregatedLeaf<D>>,
+}
+
+impl<D: TreeDigest> Node<D> {
+    // `split` trades per-leaf for shared siblings: leaves spread over the whole tree
+    // want it low, leaves packed into a few subtrees want it high
+    pub fn prove_aggregated(&self, positions: &[usize], split: usize) -> AggregatedProof<D> {
+        assert!(split <= self.height(), "split {} above the root", split);
+        let mut positions = positions.to_vec();
+        positions.sort_unstable();
+        positions.dedup();
+
+        let mut upper: Vec<(usize, Vec<Commitment<D>>)> = Vec::new();
+        let mut leaves = Vec::with_capacity(positions.len());
+        for position in positions {
+            let Proof {
+                node,
+                mut siblings,
+                blinding,
+                ..
+            } = self.prove(position);
+            let shared = siblings.split_off(split);
+            let subtree = position >> split;
+            if upper.last().map(|(index, _)| *index) != Some(subtree) {
+                upper.push((subtree, shared));
+            }
+            leaves.push(AggregatedLeaf {
+                position,
+                node,
+                blinding,
+                siblings,
+            });
+        }
+
+        AggregatedProof {
+            height: self.height(),
+            split,
+            encoding: self.encoding(),
+            upper,
+            leaves,
+        }
+    }
+}
+
+impl<D: TreeDigest> AggregatedProof<D> {
+    // The proof of the `i`th leaf on its own, as `Node::prove` would give it
+    pub fn proof(&self, i: usize) -> Proof<D> {
+        let leaf = &self.leaves[i];
+        let subtree = leaf.position >> self.split;
+        let upper = self
+            .upper
+            .binary_search_by_key(&subtree, |(index, _)| *index)
+            .expect("no shared path for the leaf's subtree");
+        Proof {
+            node: leaf.node,
+            siblings: [leaf.siblings.as_slice(), self.upper[upper].1.as_slice()].concat(),
+            index: leaf.position,
+            encoding: self.encoding,
+            blinding: leaf.blinding.clone(),
+        }
+    }
+
+    // Every leaf at once, hashing each shared path only once
+    pub fn verify(&self, root_commitment: &Commitment<D>) -> bool {
+        if self.leaves.is_empty() || self.split > self.height || self.height >= usize::BITS as usize {
+            return false;
+        }
+
+        let mut subtrees: Vec<(usize, Commitment<D>)> = Vec::with_capacity(self.upper.len());
+        let mut previous = None;
+        for leaf in &self.leaves {
+            if leaf.position >> self.height != 0 || previous >= Some(leaf.position) {
+                return false;
+            }
+            previous = Some(leaf.position);
+            if leaf.siblings.len() != self.split
+                || leaf.node.hash != leaf_digest::<D>(self.encoding, leaf.node.sum, leaf.blinding.as_ref())
+            {
+                return false;
+            }
+            let Some(subtree_root) = climb(self.encoding, leaf.node, leaf.position, &leaf.siblings) else {
+                return false;
+            };
+            // Leaves of one subtree are next to each other and must agree on its root
+            let subtree = leaf.position >> self.split;
+            match subtrees.last() {
+                Some((index, known)) if *index == subtree => {
+                    if *known != subtree_root {
+                        return false;
+                    }
+                }
+                _ => subtrees.push((subtree, subtree_root)),
+            }
+        }
+
+        // One shared path per subtree, none left over
+        subtrees.len() == self.upper.len()
+            && subtrees
+                .iter()
+                .zip(&self.upper)
+                .all(|((index, node), (upper_index, siblings))| {
+                    index == upper_index
+                        && siblings.len() == self.height - self.split
+                        && check_path(self.encoding, *node, self.split, *index, siblings, root_commitment).is_ok()
+                })
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = vec![
+            AGGREGATED_PROOF_FORMAT_VERSION,
+            self.encoding.to_byte(),
+            u8::try_from(self.height).expect("tree higher than 255 levels"),
+            self.split as u8,
+        ];
+
+        write_varint(&mut bytes, self.upper.len() as u64);
+        for (index, siblings) in &self.upper {
+            write_varint(&mut bytes, *index as u64);
+            write_siblings(&mut bytes, siblings);
+        }
+        write_varint(&mut bytes, self.leaves.len() as u64);
+        for leaf in &self.leaves {
+            write_varint(&mut bytes, leaf.position as u64);
+            write_varint(&mut bytes, leaf.node.sum);
+            write_siblings(&mut bytes, &leaf.siblings);
+            write_blinding(&mut bytes, leaf.blinding.as_ref());
+        }
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, DecodeError> {
+        let mut reader = Reader::new(bytes);
+
+        let version = reader.u8()?;
+        if version != AGGREGATED_PROOF_FORMAT_VERSION {
+            return Err(DecodeError::UnsupportedVersion(version));
+        }
+        let encoding = Encoding::from_byte(reader.u8()?)?;
+        let height = reader.u8()? as usize;
+        let split = reader.u8()?;
+        if split as usize > height {
+            return Err(DecodeError::InvalidTag {
+                field: "split",
+                tag: split,
+            });
+        }
+        let split = split as usize;
+
+        // Counts are not trusted for allocation, every item has to be read first
+        let mut upper = Vec::new();
+        for _ in 0..reader.varint()? {
+            let index = read_index(&mut reader)?;
+            upper.push((index, read_siblings(&mut reader, height - split)?));
+        }
+        let mut leaves = Vec::new();
+        for _ in 0..reader.varint()? {
+            let position = read_index(&mut reader)?;
+            let sum = reader.varint()?;
+            let siblings = read_siblings(&mut reader, split)?;
+            let blinding = reader.blinding()?;
+            leaves.push(AggregatedLeaf {
+                position,
+                node: Commitment::new(sum, leaf_digest::<D>(encoding, sum, blinding.as_ref())),
+                blinding,
+                siblings,
+            });
+        }
+        reader.finish()?;
+
+        Ok(AggregatedProof {
+            height,
+            split,
+            encoding,
+            upper,
+            leaves,
+        })
+    }
+}
+
+// The root of the subtree `siblings` lead up to, unless a sum overflows
+fn climb<D: TreeDigest>(
+    encoding: Encoding,
+    node: Commitment<D>,
+    mut index: usize,
+    siblings: &[Commitment<D>],
+) -> Option<Commitment<D>> {
+    let mut commitment = node;
+    for (height, sibling) in siblings.iter().enumerate() {
+        let (left, right) = if (index & 1) == 0 {
+            (&commitment, sibling)
+        } else {
+            (sibling, &commitment)
+        };
+        let sum = left.sum.checked_add(right.sum)?;
+        let hash = branch_digest::<D>(encoding, height + 1, sum, &left.hash, &right.hash);
+        commitment = Commitment::new(sum, hash);
+        index >>= 1;
+    }
+    Some(commitment)
+}
+
+fn write_siblings<D>(bytes: &mut Vec<u8>, siblings: &[Commitment<D>]) {
+    for sibling in siblings {
+        write_varint(bytes, sibling.sum);
+        bytes.extend_from_slice(&sibling.hash);
+    }
+}
+
+fn read_siblings<D>(reader: &mut Reader<'_>, count: usize) -> Result<Vec<Commitment<D>>, DecodeError> {
+    (0..count)
+        .map(|_| {
+            let sum = reader.varint()?;
+            Ok(Commitment::new(sum, reader.array::<32>()?))
+        })
+        .collect()
+}
+
+fn read_index(reader: &mut Reader<'_>) -> Result<usize, DecodeError> {
+    let index = reader.varint()?;
+    usize::try_from(index).map_err(|_| DecodeError::IndexOutOfRange(index))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_aggregated_proof() {
+        let tree_root: Node = Node::new((1..=16).collect());
+        let root_commitment = tree_root.commit();
+        let positions = [13, 0, 1, 2, 5, 9, 12, 1];
+
+        let proof = tree_root.prove_aggregated(&positions, 2);
+        assert!(proof.verify(&root_commitment));
+        // Leaves fall in all four subtrees of height 2
+        assert_eq!(proof.upper.len(), 4);
+        assert_eq!(proof.leaves.len(), 7);
+        for (i, leaf) in proof.leaves.iter().enumerate() {
+            assert_eq!(proof.proof(i), tree_root.prove(leaf.position));
+        }
+
+        let bytes = proof.to_bytes();
+        let decoded = AggregatedProof::<Sha256>::from_bytes(&bytes).unwrap();
+        assert!(decoded.verify(&root_commitment));
+        assert_eq!(decoded.to_bytes(), bytes);
+        for i in 0..proof.leaves.len() {
+            assert_eq!(decoded.proof(i), proof.proof(i));
+        }
+        let separate: usize = positions[..7]
+            .iter()
+            .map(|&position| tree_root.prove(position).to_compact_bytes().len())
+            .sum();
+        assert!(bytes.len() < separate);
+
+        // Each leaf its own shared path, or one path shared by all
+        for split in [0, 4] {
+            assert!(tree_root.prove_aggregated(&positions, split).verify(&root_commitment));
+        }
+
+        let mut wrong_upper = tree_root.prove_aggregated(&positions, 2);
+        wrong_upper.upper[3].1[0].sum += 1;
+        assert!(!wrong_upper.verify(&root_commitment));
+        let mut extra_upper = tree_root.prove_aggregated(&[0, 5], 2);
+        extra_upper.upper.push((3, vec![root_commitment; 2]));
+        assert!(!extra_upper.verify(&root_commitment));
+        let mut wrong_leaf = tree_root.prove_aggregated(&positions, 2);
+        wrong_leaf.leaves[2].siblings[0] = wrong_leaf.leaves[0].node;
+        assert!(!wrong_leaf.verify(&root_commitment));
+
+        assert_eq!(
+            AggregatedProof::<Sha256>::from_bytes(&bytes[..bytes.len() - 1]).map(|proof| proof.leaves.len()),
+            Err(DecodeError::UnexpectedEnd)
+        );
+    }
+}
@@ -0,0 +1,218 @@
+// Async counterpart of `NodeStore` and `StoredTree`, for stores behind a network
+// or an async disk API, so a proof service on an async runtime never blocks a
+// worker thread on a read. Nodes have the same layout and indices as in
+// storage.rs. The returned futures are `Send` so they can be spawned onto a
+// multi-threaded runtime; committing needs no store access since the root is
+// kept in memory.
+
+use alloc::vec::Vec;
+use core::convert::Infallible;
+use core::future::{self, Future};
+use core::marker::PhantomData;
+
+use super::storage::StoreError;
+use super::{join_entries, leaf_entry, Commitment, Encoding, Entry, Proof, Sha256, TreeDigest};
+
+pub trait AsyncNodeStore {
+    type Error;
+
+    // Number of nodes stored, which is also the index the next one is put at
+    fn len(&self) -> impl Future<Output = Result<usize, Self::Error>> + Send;
+
+    fn get_node(&self, index: usize) -> impl Future<Output = Result<Entry, Self::Error>> + Send;
+
+    // `index` is either an existing node to overwrite or the current length
+    fn put_node(&mut self, index: usize, entry: Entry) -> impl Future<Output = Result<(), Self::Error>> + Send;
+
+    fn is_empty(&self) -> impl Future<Output = Result<bool, Self::Error>> + Send
+    where
+        Self: Sync,
+    {
+        async { Ok(self.len().await? == 0) }
+    }
+}
+
+impl AsyncNodeStore for Vec<Entry> {
+    type Error = Infallible;
+
+    fn len(&self) -> impl Future<Output = Result<usize, Infallible>> + Send {
+        future::ready(Ok(Vec::len(self)))
+    }
+
+    fn get_node(&self, index: usize) -> impl Future<Output = Result<Entry, Infallible>> + Send {
+        future::ready(Ok(self[index].clone()))
+    }
+
+    fn put_node(&mut self, index: usize, entry: Entry) -> impl Future<Output = Result<(), Infallible>> + Send {
+        if index == Vec::len(self) {
+            self.push(entry);
+        } else {
+            self[index] = entry;
+        }
+        future::ready(Ok(()))
+    }
+}
+
+pub struct AsyncStoredTree<S, D = Sha256> {
+    store: S,
+    root: Entry,
+    hasher: PhantomData<fn() -> D>,
+}
+
+impl<S: AsyncNodeStore + Sync, D: TreeDigest> AsyncStoredTree<S, D> {
+    // Streams 2^n values into an empty store
+    pub async fn build(store: S, values: impl IntoIterator<Item = u64>) -> Result<Self, StoreError<S::Error>> {
+        Self::build_with_encoding(store, values, Encoding::default()).await
+    }
+
+    pub async fn build_with_encoding(
+        mut store: S,
+        values: impl IntoIterator<Item = u64>,
+        encoding: Encoding,
+    ) -> Result<Self, StoreError<S::Error>> {
+        let mut len = store.len().await.map_err(StoreError::Backend)?;
+        assert!(len == 0, "store already holds a tree");
+        let mut roots: Vec<(usize, Entry)> = Vec::new();
+
+        for value in values {
+            let mut node = (len, leaf_entry::<D>(value, None, encoding));
+            store
+                .put_node(node.0, node.1.clone())
+                .await
+                .map_err(StoreError::Backend)?;
+            len += 1;
+            while roots.last().is_some_and(|(_, root)| root.height() == node.1.height()) {
+                let (index, sibling) = roots.pop().unwrap();
+                let branch = join_entries::<D>((index, &sibling), (node.0, &node.1))?;
+                node = (len, branch);
+                store
+                    .put_node(node.0, node.1.clone())
+                    .await
+                    .map_err(StoreError::Backend)?;
+                len += 1;
+            }
+            roots.push(node);
+        }
+
+        // We only deal with 2^n values
+        assert!(roots.len() == 1);
+        Ok(Self {
+            store,
+            root: roots.pop().unwrap().1,
+            hasher: PhantomData,
+        })
+    }
+
+    pub async fn open(store: S) -> Result<Self, StoreError<S::Error>> {
+        let len = store.len().await.map_err(StoreError::Backend)?;
+        if len == 0 {
+            return Err(StoreError::Empty);
+        }
+        let root = store.get_node(len - 1).await.map_err(StoreError::Backend)?;
+        Ok(Self {
+            store,
+            root,
+            hasher: PhantomData,
+        })
+    }
+
+    pub fn into_store(self) -> S {
+        self.store
+    }
+
+    pub fn commit(&self) -> Commitment<D> {
+        Commitment::from(&self.root)
+    }
+
+    // Same proofs as `StoredTree::prove`, awaiting one level of the path at a time
+    pub async fn prove(&self, position: usize) -> Result<Proof<D>, S::Error> {
+        assert!(
+            position >> self.root.height() == 0,
+            "position {} out of range",
+            position
+        );
+        let mut siblings = Vec::new();
+
+        let mut current = self.root.clone();
+        let (node, blinding) = loop {
+            match current {
+                Entry::Branch {
+                    height, left, right, ..
+                } => {
+                    let mask = 1usize << (height - 1);
+                    let (next, sibling) = if (position & mask) == 0 {
+                        (left, right)
+                    } else {
+                        (right, left)
+                    };
+                    siblings.push(Commitment::from(&self.store.get_node(sibling).await?));
+                    current = self.store.get_node(next).await?;
+                }
+                Entry::Leaf { ref blinding, .. } => break (Commitment::from(&current), blinding.clone()),
+            }
+        };
+
+        siblings.reverse();
+
+        Ok(Proof {
+            node,
+            siblings,
+            index: position,
+            encoding: self.root.encoding(),
+            blinding,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::pin::pin;
+    use core::task::{Context, Poll, Waker};
+
+    use super::*;
+    use crate::storage::StoredTree;
+    use crate::{ExclusiveAllotmentProof, MerkleTree, Node};
+
+    // Enough of an executor for futures that never wait on anything outside
+    fn block_on<F: Future>(future: F) -> F::Output {
+        let mut future = pin!(future);
+        let mut context = Context::from_waker(Waker::noop());
+        loop {
+            if let Poll::Ready(output) = future.as_mut().poll(&mut context) {
+                return output;
+            }
+        }
+    }
+
+    // Checks the futures stay `Send`, as a multi-threaded runtime needs
+    fn assert_send<T: Send>(value: T) -> T {
+        value
+    }
+
+    #[test]
+    fn test_async_stored_tree() {
+        let values: Vec<u64> = (1..=16).collect();
+        let node: Node = Node::new(values.clone());
+        let stored = StoredTree::<Vec<Entry>, Sha256>::build(Vec::new(), values.iter().copied()).unwrap();
+        let tree = block_on(assert_send(AsyncStoredTree::<Vec<Entry>, Sha256>::build(
+            Vec::new(),
+            values.clone(),
+        )))
+        .unwrap();
+        assert_eq!(tree.commit(), node.commit());
+        for i in 0..values.len() {
+            let proof = block_on(assert_send(tree.prove(i))).unwrap();
+            assert_eq!(proof, stored.prove(i).unwrap());
+            assert!(proof.verify(&tree.commit()));
+        }
+
+        let store = tree.into_store();
+        assert!(store == node.entries);
+        let reopened = block_on(AsyncStoredTree::<_, Sha256>::open(store)).unwrap();
+        assert_eq!(reopened.commit(), node.commit());
+        assert!(matches!(
+            block_on(AsyncStoredTree::<Vec<Entry>, Sha256>::open(Vec::new())),
+            Err(StoreError::Empty)
+        ));
+    }
+}
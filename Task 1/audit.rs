@@ -0,0 +1,294 @@
+// Full audit of a tree in one call: every leaf hash is recomputed from its value
+// and blinding and every branch from its children, so a tree loaded from a
+// snapshot or a store, whose hashes are otherwise trusted, can be checked before
+// its root is published. Each node is checked against its own children, which
+// pins every discrepancy to the node where it occurs.
+//
+// With `serde` the report serializes as is, so the outcome of a run can be archived
+// and attached to a public attestation. `TreeDigest` covers any 32-byte digest, so
+// the name comes from `NamedDigest` through `with_algorithm` rather than the caller.
+
+use alloc::string::String;
+use alloc::vec::Vec;
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+use super::{
+    branch_digest, leaf_digest, tombstone_digest, Commitment, Encoding, Entry, Node, Sha256, SumCommitment, TreeDigest,
+};
+
+// Digests with a well-known name to put in a report
+pub trait NamedDigest: TreeDigest {
+    const NAME: &'static str;
+}
+
+impl NamedDigest for Sha256 {
+    const NAME: &'static str = "sha256";
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum Discrepancy {
+    // The leaf hash is neither that of its value and blinding nor a tombstone
+    LeafHash { position: usize },
+    // The branch sum is not that of its children, or theirs overflows
+    BranchSum { height: usize, index: usize },
+    BranchHash { height: usize, index: usize },
+    // A child is not one level down or uses another encoding
+    Shape { height: usize, index: usize },
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum Check {
+    Shape,
+    LeafHashes,
+    BranchSums,
+    BranchHashes,
+}
+
+impl Check {
+    const ALL: [Check; 4] = [Check::Shape, Check::LeafHashes, Check::BranchSums, Check::BranchHashes];
+
+    fn of(discrepancy: &Discrepancy) -> Check {
+        match discrepancy {
+            Discrepancy::Shape { .. } => Check::Shape,
+            Discrepancy::LeafHash { .. } => Check::LeafHashes,
+            Discrepancy::BranchSum { .. } => Check::BranchSums,
+            Discrepancy::BranchHash { .. } => Check::BranchHashes,
+        }
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct CheckResult {
+    pub check: Check,
+    // Nodes the check ran on; nothing below a node of the wrong shape is checked
+    pub checked: usize,
+    pub failed: usize,
+}
+
+impl CheckResult {
+    pub fn passed(&self) -> bool {
+        self.failed == 0
+    }
+}
+
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize), serde(bound = ""))]
+pub struct AuditReport<D = Sha256> {
+    // `NamedDigest::NAME` of the tree's digest, e.g. "sha256"
+    pub algorithm: Option<String>,
+    pub encoding: Encoding,
+    // Leaves still holding an account, i.e. not removed. Zero leaves added by
+    // `try_new_padded` cannot be told from zero balances and are counted.
+    pub leaves: usize,
+    // Leaf slots in the tree, a power of two
+    pub width: usize,
+    // As stored, which only stands for the leaves when there are no discrepancies.
+    // Its sum is the total liabilities.
+    pub root: Commitment<D>,
+    pub checks: Vec<CheckResult>,
+    pub discrepancies: Vec<Discrepancy>,
+}
+
+impl<D> AuditReport<D> {
+    pub fn is_clean(&self) -> bool {
+        self.discrepancies.is_empty()
+    }
+
+    // The tree is consistent and commits to the published root and total
+    pub fn confirms(&self, published: &Commitment<D>) -> bool {
+        self.is_clean() && self.root == *published
+    }
+
+    pub fn total(&self) -> u64 {
+        self.root.sum
+    }
+
+    pub fn with_algorithm(mut self) -> Self
+    where
+        D: NamedDigest,
+    {
+        self.algorithm = Some(D::NAME.into());
+        self
+    }
+}
+
+impl<D: TreeDigest> Node<D> {
+    pub fn audit(&self) -> AuditReport<D> {
+        let root = self.entries.len() - 1;
+        let mut discrepancies = Vec::new();
+        let mut checked = [0; Check::ALL.len()];
+        self.audit_entry(
+            root,
+            self.height(),
+            0,
+            self.encoding(),
+            &mut checked,
+            &mut discrepancies,
+        );
+        let checks = Check::ALL
+            .into_iter()
+            .map(|check| CheckResult {
+                check,
+                checked: checked[check as usize],
+                failed: discrepancies.iter().filter(|d| Check::of(d) == check).count(),
+            })
+            .collect();
+        let tombstone = tombstone_digest::<D>();
+        let leaves = self
+            .depth_first()
+            .filter(|(height, _, sum, digest)| *height == 0 && !(*sum == 0 && *digest == tombstone))
+            .count();
+        AuditReport {
+            algorithm: None,
+            encoding: self.encoding(),
+            leaves,
+            width: 1 << self.height(),
+            root: Commitment::from(&self.entries[root]),
+            checks,
+            discrepancies,
+        }
+    }
+
+    fn audit_entry(
+        &self,
+        entry: usize,
+        height: usize,
+        index: usize,
+        encoding: Encoding,
+        checked: &mut [usize; Check::ALL.len()],
+        discrepancies: &mut Vec<Discrepancy>,
+    ) {
+        let entry = &self.entries[entry];
+        checked[Check::Shape as usize] += 1;
+        if entry.height() != height || entry.encoding() != encoding {
+            discrepancies.push(Discrepancy::Shape { height, index });
+            return;
+        }
+        match entry {
+            Entry::Leaf {
+                value,
+                commitment,
+                blinding,
+                ..
+            } => {
+                checked[Check::LeafHashes as usize] += 1;
+                let removed = *value == 0 && *commitment == tombstone_digest::<D>();
+                if !removed && *commitment != leaf_digest::<D>(encoding, *value, blinding.as_ref()) {
+                    discrepancies.push(Discrepancy::LeafHash { position: index });
+                }
+            }
+            Entry::Branch {
+                sum,
+                left,
+                right,
+                commitment,
+                ..
+            } => {
+                checked[Check::BranchSums as usize] += 1;
+                checked[Check::BranchHashes as usize] += 1;
+                let (left_entry, right_entry) = (&self.entries[*left], &self.entries[*right]);
+                if left_entry.amount().checked_add(right_entry.amount()) != Some(*sum) {
+                    discrepancies.push(Discrepancy::BranchSum { height, index });
+                }
+                let hash = branch_digest::<D>(encoding, height, *sum, &left_entry.digest(), &right_entry.digest());
+                if *commitment != hash {
+                    discrepancies.push(Discrepancy::BranchHash { height, index });
+                }
+                self.audit_entry(*left, height - 1, 2 * index, encoding, checked, discrepancies);
+                self.audit_entry(*right, height - 1, 2 * index + 1, encoding, checked, discrepancies);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::MerkleTree;
+
+    #[test]
+    fn test_audit() {
+        let mut tree_root: Node = Node::new((1..=8).collect());
+        tree_root.remove(5);
+        let published = tree_root.commit();
+        let report = tree_root.audit();
+        assert!(report.is_clean());
+        assert!(report.confirms(&published));
+        assert_eq!((report.leaves, report.width, report.total()), (7, 8, 30));
+        assert_eq!(report.encoding, Encoding::V2);
+        assert_eq!(report.algorithm, None);
+        assert_eq!(report.with_algorithm().algorithm.as_deref(), Some("sha256"));
+        let report = tree_root.audit();
+        assert!(report.checks.iter().all(CheckResult::passed));
+        assert_eq!(
+            report.checks.iter().map(|result| result.checked).collect::<Vec<_>>(),
+            [15, 8, 7, 7]
+        );
+
+        // A balance changed behind the tree's back shows at the leaf and its parent
+        let mut tampered = tree_root.clone();
+        if let Entry::Leaf { value, .. } = &mut tampered.entries[0] {
+            *value = 2;
+        }
+        assert_eq!(
+            tampered.audit().discrepancies,
+            [
+                Discrepancy::BranchSum { height: 1, index: 0 },
+                Discrepancy::LeafHash { position: 0 }
+            ]
+        );
+
+        // So does a branch hash, which is checked against its children and its parent
+        let mut tampered = tree_root.clone();
+        let index = tampered.entries.iter().position(|entry| entry.height() == 2).unwrap();
+        if let Entry::Branch { commitment, .. } = &mut tampered.entries[index] {
+            commitment[0] ^= 1;
+        }
+        let report = tampered.audit();
+        assert_eq!(
+            report.discrepancies,
+            [
+                Discrepancy::BranchHash { height: 3, index: 0 },
+                Discrepancy::BranchHash { height: 2, index: 0 }
+            ]
+        );
+        assert!(!report.confirms(&published));
+        assert_eq!(
+            report.checks[3],
+            CheckResult {
+                check: Check::BranchHashes,
+                checked: 7,
+                failed: 2
+            }
+        );
+        assert!(report.checks[..3].iter().all(CheckResult::passed));
+
+        // The archived report reads back as written
+        #[cfg(feature = "serde")]
+        {
+            let report = report.with_algorithm();
+            let archived = serde_json::to_value(&report).unwrap();
+            assert_eq!(archived["algorithm"], "sha256");
+            assert_eq!(archived["encoding"], "V2");
+            assert_eq!(archived["root"]["sum"], 30);
+            assert_eq!(archived["checks"][3]["failed"], 2);
+            let restored: AuditReport = serde_json::from_value(archived).unwrap();
+            assert_eq!(restored.algorithm.as_deref(), Some("sha256"));
+            assert_eq!(
+                (restored.checks, restored.discrepancies),
+                (report.checks, report.discrepancies)
+            );
+            assert!(restored.root == report.root);
+        }
+
+        // A consistent tree over other balances still does not confirm the published root
+        let mut updated = tree_root.clone();
+        updated.update(0, 5).unwrap();
+        assert!(updated.audit().is_clean());
+        assert!(!updated.audit().confirms(&published));
+    }
+}
@@ -0,0 +1,124 @@
+// Verifies many independent proofs against one root. Nodes on paths that already
+// reached the root, and their siblings, are remembered by position, so a later proof
+// of the same depth stops hashing once it meets one of them with the same commitment
+// and every sibling it has left is remembered too: from there on it would hash exactly
+// the path already checked, so it gets the same answer as `Proof::verify`.
+
+use alloc::vec::Vec;
+use std::collections::HashSet;
+
+use super::{branch_digest, leaf_digest, Commitment, Encoding, Proof, TreeDigest};
+
+// (encoding, depth of the proof, height, index at that height, sum, hash)
+type NodeKey = (Encoding, usize, usize, usize, u64, [u8; 32]);
+
+pub fn verify_batch<D: TreeDigest>(root_commitment: &Commitment<D>, proofs: &[Proof<D>]) -> Vec<bool> {
+    let mut verified: HashSet<NodeKey> = HashSet::new();
+    proofs
+        .iter()
+        .map(|proof| verify_shared(root_commitment, proof, &mut verified))
+        .collect()
+}
+
+fn verify_shared<D: TreeDigest>(
+    root_commitment: &Commitment<D>,
+    proof: &Proof<D>,
+    verified: &mut HashSet<NodeKey>,
+) -> bool {
+    // The same guard as `Proof::verify_path`, or a leaf could be claimed again under an index past the tree
+    if proof.siblings.len() < usize::BITS as usize && proof.index >> proof.siblings.len() != 0 {
+        return false;
+    }
+    if proof.node.hash != leaf_digest::<D>(proof.encoding, proof.node.sum, proof.blinding.as_ref()) {
+        return false;
+    }
+
+    let key = |height: usize, index: usize, commitment: &Commitment<D>| {
+        (proof.encoding, proof.siblings.len(), height, index, commitment.sum, commitment.hash)
+    };
+    let mut path = Vec::with_capacity(2 * proof.siblings.len());
+    let mut commitment = proof.node;
+    let mut index = proof.index;
+    for (height, sibling) in proof.siblings.iter().enumerate() {
+        let rest_verified = || {
+            proof.siblings[height..]
+                .iter()
+                .enumerate()
+                .all(|(above, sibling)| verified.contains(&key(height + above, (index >> above) ^ 1, sibling)))
+        };
+        if verified.contains(&key(height, index, &commitment)) && rest_verified() {
+            return true;
+        }
+        path.push(key(height, index, &commitment));
+        path.push(key(height, index ^ 1, sibling));
+
+        let (left, right) = if (index & 1) == 0 {
+            (&commitment, sibling)
+        } else {
+            (sibling, &commitment)
+        };
+        let Some(sum) = left.sum.checked_add(right.sum) else {
+            return false;
+        };
+        let hash = branch_digest::<D>(proof.encoding, height + 1, sum, &left.hash, &right.hash);
+        commitment = Commitment::new(sum, hash);
+        index >>= 1;
+    }
+
+    if &commitment != root_commitment {
+        return false;
+    }
+    verified.extend(path);
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Blinding, ExclusiveAllotmentProof, MerkleTree, Node};
+
+    #[test]
+    fn test_verify_batch() {
+        let mut rng = rand::thread_rng();
+        let leaves = (1..=16u64)
+            .map(|value| (value, Blinding::random(&mut rng, None)))
+            .collect();
+        let tree_root: Node = Node::try_new_blinded(leaves).unwrap();
+        let root_commitment = tree_root.commit();
+
+        let mut proofs: Vec<_> = (0..16).map(|i| tree_root.prove(i)).collect();
+        assert!(verify_batch(&root_commitment, &proofs).iter().all(|valid| *valid));
+
+        // A bad proof fails on its own without affecting the rest, even when it
+        // reaches a node an earlier proof has already verified
+        proofs[5].node.sum += 1;
+        proofs[9].siblings[0] = proofs[10].node;
+        proofs[12].index = 13;
+        proofs[14].index += 16;
+        let results = verify_batch(&root_commitment, &proofs);
+        for (i, proof) in proofs.iter().enumerate() {
+            assert_eq!(results[i], proof.verify(&root_commitment), "Failed proof {}", i);
+        }
+        assert_eq!(results.iter().filter(|valid| !**valid).count(), 4);
+
+        // Tampered copies of proofs that verified earlier in the same batch must get
+        // exactly the answer `verify` gives, wherever they meet the verified paths
+        let mut proofs: Vec<_> = (0..16).map(|i| tree_root.prove(i)).collect();
+        for i in [0, 3, 6, 7] {
+            let mut aliased = tree_root.prove(i);
+            aliased.index += 16;
+            let mut truncated = tree_root.prove(i);
+            truncated.siblings.pop();
+            let mut extended = tree_root.prove(i);
+            extended.siblings.push(root_commitment);
+            let mut upper = tree_root.prove(i);
+            upper.siblings[3].hash[0] ^= 1;
+            proofs.extend([aliased, truncated, extended, upper]);
+        }
+        let results = verify_batch(&root_commitment, &proofs);
+        for (i, proof) in proofs.iter().enumerate() {
+            assert_eq!(results[i], proof.verify(&root_commitment), "Failed proof {}", i);
+        }
+        assert!(results[16..].iter().all(|valid| !*valid));
+    }
+}
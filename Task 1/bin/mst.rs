@@ -0,0 +1,14 @@
+// Thin wrapper around `run_cli`, see cli.rs for the commands
+
+use std::io;
+use std::process::ExitCode;
+
+fn main() -> ExitCode {
+    match merkle_sum_tree::run_cli(std::env::args().skip(1), io::stdout().lock()) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("mst: {}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
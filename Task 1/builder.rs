@@ -0,0 +1,118 @@
+// Collects leaves and options before building, so every way a tree can be
+// rejected comes back from `build` as a `BuildError` instead of a panic.
+
+use alloc::vec::Vec;
+use core::marker::PhantomData;
+
+use super::{leaf_entry, Blinding, BuildError, Encoding, Node, Sha256, TreeDigest};
+
+pub struct TreeBuilder<D = Sha256> {
+    leaves: Vec<(u64, Option<Blinding>)>,
+    encoding: Encoding,
+    padded: bool,
+    hasher: PhantomData<fn() -> D>,
+}
+
+impl<D: TreeDigest> Default for TreeBuilder<D> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<D: TreeDigest> TreeBuilder<D> {
+    pub fn new() -> Self {
+        Self {
+            leaves: Vec::new(),
+            encoding: Encoding::default(),
+            padded: false,
+            hasher: PhantomData,
+        }
+    }
+
+    pub fn encoding(mut self, encoding: Encoding) -> Self {
+        self.encoding = encoding;
+        self
+    }
+
+    // Fill up with unblinded zero leaves to the next power of two
+    pub fn padded(mut self, padded: bool) -> Self {
+        self.padded = padded;
+        self
+    }
+
+    pub fn leaf(mut self, value: u64) -> Self {
+        self.leaves.push((value, None));
+        self
+    }
+
+    pub fn blinded_leaf(mut self, value: u64, blinding: Blinding) -> Self {
+        self.leaves.push((value, Some(blinding)));
+        self
+    }
+
+    pub fn leaves(mut self, values: impl IntoIterator<Item = u64>) -> Self {
+        self.leaves.extend(values.into_iter().map(|value| (value, None)));
+        self
+    }
+
+    pub fn build(mut self) -> Result<Node<D>, BuildError> {
+        if self.padded {
+            self.leaves
+                .resize(self.leaves.len().max(1).next_power_of_two(), (0, None));
+        }
+        let encoding = self.encoding;
+        Node::try_from_leaves(
+            self.leaves
+                .into_iter()
+                .map(|(value, blinding)| leaf_entry::<D>(value, blinding, encoding)),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::vec;
+
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    use super::*;
+    use crate::{ExclusiveAllotmentProof, MerkleTree};
+
+    #[test]
+    fn test_tree_builder() {
+        let built = TreeBuilder::<Sha256>::new().leaves(1..=3).leaf(4).build().unwrap();
+        assert!(built == Node::new(vec![1, 2, 3, 4]));
+
+        let padded = TreeBuilder::<Sha256>::new()
+            .encoding(Encoding::V0)
+            .leaves([5, 6, 7])
+            .padded(true)
+            .build()
+            .unwrap();
+        assert!(padded == Node::try_new_with_encoding(vec![5, 6, 7, 0], Encoding::V0).unwrap());
+
+        let mut rng = StdRng::seed_from_u64(7);
+        let blinding = Blinding::random(&mut rng, None);
+        let blinded = TreeBuilder::<Sha256>::new()
+            .blinded_leaf(9, blinding.clone())
+            .leaf(1)
+            .build()
+            .unwrap();
+        assert_eq!(blinded.prove(0).blinding, Some(blinding));
+        assert!(blinded.prove(0).verify(&blinded.commit()));
+
+        assert_eq!(
+            TreeBuilder::<Sha256>::new().leaves(1..=3).build(),
+            Err(BuildError::NotPowerOfTwo { len: 3 })
+        );
+        assert_eq!(
+            TreeBuilder::<Sha256>::new().build(),
+            Err(BuildError::NotPowerOfTwo { len: 0 })
+        );
+        assert_eq!(
+            TreeBuilder::<Sha256>::new().leaves([u64::MAX, 1]).padded(true).build(),
+            Err(BuildError::SumOverflow)
+        );
+    }
+}
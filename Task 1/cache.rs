@@ -0,0 +1,174 @@
+// Prover that remembers the proofs of the most recently asked for positions, for a
+// proof service where a few popular accounts are asked for over and over. A change
+// to one leaf only changes the one sibling of each cached proof where its path meets
+// the changed leaf's, so cached proofs are patched from the changed leaf's new
+// ancestors instead of being thrown away.
+
+use alloc::collections::BTreeMap;
+use alloc::vec;
+
+use super::versioned::VersionedTree;
+use super::{branch_digest, Commitment, Proof, Sha256, SumOverflow, TreeDigest};
+
+pub struct CachedProver<D = Sha256> {
+    tree: VersionedTree<D>,
+    capacity: usize,
+    // Position to the tick of its last use and its proof
+    proofs: BTreeMap<usize, (u64, Proof<D>)>,
+    // Tick of last use to position, least recently used first
+    recency: BTreeMap<u64, usize>,
+    tick: u64,
+}
+
+impl<D: TreeDigest> CachedProver<D> {
+    // A capacity of 0 caches nothing, so every proof comes straight from the tree
+    pub fn new(tree: VersionedTree<D>, capacity: usize) -> Self {
+        Self {
+            tree,
+            capacity,
+            proofs: BTreeMap::new(),
+            recency: BTreeMap::new(),
+            tick: 0,
+        }
+    }
+
+    pub fn tree(&self) -> &VersionedTree<D> {
+        &self.tree
+    }
+
+    pub fn into_tree(self) -> VersionedTree<D> {
+        self.tree
+    }
+
+    pub fn commit(&self) -> Commitment<D> {
+        self.tree.commit()
+    }
+
+    pub fn prove(&mut self, position: usize) -> Proof<D> {
+        self.tick += 1;
+        if let Some((used, proof)) = self.proofs.get_mut(&position) {
+            self.recency.remove(used);
+            self.recency.insert(self.tick, position);
+            *used = self.tick;
+            return proof.clone();
+        }
+
+        let proof = self.tree.prove(position);
+        if self.capacity == 0 {
+            return proof;
+        }
+        if self.proofs.len() == self.capacity {
+            let (_, evicted) = self.recency.pop_first().unwrap();
+            self.proofs.remove(&evicted);
+        }
+        self.recency.insert(self.tick, position);
+        self.proofs.insert(position, (self.tick, proof.clone()));
+        proof
+    }
+
+    pub fn update(&mut self, position: usize, value: u64) -> Result<usize, SumOverflow> {
+        let version = self.tree.update(position, value)?;
+        self.patch(position);
+        Ok(version)
+    }
+
+    pub fn append(&mut self, value: u64) -> Result<usize, SumOverflow> {
+        let version = self.tree.append(value)?;
+        self.patch(self.tree.len() - 1);
+        Ok(version)
+    }
+
+    // Earlier versions may differ anywhere, so nothing cached is kept
+    pub fn rollback(&mut self, version: usize) {
+        self.tree.rollback(version);
+        self.proofs.clear();
+        self.recency.clear();
+    }
+
+    // Brings every cached proof up to date after the leaf at `changed` was set
+    fn patch(&mut self, changed: usize) {
+        let proof = self.tree.prove(changed);
+
+        // The changed leaf and its ancestors, lowest first
+        let mut ancestors = vec![proof.node];
+        for (height, sibling) in proof.siblings.iter().enumerate() {
+            let node = ancestors[height];
+            let (left, right) = if (changed >> height) & 1 == 0 {
+                (node, *sibling)
+            } else {
+                (*sibling, node)
+            };
+            // The tree has already checked every sum on the path
+            let sum = left.sum + right.sum;
+            let hash = branch_digest::<D>(proof.encoding, height + 1, sum, &left.hash, &right.hash);
+            ancestors.push(Commitment::new(sum, hash));
+        }
+
+        for (position, (_, cached)) in self.proofs.iter_mut() {
+            if *position == changed {
+                *cached = proof.clone();
+                continue;
+            }
+            // Paths meet just above the highest bit the positions differ in, where
+            // the cached proof's sibling is the changed leaf's ancestor
+            let level = (usize::BITS - (position ^ changed).leading_zeros() - 1) as usize;
+            if level < cached.siblings.len() {
+                cached.siblings[level] = ancestors[level];
+            } else {
+                // An append doubled the tree, putting the new leaf in the new right half
+                cached.siblings.push(ancestors[level]);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::vec::Vec;
+
+    use super::*;
+    use crate::ExclusiveAllotmentProof;
+
+    #[test]
+    fn test_cached_prover() {
+        let tree = VersionedTree::<Sha256>::try_new(vec![1, 2, 3, 4, 5, 6]).unwrap();
+        let mut prover = CachedProver::new(tree, 3);
+        for position in [0, 5, 2, 0] {
+            assert!(prover.prove(position) == prover.tree().prove(position));
+        }
+        assert_eq!(prover.proofs.keys().copied().collect::<Vec<_>>(), vec![0, 2, 5]);
+
+        // 5 was used least recently, so it makes way for 3
+        prover.prove(3);
+        assert_eq!(prover.proofs.keys().copied().collect::<Vec<_>>(), vec![0, 2, 3]);
+
+        // Cached proofs follow updates and appends, including the one that doubles the tree
+        prover.update(2, 30).unwrap();
+        prover.append(7).unwrap();
+        prover.update(0, 10).unwrap();
+        prover.append(8).unwrap();
+        prover.append(9).unwrap();
+        let root_commitment = prover.commit();
+        for position in [0, 2, 3] {
+            let cached = prover.proofs[&position].1.clone();
+            assert!(cached == prover.tree().prove(position), "Failed position {}", position);
+            assert!(cached.verify(&root_commitment));
+        }
+        assert_eq!(prover.prove(2).node.sum, 30);
+
+        assert_eq!(prover.update(1, u64::MAX), Err(SumOverflow));
+        assert!(prover.prove(3) == prover.tree().prove(3));
+
+        prover.rollback(1);
+        assert!(prover.proofs.is_empty());
+        assert!(prover.prove(2).verify(&prover.commit()));
+
+        // Without capacity nothing is cached, and the tree comes back out as it was
+        let mut uncached = CachedProver::new(prover.into_tree(), 0);
+        uncached.update(1, 20).unwrap();
+        assert!(uncached.prove(1).verify(&uncached.commit()));
+        assert!(uncached.proofs.is_empty());
+        let tree = uncached.into_tree();
+        assert_eq!(tree.commit().sum, 1 + 20 + 30 + 4 + 5 + 6);
+    }
+}
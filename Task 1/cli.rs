@@ -0,0 +1,217 @@
+// Command-line front end, so the tree can be used without writing Rust. Reads
+// `account,balance` rows, builds a padded tree over the balances in row order and
+// prints the root commitment or proofs as JSON.
+//
+//   mst root FILE
+//   mst prove FILE ROW      ROW counts data rows from 0 and is the leaf position
+//   mst prove FILE --all    one JSON object per line
+//   mst audit FILE          audit report of the tree, see audit.rs
+//   mst vectors             test vectors for other implementations, see vectors.rs
+//
+// FILE may be `-` for stdin. A leading `account,balance` header line is skipped.
+
+use alloc::boxed::Box;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use core::fmt;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Write};
+
+use serde_json::json;
+
+use super::vectors::test_vectors;
+use super::{BuildError, MerkleTree, Node, Sha256, SumOverflow};
+
+const USAGE: &str = "usage: mst root FILE | mst prove FILE (ROW | --all) | mst audit FILE | mst vectors";
+
+#[derive(Debug)]
+pub enum CliError {
+    Usage,
+    Io(io::Error),
+    // 1-based line number of a row that is not `account,balance`
+    InvalidRow(usize),
+    SumOverflow,
+    RowOutOfRange(usize),
+}
+
+impl fmt::Display for CliError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CliError::Usage => write!(f, "{}", USAGE),
+            CliError::Io(e) => write!(f, "{}", e),
+            CliError::InvalidRow(line) => write!(f, "line {} is not `account,balance`", line),
+            CliError::SumOverflow => write!(f, "{}", SumOverflow),
+            CliError::RowOutOfRange(row) => write!(f, "row {} out of range", row),
+        }
+    }
+}
+
+impl std::error::Error for CliError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            CliError::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for CliError {
+    fn from(e: io::Error) -> Self {
+        CliError::Io(e)
+    }
+}
+
+impl From<SumOverflow> for CliError {
+    fn from(_: SumOverflow) -> Self {
+        CliError::SumOverflow
+    }
+}
+
+struct Row {
+    account: String,
+    balance: u64,
+}
+
+fn read_rows(reader: impl BufRead) -> Result<Vec<Row>, CliError> {
+    let mut rows = Vec::new();
+    for (i, line) in reader.lines().enumerate() {
+        let line = line?;
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        // Split at the last comma so account names may contain commas
+        let (account, balance) = line.rsplit_once(',').ok_or(CliError::InvalidRow(i + 1))?;
+        let (account, balance) = (account.trim(), balance.trim());
+        if i == 0 && balance.eq_ignore_ascii_case("balance") {
+            continue;
+        }
+        let balance = balance.parse().map_err(|_| CliError::InvalidRow(i + 1))?;
+        rows.push(Row {
+            account: account.to_string(),
+            balance,
+        });
+    }
+    Ok(rows)
+}
+
+fn open(path: &str) -> io::Result<Box<dyn BufRead>> {
+    if path == "-" {
+        Ok(Box::new(io::stdin().lock()))
+    } else {
+        Ok(Box::new(BufReader::new(File::open(path)?)))
+    }
+}
+
+// `args` excludes the program name
+pub fn run_cli(args: impl IntoIterator<Item = String>, mut output: impl Write) -> Result<(), CliError> {
+    let args: Vec<String> = args.into_iter().collect();
+    let (command, path, target) = match args.as_slice() {
+        [command] if command == "vectors" => {
+            writeln!(output, "{}", test_vectors())?;
+            return Ok(());
+        }
+        [command, path] => (command.as_str(), path, None),
+        [command, path, target] => (command.as_str(), path, Some(target.as_str())),
+        _ => return Err(CliError::Usage),
+    };
+
+    let rows = read_rows(open(path)?)?;
+    let values = rows.iter().map(|row| row.balance).collect();
+    let tree_root = Node::<Sha256>::try_new_padded(values).map_err(BuildError::into_overflow)?;
+
+    let selected = match (command, target) {
+        ("root", None) => {
+            let root = json!({ "leaves": rows.len(), "root": tree_root.commit() });
+            writeln!(output, "{}", root)?;
+            return Ok(());
+        }
+        ("audit", None) => {
+            writeln!(output, "{}", json!(tree_root.audit().with_algorithm()))?;
+            return Ok(());
+        }
+        ("prove", Some("--all")) => 0..rows.len(),
+        ("prove", Some(row)) => {
+            let row: usize = row.parse().map_err(|_| CliError::Usage)?;
+            if row >= rows.len() {
+                return Err(CliError::RowOutOfRange(row));
+            }
+            row..row + 1
+        }
+        _ => return Err(CliError::Usage),
+    };
+
+    for i in selected {
+        let proof = json!({
+            "row": i,
+            "account": rows[i].account,
+            "balance": rows[i].balance,
+            "proof": tree_root.prove(i),
+        });
+        writeln!(output, "{}", proof)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::format;
+    use alloc::vec;
+
+    use serde_json::Value;
+
+    use super::*;
+    use crate::{Commitment, ExclusiveAllotmentProof, Proof};
+
+    fn run(args: &[&str]) -> Result<Vec<Value>, CliError> {
+        let mut output = Vec::new();
+        run_cli(args.iter().map(|arg| arg.to_string()), &mut output)?;
+        Ok(String::from_utf8(output)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect())
+    }
+
+    #[test]
+    fn test_cli() {
+        let path = std::env::temp_dir().join(format!("mst-cli-{}.csv", std::process::id()));
+        std::fs::write(&path, "account,balance\nalice,10\n\"bob, jr\",25\ncarol,0\n").unwrap();
+        let path = path.to_str().unwrap();
+
+        let root = run(&["root", path]).unwrap();
+        assert_eq!(root[0]["leaves"], 3);
+        let root_commitment: Commitment = serde_json::from_value(root[0]["root"].clone()).unwrap();
+        let tree_root: Node = Node::new(vec![10, 25, 0, 0]);
+        assert_eq!(root_commitment, tree_root.commit());
+
+        let report = run(&["audit", path]).unwrap();
+        assert_eq!(report[0]["algorithm"], "sha256");
+        assert_eq!(report[0]["root"], root[0]["root"]);
+        assert_eq!(report[0]["discrepancies"], json!([]));
+
+        let proofs = run(&["prove", path, "--all"]).unwrap();
+        assert_eq!(proofs.len(), 3);
+        assert_eq!(proofs[1]["account"], "\"bob, jr\"");
+        for (i, entry) in proofs.iter().enumerate() {
+            assert_eq!(entry["row"], i);
+            let proof: Proof = serde_json::from_value(entry["proof"].clone()).unwrap();
+            assert_eq!(proof.position(), i);
+            assert!(proof.verify(&root_commitment));
+        }
+        assert_eq!(run(&["prove", path, "2"]).unwrap(), vec![proofs[2].clone()]);
+
+        assert!(matches!(run(&["prove", path, "3"]), Err(CliError::RowOutOfRange(3))));
+        assert_eq!(run(&["vectors"]).unwrap(), vec![test_vectors()]);
+        assert!(matches!(run(&["root"]), Err(CliError::Usage)));
+        assert!(matches!(run(&["verify", path]), Err(CliError::Usage)));
+
+        std::fs::write(path, "alice,10\nbob\n").unwrap();
+        assert!(matches!(run(&["root", path]), Err(CliError::InvalidRow(2))));
+        std::fs::write(path, "alice,10\nbob,-1\n").unwrap();
+        assert!(matches!(run(&["root", path]), Err(CliError::InvalidRow(2))));
+        std::fs::write(path, format!("alice,{}\nbob,1\n", u64::MAX)).unwrap();
+        assert!(matches!(run(&["root", path]), Err(CliError::SumOverflow)));
+        std::fs::remove_file(path).unwrap();
+    }
+}
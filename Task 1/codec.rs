@@ -0,0 +1,471 @@
+// Canonical binary encoding of commitments and proofs. All integers are
+// big-endian and variable-length parts are prefixed with their length, so
+// the layout does not depend on the platform or on serde.
+//
+// Commitment: sum (u64) | hash (32 bytes)
+// Proof:      version (u8) | encoding (u8) | index (u64) | node (commitment)
+//             | sibling count (u32) | siblings (commitments)
+//             | blinding tag (u8) | salt (32 bytes) | user id (32 bytes)
+// The blinding tag is 0 for none, 1 for a salt only and 2 for salt and user id.
+//
+// Compact proof: version (u8) | encoding (u8) | flags (u8) | depth (u8) | index (varint)
+//                | value (varint, unless bit 0 of flags is clear)
+//                | siblings: sum (varint) | hash (32 bytes) | blinding, as above
+// Varints are unsigned LEB128. The leaf hash is left out since verification
+// recomputes it anyway, and the owner of a leaf can leave out its value too.
+
+use alloc::vec::Vec;
+use core::fmt;
+
+use super::{leaf_digest, Blinding, Commitment, Encoding, Proof, TreeDigest};
+
+const PROOF_FORMAT_VERSION: u8 = 1;
+const COMPACT_PROOF_FORMAT_VERSION: u8 = 2;
+const COMPACT_HAS_VALUE: u8 = 0x01;
+const COMMITMENT_LEN: usize = 8 + 32;
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum DecodeError {
+    UnexpectedEnd,
+    TrailingBytes,
+    UnsupportedVersion(u8),
+    IndexOutOfRange(u64),
+    InconsistentNode,
+    InvalidTag { field: &'static str, tag: u8 },
+    // A compact proof without its leaf value was decoded without supplying it
+    MissingValue,
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DecodeError::UnexpectedEnd => write!(f, "input ended early"),
+            DecodeError::TrailingBytes => write!(f, "unexpected bytes after the encoded value"),
+            DecodeError::UnsupportedVersion(version) => write!(f, "unsupported format version {}", version),
+            DecodeError::IndexOutOfRange(index) => write!(f, "leaf index {} does not fit in usize", index),
+            DecodeError::InconsistentNode => write!(f, "node does not match its children"),
+            DecodeError::InvalidTag { field, tag } => write!(f, "invalid {} tag {}", field, tag),
+            DecodeError::MissingValue => write!(f, "proof leaves out the leaf value and none was given"),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for DecodeError {}
+
+pub(super) struct Reader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    pub(super) fn new(bytes: &'a [u8]) -> Self {
+        Self { bytes }
+    }
+
+    fn take(&mut self, len: usize) -> Result<&'a [u8], DecodeError> {
+        if self.bytes.len() < len {
+            return Err(DecodeError::UnexpectedEnd);
+        }
+        let (head, tail) = self.bytes.split_at(len);
+        self.bytes = tail;
+        Ok(head)
+    }
+
+    pub(super) fn u8(&mut self) -> Result<u8, DecodeError> {
+        Ok(self.take(1)?[0])
+    }
+
+    pub(super) fn u32(&mut self) -> Result<u32, DecodeError> {
+        Ok(u32::from_be_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    pub(super) fn u64(&mut self) -> Result<u64, DecodeError> {
+        Ok(u64::from_be_bytes(self.take(8)?.try_into().unwrap()))
+    }
+
+    pub(super) fn array<const N: usize>(&mut self) -> Result<[u8; N], DecodeError> {
+        Ok(self.take(N)?.try_into().unwrap())
+    }
+
+    // Rejects overlong encodings so every value has exactly one
+    pub(super) fn varint(&mut self) -> Result<u64, DecodeError> {
+        let mut value = 0u64;
+        for shift in (0..64).step_by(7) {
+            let byte = self.u8()?;
+            if (shift == 63 && byte > 1) || (shift > 0 && byte == 0) {
+                return Err(DecodeError::InvalidTag {
+                    field: "varint",
+                    tag: byte,
+                });
+            }
+            value |= u64::from(byte & 0x7f) << shift;
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+        unreachable!("the tenth byte never continues")
+    }
+
+    pub(super) fn commitment<D>(&mut self) -> Result<Commitment<D>, DecodeError> {
+        let sum = self.u64()?;
+        let hash = self.array::<32>()?;
+        Ok(Commitment::new(sum, hash))
+    }
+
+    pub(super) fn blinding(&mut self) -> Result<Option<Blinding>, DecodeError> {
+        match self.u8()? {
+            0 => Ok(None),
+            1 => Ok(Some(Blinding {
+                salt: self.array::<32>()?,
+                user_id: None,
+            })),
+            2 => Ok(Some(Blinding {
+                salt: self.array::<32>()?,
+                user_id: Some(self.array::<32>()?),
+            })),
+            tag => Err(DecodeError::InvalidTag { field: "blinding", tag }),
+        }
+    }
+
+    pub(super) fn finish(self) -> Result<(), DecodeError> {
+        if self.bytes.is_empty() {
+            Ok(())
+        } else {
+            Err(DecodeError::TrailingBytes)
+        }
+    }
+}
+
+pub(super) fn write_blinding(bytes: &mut Vec<u8>, blinding: Option<&Blinding>) {
+    match blinding {
+        None => bytes.push(0),
+        Some(Blinding { salt, user_id: None }) => {
+            bytes.push(1);
+            bytes.extend_from_slice(salt);
+        }
+        Some(Blinding {
+            salt,
+            user_id: Some(user_id),
+        }) => {
+            bytes.push(2);
+            bytes.extend_from_slice(salt);
+            bytes.extend_from_slice(user_id);
+        }
+    }
+}
+
+pub(super) fn write_varint(bytes: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        bytes.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+    bytes.push(value as u8);
+}
+
+impl Encoding {
+    pub(super) fn to_byte(self) -> u8 {
+        match self {
+            Encoding::V0 => 0,
+            Encoding::V1 => 1,
+            Encoding::V2 => 2,
+        }
+    }
+
+    pub(super) fn from_byte(tag: u8) -> Result<Self, DecodeError> {
+        match tag {
+            0 => Ok(Encoding::V0),
+            1 => Ok(Encoding::V1),
+            2 => Ok(Encoding::V2),
+            tag => Err(DecodeError::InvalidTag { field: "encoding", tag }),
+        }
+    }
+}
+
+impl<D> Commitment<D> {
+    pub fn to_bytes(self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(COMMITMENT_LEN);
+        self.write_to(&mut bytes);
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, DecodeError> {
+        let mut reader = Reader::new(bytes);
+        let commitment = reader.commitment()?;
+        reader.finish()?;
+        Ok(commitment)
+    }
+
+    fn write_to(&self, bytes: &mut Vec<u8>) {
+        bytes.extend_from_slice(&self.sum.to_be_bytes());
+        bytes.extend_from_slice(&self.hash);
+    }
+}
+
+impl<D> Proof<D> {
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(1 + 1 + 8 + COMMITMENT_LEN * (1 + self.siblings.len()) + 4 + 1 + 64);
+        bytes.push(PROOF_FORMAT_VERSION);
+        bytes.push(self.encoding.to_byte());
+        bytes.extend_from_slice(&(self.index as u64).to_be_bytes());
+        self.node.write_to(&mut bytes);
+
+        let count = u32::try_from(self.siblings.len()).expect("proof deeper than 2^32 levels");
+        bytes.extend_from_slice(&count.to_be_bytes());
+        for sibling in &self.siblings {
+            sibling.write_to(&mut bytes);
+        }
+
+        write_blinding(&mut bytes, self.blinding.as_ref());
+
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, DecodeError> {
+        let mut reader = Reader::new(bytes);
+
+        let version = reader.u8()?;
+        if version != PROOF_FORMAT_VERSION {
+            return Err(DecodeError::UnsupportedVersion(version));
+        }
+        let encoding = Encoding::from_byte(reader.u8()?)?;
+        let index = reader.u64()?;
+        let index = usize::try_from(index).map_err(|_| DecodeError::IndexOutOfRange(index))?;
+        let node = reader.commitment()?;
+
+        let count = reader.u32()? as usize;
+        // Bound the allocation by what the input can actually hold
+        if reader.bytes.len() < count.saturating_mul(COMMITMENT_LEN) {
+            return Err(DecodeError::UnexpectedEnd);
+        }
+        let siblings = (0..count).map(|_| reader.commitment()).collect::<Result<Vec<_>, _>>()?;
+
+        let blinding = reader.blinding()?;
+        reader.finish()?;
+
+        Ok(Proof {
+            node,
+            siblings,
+            index,
+            encoding,
+            blinding,
+        })
+    }
+}
+
+impl<D: TreeDigest> Proof<D> {
+    pub fn to_compact_bytes(&self) -> Vec<u8> {
+        self.write_compact(true)
+    }
+
+    // For the owner of the leaf, who already knows the balance it should hold
+    pub fn to_compact_bytes_without_value(&self) -> Vec<u8> {
+        self.write_compact(false)
+    }
+
+    fn write_compact(&self, with_value: bool) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(4 + 10 + 10 + (10 + 32) * self.siblings.len() + 1 + 64);
+        bytes.push(COMPACT_PROOF_FORMAT_VERSION);
+        bytes.push(self.encoding.to_byte());
+        bytes.push(if with_value { COMPACT_HAS_VALUE } else { 0 });
+        bytes.push(u8::try_from(self.siblings.len()).expect("proof deeper than 255 levels"));
+        write_varint(&mut bytes, self.index as u64);
+        if with_value {
+            write_varint(&mut bytes, self.node.sum);
+        }
+        for sibling in &self.siblings {
+            write_varint(&mut bytes, sibling.sum);
+            bytes.extend_from_slice(&sibling.hash);
+        }
+        write_blinding(&mut bytes, self.blinding.as_ref());
+        bytes
+    }
+
+    // `value` is needed when the proof was encoded without it, and must agree
+    // with the encoded one otherwise
+    pub fn from_compact_bytes(bytes: &[u8], value: Option<u64>) -> Result<Self, DecodeError> {
+        let mut reader = Reader::new(bytes);
+
+        let version = reader.u8()?;
+        if version != COMPACT_PROOF_FORMAT_VERSION {
+            return Err(DecodeError::UnsupportedVersion(version));
+        }
+        let encoding = Encoding::from_byte(reader.u8()?)?;
+        let flags = reader.u8()?;
+        if flags & !COMPACT_HAS_VALUE != 0 {
+            return Err(DecodeError::InvalidTag {
+                field: "flags",
+                tag: flags,
+            });
+        }
+        let depth = reader.u8()?;
+        let index = reader.varint()?;
+        let index = usize::try_from(index).map_err(|_| DecodeError::IndexOutOfRange(index))?;
+        let sum = if flags & COMPACT_HAS_VALUE != 0 {
+            let sum = reader.varint()?;
+            if value.is_some_and(|value| value != sum) {
+                return Err(DecodeError::InconsistentNode);
+            }
+            sum
+        } else {
+            value.ok_or(DecodeError::MissingValue)?
+        };
+
+        let siblings = (0..depth)
+            .map(|_| {
+                let sum = reader.varint()?;
+                Ok(Commitment::new(sum, reader.array::<32>()?))
+            })
+            .collect::<Result<Vec<_>, DecodeError>>()?;
+
+        let blinding = reader.blinding()?;
+        reader.finish()?;
+
+        Ok(Proof {
+            node: Commitment::new(sum, leaf_digest::<D>(encoding, sum, blinding.as_ref())),
+            siblings,
+            index,
+            encoding,
+            blinding,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::vec;
+
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    use super::*;
+    use crate::{ExclusiveAllotmentProof, MerkleTree, Node};
+
+    #[test]
+    fn test_round_trip() {
+        let mut rng = StdRng::seed_from_u64(7);
+        let leaves = (1..=8u64)
+            .map(|value| (value, Blinding::random(&mut rng, None)))
+            .collect();
+        let blinded: Node = Node::try_new_blinded(leaves).unwrap();
+        let plain: Node = Node::new(vec![1, 2, 3, 4]);
+
+        for tree_root in [&blinded, &plain] {
+            let root_commitment = tree_root.commit();
+            let root_bytes = root_commitment.to_bytes();
+            assert_eq!(root_bytes.len(), COMMITMENT_LEN);
+            assert_eq!(Commitment::from_bytes(&root_bytes), Ok(root_commitment));
+
+            let proof = tree_root.prove(3);
+            let decoded = Proof::from_bytes(&proof.to_bytes()).unwrap();
+            assert_eq!(decoded, proof);
+            assert!(decoded.verify(&root_commitment));
+        }
+    }
+
+    #[test]
+    fn test_malformed() {
+        let tree_root: Node = Node::new(vec![1, 2, 3, 4]);
+        let bytes = tree_root.prove(1).to_bytes();
+
+        assert_eq!(
+            Proof::<sha2::Sha256>::from_bytes(&bytes[..bytes.len() - 1]),
+            Err(DecodeError::UnexpectedEnd)
+        );
+        assert_eq!(
+            Proof::<sha2::Sha256>::from_bytes(&[bytes.as_slice(), &[0]].concat()),
+            Err(DecodeError::TrailingBytes)
+        );
+
+        let mut bad_version = bytes.clone();
+        bad_version[0] = 9;
+        assert_eq!(
+            Proof::<sha2::Sha256>::from_bytes(&bad_version),
+            Err(DecodeError::UnsupportedVersion(9))
+        );
+
+        let mut bad_encoding = bytes;
+        bad_encoding[1] = 7;
+        assert_eq!(
+            Proof::<sha2::Sha256>::from_bytes(&bad_encoding),
+            Err(DecodeError::InvalidTag {
+                field: "encoding",
+                tag: 7
+            })
+        );
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_serde_round_trip() {
+        let tree_root: Node = Node::new(vec![1, 2, 3, 4]);
+        let root_commitment = tree_root.commit();
+        let proof = tree_root.prove(2);
+
+        let json = serde_json::to_string(&proof).unwrap();
+        let decoded: Proof = serde_json::from_str(&json).unwrap();
+        assert_eq!(decoded, proof);
+        assert!(decoded.verify(&root_commitment));
+
+        let json = serde_json::to_string(&root_commitment).unwrap();
+        let decoded: Commitment = serde_json::from_str(&json).unwrap();
+        assert_eq!(decoded, root_commitment);
+    }
+
+    #[test]
+    fn test_compact_round_trip() {
+        let mut rng = StdRng::seed_from_u64(7);
+        let leaves = (0..64u64)
+            .map(|i| (i * 1_000_003, Blinding::random(&mut rng, Some([7; 32]))))
+            .collect();
+        let blinded: Node = Node::try_new_blinded(leaves).unwrap();
+        let plain: Node = Node::new((0..1024).collect());
+
+        for (tree_root, position) in [(&blinded, 37), (&plain, 1000)] {
+            let root_commitment = tree_root.commit();
+            let proof = tree_root.prove(position);
+            let value = proof.node.sum;
+
+            let compact = proof.to_compact_bytes();
+            assert!(compact.len() < proof.to_bytes().len());
+            assert_eq!(Proof::from_compact_bytes(&compact, None), Ok(proof.clone()));
+            assert_eq!(Proof::from_compact_bytes(&compact, Some(value)), Ok(proof.clone()));
+            assert_eq!(
+                Proof::<sha2::Sha256>::from_compact_bytes(&compact, Some(value + 1)),
+                Err(DecodeError::InconsistentNode)
+            );
+
+            // The owner supplies the value, and a wrong one no longer verifies
+            let without_value = proof.to_compact_bytes_without_value();
+            assert!(without_value.len() < compact.len());
+            assert_eq!(
+                Proof::<sha2::Sha256>::from_compact_bytes(&without_value, None),
+                Err(DecodeError::MissingValue)
+            );
+            let decoded = Proof::from_compact_bytes(&without_value, Some(value)).unwrap();
+            assert_eq!(decoded, proof);
+            assert!(decoded.verify(&root_commitment));
+            let wrong = Proof::from_compact_bytes(&without_value, Some(value + 1)).unwrap();
+            assert!(!wrong.verify(&root_commitment));
+        }
+    }
+
+    #[test]
+    fn test_varint() {
+        for value in [0, 1, 127, 128, 300, u32::MAX as u64, u64::MAX] {
+            let mut bytes = Vec::new();
+            write_varint(&mut bytes, value);
+            let mut reader = Reader::new(&bytes);
+            assert_eq!(reader.varint(), Ok(value));
+            assert_eq!(reader.finish(), Ok(()));
+        }
+
+        // Overlong, overflowing and truncated encodings are rejected
+        assert!(Reader::new(&[0x80, 0x00]).varint().is_err());
+        assert!(
+            Reader::new(&[0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x02])
+                .varint()
+                .is_err()
+        );
+        assert_eq!(Reader::new(&[0x80]).varint(), Err(DecodeError::UnexpectedEnd));
+    }
+}
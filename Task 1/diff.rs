@@ -0,0 +1,170 @@
+// Leaf-level differences between two trees, e.g. yesterday's and today's
+// liabilities. Subtrees with the same commitment are skipped without being
+// walked, so the cost follows the number of changes rather than the tree size.
+// Leaves are matched by position; where one tree has more leaves than the other,
+// the extra positions are reported as added or removed, padding included.
+
+use alloc::vec::Vec;
+
+use super::{Entry, Node, SumCommitment};
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum LeafChange {
+    // The leaf commitment differs, which may be its blinding alone
+    Changed { position: usize, old: u64, new: u64 },
+    Added { position: usize, value: u64 },
+    Removed { position: usize, value: u64 },
+}
+
+impl LeafChange {
+    pub fn position(&self) -> usize {
+        match self {
+            LeafChange::Changed { position, .. }
+            | LeafChange::Added { position, .. }
+            | LeafChange::Removed { position, .. } => *position,
+        }
+    }
+}
+
+impl<D> Node<D> {
+    // The changes that turn `self` into `other`, by position
+    pub fn diff(&self, other: &Self) -> Vec<LeafChange> {
+        let mut changes = Vec::new();
+        let (mut old, mut new) = (self.entries.len() - 1, other.entries.len() - 1);
+        // The smaller tree lines up with the leftmost subtree of its height in the larger one
+        while self.entries[old].height() > other.entries[new].height() {
+            let (left, right, height) = children(&self.entries[old]);
+            self.each_leaf(right, 1 << (height - 1), &mut |position, value| {
+                changes.push(LeafChange::Removed { position, value })
+            });
+            old = left;
+        }
+        while other.entries[new].height() > self.entries[old].height() {
+            let (left, right, height) = children(&other.entries[new]);
+            other.each_leaf(right, 1 << (height - 1), &mut |position, value| {
+                changes.push(LeafChange::Added { position, value })
+            });
+            new = left;
+        }
+        diff_entries(self, other, old, new, 0, &mut changes);
+        changes.sort_unstable_by_key(LeafChange::position);
+        changes
+    }
+
+    fn each_leaf(&self, index: usize, offset: usize, f: &mut impl FnMut(usize, u64)) {
+        match &self.entries[index] {
+            Entry::Leaf { value, .. } => f(offset, *value),
+            Entry::Branch {
+                height, left, right, ..
+            } => {
+                self.each_leaf(*left, offset, f);
+                self.each_leaf(*right, offset + (1 << (height - 1)), f);
+            }
+        }
+    }
+}
+
+fn children(entry: &Entry) -> (usize, usize, usize) {
+    match entry {
+        Entry::Branch {
+            left, right, height, ..
+        } => (*left, *right, *height),
+        Entry::Leaf { .. } => unreachable!(),
+    }
+}
+
+// Both entries have the same height and `offset` is the position of their first leaf
+fn diff_entries<D>(
+    old_tree: &Node<D>,
+    new_tree: &Node<D>,
+    old: usize,
+    new: usize,
+    offset: usize,
+    changes: &mut Vec<LeafChange>,
+) {
+    let (old_entry, new_entry) = (&old_tree.entries[old], &new_tree.entries[new]);
+    // Hashes cover the sums, so equal hashes mean equal subtrees
+    if old_entry.digest() == new_entry.digest() {
+        return;
+    }
+    match (old_entry, new_entry) {
+        (Entry::Leaf { value: old, .. }, Entry::Leaf { value: new, .. }) => changes.push(LeafChange::Changed {
+            position: offset,
+            old: *old,
+            new: *new,
+        }),
+        _ => {
+            let (old_left, old_right, height) = children(old_entry);
+            let (new_left, new_right, _) = children(new_entry);
+            diff_entries(old_tree, new_tree, old_left, new_left, offset, changes);
+            diff_entries(
+                old_tree,
+                new_tree,
+                old_right,
+                new_right,
+                offset + (1 << (height - 1)),
+                changes,
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::vec;
+
+    use super::*;
+    use crate::{MerkleTree, Sha256};
+
+    #[test]
+    fn test_diff() {
+        let yesterday: Node<Sha256> = Node::new((1..=8).collect());
+        assert_eq!(yesterday.diff(&yesterday), vec![]);
+
+        let today: Node<Sha256> = Node::new(vec![1, 2, 30, 4, 5, 6, 7, 0]);
+        assert_eq!(
+            yesterday.diff(&today),
+            vec![
+                LeafChange::Changed {
+                    position: 2,
+                    old: 3,
+                    new: 30
+                },
+                LeafChange::Changed {
+                    position: 7,
+                    old: 8,
+                    new: 0
+                },
+            ]
+        );
+
+        let grown: Node<Sha256> = Node::new(vec![1, 2, 3, 4, 5, 6, 7, 9, 10, 11, 0, 0, 0, 0, 0, 0]);
+        let changes = yesterday.diff(&grown);
+        assert_eq!(changes.len(), 9);
+        assert_eq!(
+            changes[0],
+            LeafChange::Changed {
+                position: 7,
+                old: 8,
+                new: 9
+            }
+        );
+        assert_eq!(changes[1], LeafChange::Added { position: 8, value: 10 });
+        assert_eq!(changes[8], LeafChange::Added { position: 15, value: 0 });
+        assert_eq!(
+            grown.diff(&yesterday)[1..],
+            (8..16)
+                .map(|position| LeafChange::Removed {
+                    position,
+                    value: [10, 11, 0, 0, 0, 0, 0, 0][position - 8]
+                })
+                .collect::<Vec<_>>()[..]
+        );
+
+        let single: Node<Sha256> = Node::new(vec![1]);
+        assert_eq!(
+            single.diff(&Node::new(vec![1, 2])),
+            vec![LeafChange::Added { position: 1, value: 2 }]
+        );
+    }
+}
@@ -0,0 +1,63 @@
+// C entry point for wallets verifying their proof natively. The proof is in the
+// canonical byte encoding from codec.rs and the root is passed as its hash and
+// total, so the caller never has to build a commitment. include/mst.h is
+// generated from this file with cbindgen.
+
+use core::slice;
+
+use super::{Commitment, ExclusiveAllotmentProof, Proof, Sha256};
+
+pub const MST_VALID: i32 = 1;
+pub const MST_INVALID: i32 = 0;
+// A null pointer or bytes that do not decode as a proof
+pub const MST_MALFORMED: i32 = -1;
+
+/// Checks a SHA-256 proof against the root with hash `root` and sum `total`.
+///
+/// Returns `MST_VALID`, `MST_INVALID` or `MST_MALFORMED`.
+///
+/// # Safety
+///
+/// `proof` must point to `len` readable bytes and `root` to 32 readable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn mst_verify(proof: *const u8, len: usize, root: *const u8, total: u64) -> i32 {
+    if proof.is_null() || root.is_null() {
+        return MST_MALFORMED;
+    }
+    let (proof, root) = unsafe { (slice::from_raw_parts(proof, len), &*(root as *const [u8; 32])) };
+    let Ok(proof) = Proof::<Sha256>::from_bytes(proof) else {
+        return MST_MALFORMED;
+    };
+    if proof.verify(&Commitment::new(total, *root)) {
+        MST_VALID
+    } else {
+        MST_INVALID
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::ptr;
+
+    use super::*;
+    use crate::{MerkleTree, Node, SumCommitment};
+
+    #[test]
+    fn test_mst_verify() {
+        let tree_root: Node = Node::new((1..=8).collect());
+        let proof = tree_root.prove(3).to_bytes();
+        let (root, total) = (tree_root.digest(), tree_root.amount());
+
+        let verify = |proof: &[u8], root: &[u8; 32], total| unsafe {
+            mst_verify(proof.as_ptr(), proof.len(), root.as_ptr(), total)
+        };
+        assert_eq!(verify(&proof, &root, total), MST_VALID);
+        assert_eq!(verify(&proof, &root, total + 1), MST_INVALID);
+        assert_eq!(verify(&proof, &[0; 32], total), MST_INVALID);
+        assert_eq!(verify(&proof[..proof.len() - 1], &root, total), MST_MALFORMED);
+        assert_eq!(
+            unsafe { mst_verify(ptr::null(), 0, root.as_ptr(), total) },
+            MST_MALFORMED
+        );
+    }
+}
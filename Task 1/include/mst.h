@@ -0,0 +1,27 @@
+#ifndef MST_H
+#define MST_H
+
+/* Generated with cbindgen from ffi.rs, do not edit by hand. */
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+#define MST_VALID 1
+
+#define MST_INVALID 0
+
+#define MST_MALFORMED -1
+
+// Checks a SHA-256 proof against the root with hash `root` and sum `total`.
+//
+// Returns `MST_VALID`, `MST_INVALID` or `MST_MALFORMED`.
+//
+// # Safety
+//
+// `proof` must point to `len` readable bytes and `root` to 32 readable bytes.
+int32_t mst_verify(const uint8_t *proof, size_t len, const uint8_t *root, uint64_t total);
+
+#endif  /* MST_H */
@@ -0,0 +1,92 @@
+// Read-only walks over a built tree, yielding plain (position, sum, digest) data so
+// callers can inspect a tree without matching on its entries.
+
+use alloc::vec;
+use alloc::vec::Vec;
+
+use super::{Entry, Node, SumCommitment};
+
+// Pre-order, left before right, yielding (height, index among that height, sum, digest)
+pub struct DepthFirst<'a> {
+    entries: &'a [Entry],
+    // Entries still to visit with their index, the next one last
+    stack: Vec<(usize, usize)>,
+    // Branches at this height are yielded but not descended into
+    lowest: usize,
+}
+
+impl Iterator for DepthFirst<'_> {
+    type Item = (usize, usize, u64, [u8; 32]);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (entry, index) = self.stack.pop()?;
+        let entry = &self.entries[entry];
+        if let Entry::Branch {
+            height, left, right, ..
+        } = entry
+        {
+            if *height > self.lowest {
+                self.stack.push((*right, 2 * index + 1));
+                self.stack.push((*left, 2 * index));
+            }
+        }
+        Some((entry.height(), index, entry.amount(), entry.digest()))
+    }
+}
+
+impl<D> Node<D> {
+    pub fn depth_first(&self) -> DepthFirst<'_> {
+        self.walk(0)
+    }
+
+    // Nodes of `height` left to right as (index, sum, digest); none if the tree is lower
+    pub fn nodes_at_height(&self, height: usize) -> impl Iterator<Item = (usize, u64, [u8; 32])> + '_ {
+        self.walk(height)
+            .filter(move |node| node.0 == height)
+            .map(|(_, index, sum, digest)| (index, sum, digest))
+    }
+
+    // Leaves left to right as (position, value, digest)
+    pub fn leaves(&self) -> impl Iterator<Item = (usize, u64, [u8; 32])> + '_ {
+        self.nodes_at_height(0)
+    }
+
+    fn walk(&self, lowest: usize) -> DepthFirst<'_> {
+        DepthFirst {
+            entries: &self.entries,
+            stack: vec![(self.entries.len() - 1, 0)],
+            lowest,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{MerkleTree, Sha256};
+
+    #[test]
+    fn test_iterators() {
+        let tree_root: Node<Sha256> = Node::new((1..=8).collect());
+        let leaves: Vec<_> = tree_root.leaves().collect();
+        assert_eq!(leaves.len(), 8);
+        for (i, (position, value, digest)) in leaves.into_iter().enumerate() {
+            assert_eq!((position, value), (i, i as u64 + 1));
+            assert_eq!(digest, tree_root.prove(i).node.hash);
+        }
+
+        let sums: Vec<u64> = tree_root.nodes_at_height(2).map(|(_, sum, _)| sum).collect();
+        assert_eq!(sums, vec![10, 26]);
+        let root: Vec<_> = tree_root.nodes_at_height(3).collect();
+        assert_eq!(root, vec![(0, 36, tree_root.digest())]);
+        assert_eq!(tree_root.nodes_at_height(4).count(), 0);
+
+        let order: Vec<(usize, usize)> = tree_root
+            .depth_first()
+            .map(|(height, index, _, _)| (height, index))
+            .collect();
+        assert_eq!(order.len(), 15);
+        assert_eq!(&order[..5], &[(3, 0), (2, 0), (1, 0), (0, 0), (0, 1)]);
+        assert_eq!(order[14], (0, 7));
+    }
+}
@@ -0,0 +1,426 @@
+// Append-only tree kept as the frontier of perfect subtrees, largest first, the
+// same way `Node::try_from_leaves` bubbles leaves up. Appending hashes at most one
+// branch per level, and the root is the frontier folded together with all-zero
+// subtrees on the right, i.e. the root `Node::new_padded` would build.
+
+use alloc::vec;
+use alloc::vec::Vec;
+use core::marker::PhantomData;
+
+use super::{
+    branch_digest, leaf_digest, Blinding, BuildError, Commitment, Encoding, MerkleTree, Node, Proof, Sha256,
+    SumCommitment, SumOverflow, TreeDigest,
+};
+
+pub struct Ledger<D = Sha256> {
+    frontier: Vec<Node<D>>,
+    len: usize,
+    sum: u64,
+    encoding: Encoding,
+    // Hash of an all-zero subtree at each height
+    zeros: Vec<[u8; 32]>,
+    hasher: PhantomData<fn() -> D>,
+}
+
+impl<D: TreeDigest> Default for Ledger<D> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<D: TreeDigest> Ledger<D> {
+    pub fn new() -> Self {
+        Self::with_encoding(Encoding::default())
+    }
+
+    pub fn with_encoding(encoding: Encoding) -> Self {
+        Self {
+            frontier: Vec::new(),
+            len: 0,
+            sum: 0,
+            encoding,
+            zeros: vec![leaf_digest::<D>(encoding, 0, None)],
+            hasher: PhantomData,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn append(&mut self, value: u64) -> Result<(), SumOverflow> {
+        self.append_leaf(Node::new_leaf_with_encoding(value, self.encoding))
+    }
+
+    pub fn append_blinded(&mut self, value: u64, blinding: Blinding) -> Result<(), SumOverflow> {
+        self.append_leaf(Node::new_blinded_leaf(value, Some(blinding), self.encoding))
+    }
+
+    fn append_leaf(&mut self, leaf: Node<D>) -> Result<(), SumOverflow> {
+        // Checking the total up front means no branch below can overflow
+        self.sum = self.sum.checked_add(leaf.amount()).ok_or(SumOverflow)?;
+        self.len += 1;
+
+        let mut node = leaf;
+        while self.frontier.last().is_some_and(|last| last.height() == node.height()) {
+            let sibling = self.frontier.pop().unwrap();
+            node = Node::try_new_branch(sibling, node).map_err(BuildError::into_overflow)?;
+        }
+        while self.zeros.len() <= node.height() {
+            let height = self.zeros.len();
+            let below = self.zeros[height - 1];
+            self.zeros
+                .push(branch_digest::<D>(self.encoding, height, 0, &below, &below));
+        }
+        self.frontier.push(node);
+        Ok(())
+    }
+
+    pub fn commit(&self) -> Commitment<D> {
+        self.fold(None).0
+    }
+
+    pub fn prove(&self, position: usize) -> Proof<D> {
+        assert!(
+            position < self.len,
+            "position {} out of range for {} leaves",
+            position,
+            self.len
+        );
+        let (_, proof) = self.fold(Some(position));
+        proof.unwrap()
+    }
+
+    // Folds the frontier into the padded root, collecting the proof for `position` on the way
+    fn fold(&self, position: Option<usize>) -> (Commitment<D>, Option<Proof<D>>) {
+        let Some((smallest, rest)) = self.frontier.split_last() else {
+            return (Commitment::new(0, self.zeros[0]), None);
+        };
+
+        // Locate the frontier subtree holding `position` and prove within it
+        let mut proof = None;
+        let mut offset = 0;
+        for (i, subtree) in self.frontier.iter().enumerate() {
+            let size = 1usize << subtree.height();
+            if let Some(position) = position.filter(|position| (offset..offset + size).contains(position)) {
+                let mut inner = subtree.prove(position - offset);
+                inner.index = position;
+                proof = Some((i, inner));
+                break;
+            }
+            offset += size;
+        }
+
+        let mut included = proof.as_ref().is_some_and(|(i, _)| *i == rest.len());
+        let mut acc = Commitment::from(smallest);
+        let mut height = smallest.height();
+        let mut rest = rest.iter().enumerate().rev().peekable();
+        while let Some(&(i, subtree)) = rest.peek() {
+            let (left, right) = if subtree.height() == height {
+                rest.next();
+                let left = Commitment::from(subtree);
+                if let Some((target, inner)) = proof.as_mut() {
+                    if *target == i {
+                        inner.siblings.push(acc);
+                        included = true;
+                    } else if included {
+                        inner.siblings.push(left);
+                    }
+                }
+                (left, acc)
+            } else {
+                let zero = Commitment::new(0, self.zeros[height]);
+                if let Some((_, inner)) = proof.as_mut().filter(|_| included) {
+                    inner.siblings.push(zero);
+                }
+                (acc, zero)
+            };
+            height += 1;
+            // Cannot overflow, the ledger total was checked on append
+            let sum = left.sum + right.sum;
+            acc = Commitment::new(
+                sum,
+                branch_digest::<D>(self.encoding, height, sum, &left.hash, &right.hash),
+            );
+        }
+
+        (acc, proof.map(|(_, inner)| inner))
+    }
+}
+
+// Shows that the ledger at `old_size` is a prefix of the ledger at `new_size`. The
+// perfect subtrees making up the old ledger reappear unchanged in the new one, so
+// they must fold into the old root and, with `siblings`, into the new root.
+pub struct ConsistencyProof<D = Sha256> {
+    pub old_size: usize,
+    pub new_size: usize,
+    // The old frontier, largest subtree first
+    pub old_subtrees: Vec<Commitment<D>>,
+    // Missing nodes for the new root, level by level and left to right
+    pub siblings: Vec<Commitment<D>>,
+    pub encoding: Encoding,
+}
+
+// Heights and first positions of the perfect subtrees covering `size` leaves, largest first
+fn frontier_shape(size: usize) -> Vec<(usize, usize)> {
+    let mut offset = 0;
+    (0..usize::BITS as usize)
+        .rev()
+        .filter(|height| size & (1usize << height) != 0)
+        .map(|height| {
+            let start = offset;
+            offset += 1usize << height;
+            (height, start)
+        })
+        .collect()
+}
+
+// Height of the padded tree over `size` leaves
+fn padded_height(size: usize) -> usize {
+    size.max(1).next_power_of_two().trailing_zeros() as usize
+}
+
+impl<D: TreeDigest> Ledger<D> {
+    pub fn consistency_proof(&self, old_size: usize) -> ConsistencyProof<D> {
+        assert!(
+            0 < old_size && old_size <= self.len,
+            "old size {} out of range for {} leaves",
+            old_size,
+            self.len
+        );
+        let old_subtrees = frontier_shape(old_size)
+            .into_iter()
+            .map(|(height, start)| self.padded_node(height, start >> height))
+            .collect::<Vec<_>>();
+
+        let mut siblings = Vec::new();
+        fold_up(self.encoding, old_size, self.len, &old_subtrees, |level, index| {
+            let sibling = self.padded_node(level, index);
+            siblings.push(sibling);
+            Some(sibling)
+        });
+
+        ConsistencyProof {
+            old_size,
+            new_size: self.len,
+            old_subtrees,
+            siblings,
+            encoding: self.encoding,
+        }
+    }
+
+    // The node of the padded tree at `index` among those of height `level`
+    fn padded_node(&self, level: usize, index: usize) -> Commitment<D> {
+        let start = index << level;
+        if start >= self.len {
+            return Commitment::new(0, self.zeros[level]);
+        }
+        let mut offset = 0;
+        for subtree in &self.frontier {
+            let size = 1usize << subtree.height();
+            if start < offset + size {
+                if subtree.height() >= level {
+                    return Commitment::from(subtree.descendant(level, (start - offset) >> level));
+                }
+                break;
+            }
+            offset += size;
+        }
+
+        // Straddles the end of the ledger, so part of it is padding
+        let left = self.padded_node(level - 1, index << 1);
+        let right = self.padded_node(level - 1, (index << 1) | 1);
+        let sum = left.sum + right.sum;
+        Commitment::new(
+            sum,
+            branch_digest::<D>(self.encoding, level, sum, &left.hash, &right.hash),
+        )
+    }
+}
+
+// Hashes the old frontier up to the root of the padded tree over `new_size`
+// leaves, asking `sibling` for every node that cannot be computed from it
+fn fold_up<D: TreeDigest>(
+    encoding: Encoding,
+    old_size: usize,
+    new_size: usize,
+    old_subtrees: &[Commitment<D>],
+    mut sibling: impl FnMut(usize, usize) -> Option<Commitment<D>>,
+) -> Option<Commitment<D>> {
+    let shape = frontier_shape(old_size);
+    if shape.len() != old_subtrees.len() {
+        return None;
+    }
+    // (height, index at that height, commitment), ordered by position
+    let mut known: Vec<(usize, usize, Commitment<D>)> = shape
+        .into_iter()
+        .zip(old_subtrees)
+        .map(|((height, start), commitment)| (height, start >> height, *commitment))
+        .collect();
+
+    for level in 0..padded_height(new_size) {
+        let mut next = Vec::with_capacity(known.len());
+        let mut i = 0;
+        while i < known.len() {
+            let (height, index, commitment) = known[i];
+            i += 1;
+            if height != level {
+                next.push((height, index, commitment));
+                continue;
+            }
+            let other = match known.get(i) {
+                Some(&(height, other_index, other)) if height == level && other_index == index ^ 1 => {
+                    i += 1;
+                    other
+                }
+                _ => sibling(level, index ^ 1)?,
+            };
+            let (left, right) = if (index & 1) == 0 {
+                (commitment, other)
+            } else {
+                (other, commitment)
+            };
+            let sum = left.sum.checked_add(right.sum)?;
+            let hash = branch_digest::<D>(encoding, level + 1, sum, &left.hash, &right.hash);
+            next.push((level + 1, index >> 1, Commitment::new(sum, hash)));
+        }
+        known = next;
+    }
+
+    match known.as_slice() {
+        [(_, 0, root)] => Some(*root),
+        _ => None,
+    }
+}
+
+impl<D: TreeDigest> ConsistencyProof<D> {
+    pub fn verify(&self, old_root: &Commitment<D>, new_root: &Commitment<D>) -> bool {
+        if self.old_size == 0 || self.old_size > self.new_size {
+            return false;
+        }
+
+        // Against the old root the missing nodes are all padding
+        let mut zeros = vec![leaf_digest::<D>(self.encoding, 0, None)];
+        for height in 1..padded_height(self.old_size) {
+            let below = zeros[height - 1];
+            zeros.push(branch_digest::<D>(self.encoding, height, 0, &below, &below));
+        }
+        let padding = |level: usize, _| Some(Commitment::new(0, zeros[level]));
+        if fold_up(self.encoding, self.old_size, self.old_size, &self.old_subtrees, padding).as_ref() != Some(old_root)
+        {
+            return false;
+        }
+
+        let mut siblings = self.siblings.iter();
+        let folded = fold_up(
+            self.encoding,
+            self.old_size,
+            self.new_size,
+            &self.old_subtrees,
+            |_, _| siblings.next().copied(),
+        );
+        siblings.next().is_none() && folded.as_ref() == Some(new_root)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ExclusiveAllotmentProof;
+
+    #[test]
+    fn test_append_matches_padded() {
+        let mut ledger = Ledger::<Sha256>::new();
+        assert_eq!(
+            ledger.commit(),
+            Node::<Sha256>::try_new_padded(vec![]).unwrap().commit()
+        );
+
+        let mut values = Vec::new();
+        for value in 1..=17u64 {
+            ledger.append(value).unwrap();
+            values.push(value);
+
+            let root_commitment = Node::<Sha256>::try_new_padded(values.clone()).unwrap().commit();
+            assert_eq!(ledger.commit(), root_commitment, "Failed length {}", values.len());
+            for i in 0..values.len() {
+                let proof = ledger.prove(i);
+                assert!(
+                    proof.verify(&root_commitment),
+                    "Failed length {} position {}",
+                    values.len(),
+                    i
+                );
+            }
+        }
+        assert_eq!(ledger.len(), 17);
+    }
+
+    #[test]
+    fn test_append_blinded() {
+        let leaves: Vec<(u64, Blinding)> = (1..=4u8)
+            .map(|i| (u64::from(i), Blinding { salt: [i; 32], user_id: None }))
+            .collect();
+        let mut ledger = Ledger::<Sha256>::new();
+        assert!(ledger.is_empty());
+        for (value, blinding) in leaves.clone() {
+            ledger.append_blinded(value, blinding).unwrap();
+        }
+        assert!(!ledger.is_empty());
+
+        let tree_root = Node::<Sha256>::try_new_blinded(leaves.clone()).unwrap();
+        assert_eq!(ledger.commit(), tree_root.commit());
+        for (i, (_, blinding)) in leaves.iter().enumerate() {
+            let proof = ledger.prove(i);
+            assert_eq!(proof.blinding.as_ref(), Some(blinding));
+            assert!(proof.verify(&tree_root.commit()), "Failed position {}", i);
+        }
+    }
+
+    #[test]
+    fn test_append_overflow() {
+        let mut ledger = Ledger::<Sha256>::new();
+        ledger.append(u64::MAX).unwrap();
+        assert_eq!(ledger.append(1), Err(SumOverflow));
+        assert_eq!(ledger.len(), 1);
+        assert_eq!(ledger.commit().amount(), u64::MAX);
+    }
+
+    #[test]
+    fn test_consistency_proof() {
+        let mut ledger = Ledger::<Sha256>::new();
+        let mut roots = Vec::new();
+        for value in 1..=13u64 {
+            ledger.append(value).unwrap();
+            roots.push(ledger.commit());
+        }
+
+        for old_size in 1..=13 {
+            let proof = ledger.consistency_proof(old_size);
+            let old_root = &roots[old_size - 1];
+            assert!(proof.verify(old_root, &ledger.commit()), "Failed old size {}", old_size);
+            if old_size < 13 {
+                assert!(
+                    !proof.verify(&roots[old_size], &ledger.commit()),
+                    "Failed old size {}",
+                    old_size
+                );
+            }
+        }
+
+        // Rewriting history breaks consistency with the old root
+        let mut forked = Ledger::<Sha256>::new();
+        for value in [1, 2, 3, 5, 5, 6, 7] {
+            forked.append(value).unwrap();
+        }
+        assert!(!forked.consistency_proof(5).verify(&roots[4], &forked.commit()));
+
+        let mut tampered = ledger.consistency_proof(6);
+        tampered.siblings.pop();
+        assert!(!tampered.verify(&roots[5], &ledger.commit()));
+    }
+}
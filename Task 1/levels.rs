@@ -0,0 +1,143 @@
+// Proving from per-level arrays of (sum, hash), without the node graph the tree
+// was built with. Anything that can look up the node at a level and index, such
+// as a mapped tree file or arrays exported from a `Node`, can serve proofs.
+
+use alloc::vec;
+use alloc::vec::Vec;
+use core::marker::PhantomData;
+
+use super::{Blinding, Commitment, Encoding, Entry, Node, Proof, Sha256};
+
+pub trait TreeLevels<D> {
+    // Height of the root; the leaves are at level 0
+    fn height(&self) -> usize;
+
+    fn encoding(&self) -> Encoding;
+
+    // The node at `index` among those of height `level`, counting from the left
+    fn node(&self, level: usize, index: usize) -> Commitment<D>;
+
+    // Only needed for blinded trees
+    fn blinding(&self, _position: usize) -> Option<Blinding> {
+        None
+    }
+
+    fn prove(&self, position: usize) -> Proof<D> {
+        assert!(position >> self.height() == 0, "position {} out of range", position);
+        let siblings = (0..self.height())
+            .map(|level| self.node(level, (position >> level) ^ 1))
+            .collect();
+        Proof {
+            node: self.node(0, position),
+            siblings,
+            index: position,
+            encoding: self.encoding(),
+            blinding: self.blinding(position),
+        }
+    }
+}
+
+// Every level of a tree as plain arrays, from the leaves up to the root
+pub struct LevelArrays<D = Sha256> {
+    levels: Vec<Vec<Commitment<D>>>,
+    // Empty unless the tree has blinded leaves
+    blindings: Vec<Option<Blinding>>,
+    encoding: Encoding,
+    hasher: PhantomData<fn() -> D>,
+}
+
+impl<D> Node<D> {
+    // Entries grouped by level from the leaves up, each level left to right
+    pub(super) fn level_entries(&self) -> Vec<Vec<&Entry>> {
+        let mut levels: Vec<Vec<&Entry>> = vec![vec![self.root()]];
+        while let Entry::Branch { .. } = levels.last().unwrap()[0] {
+            let below = levels
+                .last()
+                .unwrap()
+                .iter()
+                .flat_map(|entry| match entry {
+                    Entry::Branch { left, right, .. } => [&self.entries[*left], &self.entries[*right]],
+                    Entry::Leaf { .. } => unreachable!(),
+                })
+                .collect();
+            levels.push(below);
+        }
+        levels.reverse();
+        levels
+    }
+
+    pub fn to_levels(&self) -> LevelArrays<D> {
+        let levels = self.level_entries();
+        let blindings: Vec<Option<Blinding>> = levels[0]
+            .iter()
+            .map(|entry| match entry {
+                Entry::Leaf { blinding, .. } => blinding.clone(),
+                Entry::Branch { .. } => unreachable!(),
+            })
+            .collect();
+        LevelArrays {
+            levels: levels
+                .iter()
+                .map(|level| level.iter().map(|entry| Commitment::from(*entry)).collect())
+                .collect(),
+            blindings: if blindings.iter().any(Option::is_some) {
+                blindings
+            } else {
+                Vec::new()
+            },
+            encoding: self.encoding(),
+            hasher: PhantomData,
+        }
+    }
+}
+
+impl<D> TreeLevels<D> for LevelArrays<D> {
+    fn height(&self) -> usize {
+        self.levels.len() - 1
+    }
+
+    fn encoding(&self) -> Encoding {
+        self.encoding
+    }
+
+    fn node(&self, level: usize, index: usize) -> Commitment<D> {
+        self.levels[level][index]
+    }
+
+    fn blinding(&self, position: usize) -> Option<Blinding> {
+        self.blindings.get(position).cloned().flatten()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    use super::*;
+    use crate::{ExclusiveAllotmentProof, MerkleTree};
+
+    #[test]
+    fn test_prove_from_levels() {
+        let mut rng = StdRng::seed_from_u64(7);
+        let plain: Node = Node::new((1..=16).collect());
+        let leaves = (1..=8u64)
+            .map(|value| (value, Blinding::random(&mut rng, Some([3; 32]))))
+            .collect();
+        let blinded: Node = Node::try_new_blinded(leaves).unwrap();
+
+        for tree_root in [&plain, &blinded] {
+            let levels = tree_root.to_levels();
+            assert_eq!(levels.height(), tree_root.height());
+            assert_eq!(levels.node(levels.height(), 0), tree_root.commit());
+            // The tree is no longer needed to serve proofs
+            let root_commitment = tree_root.commit();
+            for i in 0..1 << levels.height() {
+                let proof = levels.prove(i);
+                assert_eq!(proof, tree_root.prove(i));
+                assert!(proof.verify(&root_commitment));
+            }
+        }
+        assert!(plain.to_levels().blindings.is_empty());
+    }
+}
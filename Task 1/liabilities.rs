@@ -0,0 +1,228 @@
+// Proof of liabilities on top of the sum tree. Every leaf commits to a user's
+// hashed identifier and a salted balance; the exchange publishes the root and
+// hands each user a bundle they can check with nothing but their own identifier.
+// Users are placed in random order and the tree is padded with blinded zero
+// leaves, so neither positions nor the padding reveal who or how many. With
+// `build_split` each balance is further spread over several randomly sized leaves,
+// so a neighbouring leaf's sibling hash reveals only a share of one balance.
+
+use alloc::vec::Vec;
+use core::fmt;
+use std::collections::HashMap;
+
+use rand::seq::SliceRandom;
+use rand::{CryptoRng, Rng, RngCore};
+
+use super::{
+    hash_bytes, Blinding, BuildError, Commitment, Encoding, ExclusiveAllotmentProof, MerkleTree, Node, Proof, Sha256,
+    SumCommitment, SumOverflow, TreeDigest,
+};
+
+pub struct Liabilities<D = Sha256> {
+    tree: Node<D>,
+    // Hashed identifier to the positions of the user's leaves
+    positions: HashMap<[u8; 32], Vec<usize>>,
+}
+
+// Everything a user needs to check their balance is included in the published root,
+// one proof per leaf the balance was split into
+pub struct UserBundle<D = Sha256> {
+    pub proofs: Vec<Proof<D>>,
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum BundleError {
+    // The leaf is bound to a different identifier, or to none
+    WrongUser,
+    InvalidProof,
+    // The same leaf appears twice, or the shares add up past u64::MAX
+    InvalidShares,
+}
+
+impl fmt::Display for BundleError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BundleError::WrongUser => write!(f, "bundle belongs to a different user"),
+            BundleError::InvalidProof => write!(f, "bundle does not lead to the published root"),
+            BundleError::InvalidShares => write!(f, "bundle shares are repeated or overflow"),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for BundleError {}
+
+impl<D: TreeDigest> Liabilities<D> {
+    // Balances of the same user are added together
+    pub fn build<R: RngCore + CryptoRng>(
+        accounts: impl IntoIterator<Item = (Vec<u8>, u64)>,
+        rng: &mut R,
+    ) -> Result<Self, SumOverflow> {
+        Self::build_split(accounts, 1, rng)
+    }
+
+    // Like `build`, but every balance is split into `shares` leaves
+    pub fn build_split<R: RngCore + CryptoRng>(
+        accounts: impl IntoIterator<Item = (Vec<u8>, u64)>,
+        shares: usize,
+        rng: &mut R,
+    ) -> Result<Self, SumOverflow> {
+        assert!(shares > 0, "every balance needs at least one leaf");
+        let mut balances: HashMap<[u8; 32], u64> = HashMap::new();
+        for (id, balance) in accounts {
+            #[cfg(feature = "zeroize")]
+            let id = zeroize::Zeroizing::new(id);
+            let total = balances.entry(hash_bytes::<D>(&id)).or_insert(0);
+            *total = total.checked_add(balance).ok_or(SumOverflow)?;
+        }
+
+        let mut leaves: Vec<(u64, Blinding)> = Vec::with_capacity(balances.len() * shares);
+        for (user_id, balance) in balances {
+            for share in split_balance(balance, shares, rng) {
+                leaves.push((share, Blinding::random(rng, Some(user_id))));
+            }
+        }
+        leaves.shuffle(rng);
+        let len = leaves.len().max(1).next_power_of_two();
+        while leaves.len() < len {
+            leaves.push((0, Blinding::random(rng, None)));
+        }
+
+        let mut positions: HashMap<[u8; 32], Vec<usize>> = HashMap::new();
+        for (position, (_, blinding)) in leaves.iter().enumerate() {
+            if let Some(user_id) = blinding.user_id {
+                positions.entry(user_id).or_default().push(position);
+            }
+        }
+        Ok(Self {
+            tree: Node::try_new_blinded(leaves).map_err(BuildError::into_overflow)?,
+            positions,
+        })
+    }
+
+    pub fn root(&self) -> Commitment<D> {
+        self.tree.commit()
+    }
+
+    pub fn bundle_for(&self, id: &[u8]) -> Option<UserBundle<D>> {
+        let positions = self.positions.get(&hash_bytes::<D>(id))?;
+        Some(self.bundle_at(positions))
+    }
+
+    pub fn bundles(&self) -> impl Iterator<Item = ([u8; 32], UserBundle<D>)> + '_ {
+        self.positions
+            .iter()
+            .map(|(user_id, positions)| (*user_id, self.bundle_at(positions)))
+    }
+
+    fn bundle_at(&self, positions: &[usize]) -> UserBundle<D> {
+        UserBundle {
+            proofs: positions.iter().map(|position| self.tree.prove(*position)).collect(),
+        }
+    }
+}
+
+// Random cut points over [0, balance] give `shares` parts adding up to the balance
+fn split_balance<R: RngCore>(balance: u64, shares: usize, rng: &mut R) -> Vec<u64> {
+    let mut cuts: Vec<u64> = (1..shares).map(|_| rng.gen_range(0..=balance)).collect();
+    cuts.push(0);
+    cuts.push(balance);
+    cuts.sort_unstable();
+    cuts.windows(2).map(|pair| pair[1] - pair[0]).collect()
+}
+
+impl<D: TreeDigest> UserBundle<D> {
+    // Returns the balance the exchange committed to for this user, over all shares
+    pub fn verify(&self, id: &[u8], root: &Commitment<D>) -> Result<u64, BundleError> {
+        if self.proofs.is_empty() {
+            return Err(BundleError::InvalidProof);
+        }
+        let user_id = hash_bytes::<D>(id);
+        let mut positions = Vec::with_capacity(self.proofs.len());
+        let mut balance = 0u64;
+        for proof in &self.proofs {
+            let bound_to_user = proof
+                .blinding
+                .as_ref()
+                .is_some_and(|blinding| blinding.user_id == Some(user_id));
+            if !bound_to_user {
+                return Err(BundleError::WrongUser);
+            }
+            // V0 is not domain separated, so it is not accepted here
+            if proof.encoding == Encoding::V0 || !proof.verify(root) {
+                return Err(BundleError::InvalidProof);
+            }
+            positions.push(proof.position());
+            balance = balance
+                .checked_add(proof.node.amount())
+                .ok_or(BundleError::InvalidShares)?;
+        }
+
+        // Counting a share twice would overstate what the root commits to
+        positions.sort_unstable();
+        if positions.windows(2).any(|pair| pair[0] == pair[1]) {
+            return Err(BundleError::InvalidShares);
+        }
+        Ok(balance)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::vec;
+
+    use super::*;
+
+    #[test]
+    fn test_liabilities_end_to_end() {
+        let mut rng = rand::thread_rng();
+        let accounts = vec![
+            (b"alice".to_vec(), 100),
+            (b"bob".to_vec(), 250),
+            (b"carol".to_vec(), 0),
+            (b"alice".to_vec(), 20),
+            (b"dave".to_vec(), 5),
+        ];
+        let liabilities = Liabilities::<Sha256>::build(accounts, &mut rng).unwrap();
+        let root = liabilities.root();
+        assert_eq!(root.amount(), 375);
+
+        for (id, balance) in [(&b"alice"[..], 120), (b"bob", 250), (b"carol", 0), (b"dave", 5)] {
+            let bundle = liabilities.bundle_for(id).unwrap();
+            assert_eq!(bundle.verify(id, &root), Ok(balance));
+        }
+        assert!(liabilities.bundle_for(b"erin").is_none());
+        assert_eq!(liabilities.bundles().count(), 4);
+
+        // Someone else's bundle, or a tampered one, does not verify
+        let bundle = liabilities.bundle_for(b"bob").unwrap();
+        assert_eq!(bundle.verify(b"alice", &root), Err(BundleError::WrongUser));
+        let mut tampered = liabilities.bundle_for(b"bob").unwrap();
+        tampered.proofs[0].node.sum = 1;
+        assert_eq!(tampered.verify(b"bob", &root), Err(BundleError::InvalidProof));
+    }
+
+    #[test]
+    fn test_split_shares() {
+        let mut rng = rand::thread_rng();
+        let accounts = vec![
+            (b"alice".to_vec(), 1_000),
+            (b"bob".to_vec(), 0),
+            (b"carol".to_vec(), u64::MAX - 1_000),
+        ];
+        let liabilities = Liabilities::<Sha256>::build_split(accounts, 4, &mut rng).unwrap();
+        let root = liabilities.root();
+        assert_eq!(root.amount(), u64::MAX);
+
+        for (id, balance) in [(&b"alice"[..], 1_000), (b"bob", 0), (b"carol", u64::MAX - 1_000)] {
+            let bundle = liabilities.bundle_for(id).unwrap();
+            assert_eq!(bundle.proofs.len(), 4);
+            assert_eq!(bundle.verify(id, &root), Ok(balance));
+        }
+
+        // Repeating a share to inflate the balance is caught
+        let mut repeated = liabilities.bundle_for(b"alice").unwrap();
+        repeated.proofs[1] = repeated.proofs[0].clone();
+        assert_eq!(repeated.verify(b"alice", &root), Err(BundleError::InvalidShares));
+    }
+}
@@ -0,0 +1,214 @@
+// Balances keyed by account identifier on top of the positional tree. Keys get
+// positions in insertion order; the tree is padded and doubles when it runs out
+// of free slots, so most inserts only rehash one path.
+
+use alloc::vec::Vec;
+use core::fmt;
+use core::hash::Hash;
+use std::collections::hash_map::{Entry, HashMap};
+
+use super::{BuildError, Commitment, MerkleTree, Node, Proof, Sha256, SumCommitment, SumOverflow, TreeDigest};
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum MapError {
+    // Rows are counted from 0 in the order the entries were given
+    DuplicateKey { first: usize, repeated: usize },
+    SumOverflow,
+}
+
+impl fmt::Display for MapError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MapError::DuplicateKey { first, repeated } => {
+                write!(f, "entry {} repeats the key of entry {}", repeated, first)
+            }
+            MapError::SumOverflow => write!(f, "{}", SumOverflow),
+        }
+    }
+}
+
+impl std::error::Error for MapError {}
+
+impl From<SumOverflow> for MapError {
+    fn from(_: SumOverflow) -> Self {
+        MapError::SumOverflow
+    }
+}
+
+pub struct MerkleSumMap<K, D = Sha256> {
+    positions: HashMap<K, usize>,
+    len: usize,
+    tree: Node<D>,
+}
+
+impl<K: Eq + Hash, D: TreeDigest> Default for MerkleSumMap<K, D> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<K: Eq + Hash, D: TreeDigest> MerkleSumMap<K, D> {
+    pub fn new() -> Self {
+        Self {
+            positions: HashMap::new(),
+            len: 0,
+            tree: Node::try_new_padded(Vec::new()).expect("an empty tree cannot overflow"),
+        }
+    }
+
+    pub fn try_from_entries(entries: impl IntoIterator<Item = (K, u64)>) -> Result<Self, SumOverflow> {
+        let mut positions = HashMap::new();
+        let mut values = Vec::new();
+        for (key, value) in entries {
+            match positions.get(&key) {
+                // A repeated key keeps its first position and takes the latest value
+                Some(&position) => values[position] = value,
+                None => {
+                    positions.insert(key, values.len());
+                    values.push(value);
+                }
+            }
+        }
+        Self::from_positions(positions, values)
+    }
+
+    // For account lists where a repeated identifier is a bug upstream rather than an update
+    pub fn try_from_unique_entries(entries: impl IntoIterator<Item = (K, u64)>) -> Result<Self, MapError> {
+        let mut positions = HashMap::new();
+        let mut values = Vec::new();
+        for (key, value) in entries {
+            match positions.entry(key) {
+                Entry::Occupied(first) => {
+                    return Err(MapError::DuplicateKey {
+                        first: *first.get(),
+                        repeated: values.len(),
+                    })
+                }
+                Entry::Vacant(slot) => {
+                    slot.insert(values.len());
+                    values.push(value);
+                }
+            }
+        }
+        Ok(Self::from_positions(positions, values)?)
+    }
+
+    fn from_positions(positions: HashMap<K, usize>, mut values: Vec<u64>) -> Result<Self, SumOverflow> {
+        let len = values.len();
+        values.resize(len.max(1).next_power_of_two(), 0);
+        Ok(Self {
+            positions,
+            len,
+            tree: Node::try_new(values).map_err(BuildError::into_overflow)?,
+        })
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn position(&self, key: &K) -> Option<usize> {
+        self.positions.get(key).copied()
+    }
+
+    pub fn get(&self, key: &K) -> Option<u64> {
+        self.position(key).map(|position| self.tree.leaf(position).amount())
+    }
+
+    pub fn insert(&mut self, key: K, value: u64) -> Result<Option<u64>, SumOverflow> {
+        if let Some(position) = self.position(&key) {
+            let old = self.tree.leaf(position).amount();
+            self.tree.update(position, value)?;
+            return Ok(Some(old));
+        }
+
+        let position = self.len;
+        if position >> self.tree.height() != 0 {
+            // Out of padding, so rebuild at twice the size
+            let mut values: Vec<u64> = (0..position).map(|i| self.tree.leaf(i).amount()).collect();
+            values.push(value);
+            values.resize(2 * position, 0);
+            self.tree = Node::try_new(values).map_err(BuildError::into_overflow)?;
+        } else {
+            self.tree.update(position, value)?;
+        }
+        self.positions.insert(key, position);
+        self.len += 1;
+        Ok(None)
+    }
+
+    pub fn commit(&self) -> Commitment<D> {
+        self.tree.commit()
+    }
+
+    pub fn prove_by_key(&self, key: &K) -> Option<Proof<D>> {
+        self.position(key).map(|position| self.tree.prove(position))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::vec;
+
+    use super::*;
+    use crate::ExclusiveAllotmentProof;
+
+    #[test]
+    fn test_map() {
+        let mut map: MerkleSumMap<&str> =
+            MerkleSumMap::try_from_entries([("alice", 5), ("bob", 7), ("alice", 6)]).unwrap();
+        assert_eq!(map.len(), 2);
+        assert!(!map.is_empty());
+        assert!(MerkleSumMap::<&str>::new().is_empty());
+        assert_eq!(map.get(&"alice"), Some(6));
+        assert_eq!(map.get(&"carol"), None);
+        assert!(map.prove_by_key(&"carol").is_none());
+
+        for (i, name) in ["carol", "dave", "erin", "frank"].into_iter().enumerate() {
+            assert_eq!(map.insert(name, i as u64 + 1), Ok(None));
+        }
+        assert_eq!(map.insert("bob", 70), Ok(Some(7)));
+
+        let root_commitment = map.commit();
+        assert_eq!(root_commitment.amount(), 6 + 70 + 1 + 2 + 3 + 4);
+        for name in ["alice", "bob", "carol", "dave", "erin", "frank"] {
+            let proof = map.prove_by_key(&name).unwrap();
+            assert_eq!(proof.position(), map.position(&name).unwrap());
+            assert_eq!(Some(proof.node.sum), map.get(&name));
+            assert!(proof.verify(&root_commitment), "Failed key {}", name);
+        }
+
+        // Same root as building the positional tree directly
+        assert_eq!(
+            root_commitment,
+            Node::<Sha256>::try_new_padded(vec![6, 70, 1, 2, 3, 4])
+                .unwrap()
+                .commit()
+        );
+
+        assert_eq!(map.insert("grace", u64::MAX), Err(SumOverflow));
+        assert_eq!(map.get(&"grace"), None);
+        assert_eq!(map.commit(), root_commitment);
+    }
+
+    #[test]
+    fn test_unique_keys() {
+        let map: MerkleSumMap<&str> = MerkleSumMap::try_from_unique_entries([("alice", 5), ("bob", 7)]).unwrap();
+        let proof = map.prove_by_key(&"bob").unwrap();
+        assert_eq!((proof.position(), proof.node.sum), (1, 7));
+        assert!(proof.verify(&map.commit()));
+
+        assert_eq!(
+            MerkleSumMap::<&str>::try_from_unique_entries([("alice", 5), ("bob", 7), ("carol", 1), ("bob", 2)]).err(),
+            Some(MapError::DuplicateKey { first: 1, repeated: 3 })
+        );
+        assert_eq!(
+            MerkleSumMap::<&str>::try_from_unique_entries([("alice", u64::MAX), ("bob", 1)]).err(),
+            Some(MapError::SumOverflow)
+        );
+    }
+}
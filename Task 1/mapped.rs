@@ -0,0 +1,172 @@
+// Read-only tree file laid out level by level, so it can be memory-mapped and
+// proved against without loading or re-hashing anything. Opening only checks
+// the header and the length; like snapshots, the file is trusted local state.
+//
+// Header: magic (4 bytes) | version (u8) | encoding (u8) | height (u8)
+// Then every level from the leaves up to the root, left to right, one record
+// per node: sum (u64) | hash (32 bytes)
+// Blinded trees are not supported, since proofs would need the salts as well.
+
+use alloc::vec::Vec;
+use core::marker::PhantomData;
+use std::fs::File;
+use std::io::{self, Write};
+use std::path::Path;
+
+use memmap2::Mmap;
+
+use super::codec::{DecodeError, Reader};
+use super::levels::TreeLevels;
+use super::{Commitment, Encoding, Entry, Node, Sha256, SumCommitment};
+
+const MAPPED_MAGIC: &[u8; 4] = b"MSTM";
+const MAPPED_VERSION: u8 = 1;
+const HEADER_LEN: usize = 4 + 1 + 1 + 1;
+const RECORD_LEN: usize = 8 + 32;
+
+pub struct MappedTree<B = Mmap, D = Sha256> {
+    bytes: B,
+    encoding: Encoding,
+    height: usize,
+    hasher: PhantomData<fn() -> D>,
+}
+
+impl<D> Node<D> {
+    pub fn write_mapped<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        let mut bytes = Vec::with_capacity(HEADER_LEN + RECORD_LEN * self.entries.len());
+        bytes.extend_from_slice(MAPPED_MAGIC);
+        bytes.push(MAPPED_VERSION);
+        bytes.push(self.encoding().to_byte());
+        bytes.push(self.height() as u8);
+        for level in self.level_entries() {
+            for entry in level {
+                if let Entry::Leaf { blinding: Some(_), .. } = entry {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidInput,
+                        "blinded trees cannot be written as mapped files",
+                    ));
+                }
+                bytes.extend_from_slice(&entry.amount().to_be_bytes());
+                bytes.extend_from_slice(&entry.digest());
+            }
+        }
+        writer.write_all(&bytes)
+    }
+}
+
+impl<D> MappedTree<Mmap, D> {
+    pub fn open(path: impl AsRef<Path>) -> io::Result<Self> {
+        let file = File::open(path)?;
+        // The file must not be modified while it is mapped
+        let map = unsafe { Mmap::map(&file)? };
+        Self::from_bytes(map).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+}
+
+impl<B: AsRef<[u8]>, D> MappedTree<B, D> {
+    pub fn from_bytes(bytes: B) -> Result<Self, DecodeError> {
+        let mut reader = Reader::new(bytes.as_ref());
+        if reader.array::<4>()? != *MAPPED_MAGIC {
+            return Err(DecodeError::InvalidTag {
+                field: "mapped tree magic",
+                tag: bytes.as_ref()[0],
+            });
+        }
+        let version = reader.u8()?;
+        if version != MAPPED_VERSION {
+            return Err(DecodeError::UnsupportedVersion(version));
+        }
+        let encoding = Encoding::from_byte(reader.u8()?)?;
+        let height = reader.u8()? as usize;
+        if height >= usize::BITS as usize - 1 {
+            return Err(DecodeError::InconsistentNode);
+        }
+        // A perfect tree of this height has 2^(height + 1) - 1 nodes
+        let expected = ((2usize << height) - 1)
+            .checked_mul(RECORD_LEN)
+            .ok_or(DecodeError::UnexpectedEnd)?;
+        let len = bytes.as_ref().len() - HEADER_LEN;
+        if len < expected {
+            return Err(DecodeError::UnexpectedEnd);
+        }
+        if len > expected {
+            return Err(DecodeError::TrailingBytes);
+        }
+        Ok(Self {
+            bytes,
+            encoding,
+            height,
+            hasher: PhantomData,
+        })
+    }
+
+    pub fn commit(&self) -> Commitment<D> {
+        self.node(self.height, 0)
+    }
+}
+
+impl<B: AsRef<[u8]>, D> TreeLevels<D> for MappedTree<B, D> {
+    fn height(&self) -> usize {
+        self.height
+    }
+
+    fn encoding(&self) -> Encoding {
+        self.encoding
+    }
+
+    fn node(&self, level: usize, index: usize) -> Commitment<D> {
+        let leaves = 1usize << self.height;
+        // Levels below hold leaves + leaves/2 + ... = 2 * leaves - 2 * (leaves >> level) nodes
+        let offset = HEADER_LEN + RECORD_LEN * (2 * leaves - 2 * (leaves >> level) + index);
+        let mut reader = Reader::new(&self.bytes.as_ref()[offset..offset + RECORD_LEN]);
+        // The length was checked on open, so the record is always complete
+        let sum = reader.u64().expect("record in bounds");
+        let hash = reader.array::<32>().expect("record in bounds");
+        Commitment::new(sum, hash)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::{format, vec};
+
+    use super::*;
+    use crate::{Blinding, ExclusiveAllotmentProof, MerkleTree};
+
+    #[test]
+    fn test_mapped_tree() {
+        for encoding in [Encoding::V0, Encoding::V1, Encoding::V2] {
+            let tree_root: Node = Node::try_new_with_encoding((1..=16).collect(), encoding).unwrap();
+            let mut bytes = Vec::new();
+            tree_root.write_mapped(&mut bytes).unwrap();
+            assert_eq!(bytes.len(), HEADER_LEN + RECORD_LEN * 31);
+
+            let mapped = MappedTree::<_, Sha256>::from_bytes(bytes.as_slice()).unwrap();
+            assert_eq!(mapped.commit(), tree_root.commit());
+            for i in 0..16 {
+                let proof = mapped.prove(i);
+                assert_eq!(proof, tree_root.prove(i));
+                assert!(proof.verify(&tree_root.commit()));
+            }
+
+            assert_eq!(
+                MappedTree::<_, Sha256>::from_bytes(&bytes[..bytes.len() - 1]).err(),
+                Some(DecodeError::UnexpectedEnd)
+            );
+        }
+
+        // Served straight from a mapped file
+        let tree_root: Node = Node::new(vec![5, 0, 7, 1]);
+        let path = std::env::temp_dir().join(format!("mapped-tree-{}.mst", std::process::id()));
+        tree_root.write_mapped(File::create(&path).unwrap()).unwrap();
+        let mapped = MappedTree::<Mmap, Sha256>::open(&path).unwrap();
+        assert_eq!(mapped.commit(), tree_root.commit());
+        assert!(mapped.prove(2).verify(&tree_root.commit()));
+        std::fs::remove_file(&path).unwrap();
+
+        let mut rng = rand::thread_rng();
+        let blinded: Node = Node::try_new_blinded(vec![(1, Blinding::random(&mut rng, None)); 2]).unwrap();
+        let err = blinded.write_mapped(Vec::new()).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+    }
+}
@@ -0,0 +1,221 @@
+// Merkle mountain range: the leaves so far as perfect subtrees ("peaks"), largest
+// first, without padding them out to a power of two. The root bags the peaks
+// together from the right under BAG_TAG, binding the leaf count at every step,
+// so any number of leaves can be committed and proved against.
+
+use alloc::vec::Vec;
+
+use super::{
+    hash_bytes, Blinding, BuildError, Commitment, Encoding, ExclusiveAllotmentProof, MerkleTree, Node, Proof, Sha256,
+    SumCommitment, SumOverflow, TreeDigest, BAG_TAG,
+};
+
+pub struct MountainRange<D = Sha256> {
+    peaks: Vec<Node<D>>,
+    len: usize,
+    sum: u64,
+    encoding: Encoding,
+}
+
+impl<D: TreeDigest> Default for MountainRange<D> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<D: TreeDigest> MountainRange<D> {
+    pub fn new() -> Self {
+        Self::with_encoding(Encoding::default())
+    }
+
+    pub fn with_encoding(encoding: Encoding) -> Self {
+        Self {
+            peaks: Vec::new(),
+            len: 0,
+            sum: 0,
+            encoding,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn append(&mut self, value: u64) -> Result<(), SumOverflow> {
+        self.append_leaf(Node::new_leaf_with_encoding(value, self.encoding))
+    }
+
+    pub fn append_blinded(&mut self, value: u64, blinding: Blinding) -> Result<(), SumOverflow> {
+        self.append_leaf(Node::new_blinded_leaf(value, Some(blinding), self.encoding))
+    }
+
+    fn append_leaf(&mut self, leaf: Node<D>) -> Result<(), SumOverflow> {
+        // Checking the total up front means no branch or bag below can overflow
+        self.sum = self.sum.checked_add(leaf.amount()).ok_or(SumOverflow)?;
+        self.len += 1;
+
+        let mut node = leaf;
+        while self.peaks.last().is_some_and(|last| last.height() == node.height()) {
+            let sibling = self.peaks.pop().unwrap();
+            node = Node::try_new_branch(sibling, node).map_err(BuildError::into_overflow)?;
+        }
+        self.peaks.push(node);
+        Ok(())
+    }
+
+    pub fn peaks(&self) -> Vec<Commitment<D>> {
+        self.peaks.iter().map(Commitment::from).collect()
+    }
+
+    pub fn commit(&self) -> Commitment<D> {
+        bag_peaks(self.len, &self.peaks()).expect("total checked on append")
+    }
+
+    pub fn prove(&self, position: usize) -> MmrProof<D> {
+        assert!(
+            position < self.len,
+            "position {} out of range for {} leaves",
+            position,
+            self.len
+        );
+        let (peak, _, start) = peak_of(self.len, position);
+        MmrProof {
+            len: self.len,
+            position,
+            path: self.peaks[peak].prove(position - start),
+            peaks: self.peaks(),
+        }
+    }
+}
+
+// Heights and first positions of the peaks over `len` leaves, largest first
+fn peak_shape(len: usize) -> impl Iterator<Item = (usize, usize)> {
+    let mut offset = 0;
+    (0..usize::BITS as usize)
+        .rev()
+        .filter(move |height| len & (1usize << height) != 0)
+        .map(move |height| {
+            let start = offset;
+            offset += 1usize << height;
+            (height, start)
+        })
+}
+
+// The peak holding `position`, with its height and first position
+fn peak_of(len: usize, position: usize) -> (usize, usize, usize) {
+    peak_shape(len)
+        .enumerate()
+        .find(|(_, (height, start))| position < start + (1usize << height))
+        .map(|(peak, (height, start))| (peak, height, start))
+        .expect("position below the leaf count")
+}
+
+fn bag_digest<D: TreeDigest>(len: usize, sum: u64, left: &[u8; 32], right: &[u8; 32]) -> [u8; 32] {
+    let serialized = [
+        [BAG_TAG].as_slice(),
+        (len as u64).to_be_bytes().as_slice(),
+        sum.to_be_bytes().as_slice(),
+        left.as_slice(),
+        right.as_slice(),
+    ]
+    .concat();
+    hash_bytes::<D>(&serialized)
+}
+
+// None if the peak sums overflow
+fn bag_peaks<D: TreeDigest>(len: usize, peaks: &[Commitment<D>]) -> Option<Commitment<D>> {
+    let Some((last, rest)) = peaks.split_last() else {
+        return Some(Commitment::new(0, hash_bytes::<D>(&[BAG_TAG])));
+    };
+    rest.iter().rev().try_fold(*last, |acc, peak| {
+        let sum = peak.sum.checked_add(acc.sum)?;
+        Some(Commitment::new(sum, bag_digest::<D>(len, sum, &peak.hash, &acc.hash)))
+    })
+}
+
+// Inclusion of one leaf: the path up to its peak, then every peak for the bagging.
+// Proofs are against the range at `len` leaves and go stale once it grows.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct MmrProof<D = Sha256> {
+    pub len: usize,
+    pub position: usize,
+    // Counts `index` from the first leaf of the peak
+    pub path: Proof<D>,
+    pub peaks: Vec<Commitment<D>>,
+}
+
+impl<D: TreeDigest> MmrProof<D> {
+    pub fn verify(&self, root_commitment: &Commitment<D>) -> bool {
+        if self.position >= self.len || self.peaks.len() != self.len.count_ones() as usize {
+            return false;
+        }
+        // The leaf count fixes which peak holds the position and how tall it is
+        let (peak, height, start) = peak_of(self.len, self.position);
+        if self.path.siblings.len() != height || self.path.index != self.position - start {
+            return false;
+        }
+        self.path.verify(&self.peaks[peak]) && bag_peaks(self.len, &self.peaks).as_ref() == Some(root_commitment)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::vec;
+
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    use super::*;
+
+    #[test]
+    fn test_mountain_range() {
+        let mut range = MountainRange::<Sha256>::new();
+        assert!(range.is_empty());
+        let mut roots = Vec::new();
+        for value in 1..=19u64 {
+            range.append(value).unwrap();
+            assert_eq!(range.peaks().len(), range.len().count_ones() as usize);
+            let root_commitment = range.commit();
+            assert_eq!(root_commitment.amount(), (1..=value).sum::<u64>());
+            for i in 0..range.len() {
+                let proof = range.prove(i);
+                assert!(proof.verify(&root_commitment), "Failed length {} position {}", value, i);
+            }
+            roots.push(root_commitment);
+        }
+
+        assert!(!range.is_empty());
+
+        // A single peak is a plain tree over the same leaves
+        let tree_root: Node = Node::new((1..=16).collect());
+        assert_eq!(roots[15], tree_root.commit());
+
+        // Proofs do not carry over to other sizes
+        assert!(!range.prove(3).verify(&roots[17]));
+
+        let mut moved = range.prove(17);
+        moved.position = 16;
+        assert!(!moved.verify(&range.commit()));
+
+        let mut tampered = range.prove(5);
+        tampered.peaks[2] = Commitment::new(tampered.peaks[2].sum + 1, tampered.peaks[2].hash);
+        assert!(!tampered.verify(&range.commit()));
+
+        let mut rng = StdRng::seed_from_u64(7);
+        let mut blinded = MountainRange::<Sha256>::new();
+        for value in [3, 1, 4] {
+            blinded.append_blinded(value, Blinding::random(&mut rng, None)).unwrap();
+        }
+        assert!(blinded.prove(2).verify(&blinded.commit()));
+
+        let mut full = MountainRange::<Sha256>::new();
+        full.append(u64::MAX).unwrap();
+        assert_eq!(full.append(1), Err(SumOverflow));
+        assert_eq!(full.commit().amount(), u64::MAX);
+        assert_eq!(vec![full.commit()], full.peaks());
+    }
+}
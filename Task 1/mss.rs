@@ -0,0 +1,203 @@
+// Merkle signature scheme: the tree commits to 2^height Lamport one-time keys and
+// its root is the public key. Each signature reveals one secret of every pair in
+// the next unused one-time key, picked by the bits of the message digest, and
+// carries the other halves of the key and a proof of the key's leaf, so verifying
+// is the usual path check. Leaves hold 1, so the root sum is the number of keys.
+//
+// Secrets are derived from a seed as hash(seed | key index (u64) | bit (u16) | side (u8)),
+// so the signer only keeps the seed and the tree. Never sign twice with one key:
+// a second message reveals the other halves and lets anyone forge.
+
+use alloc::vec::Vec;
+
+use super::{
+    hash_bytes, BuildError, Commitment, Encoding, Entry, MerkleTree, Node, Proof, Sha256, TreeDigest, V2_VERSION,
+};
+
+// One-time public keys are leaves of their own kind, so no balance leaf can pass for one
+const OTS_LEAF_TAG: u8 = 0x08;
+// A one-time key signs a 256-bit digest, one pair of secrets per bit
+const OTS_BITS: usize = 256;
+
+fn ots_secret<D: TreeDigest>(seed: &[u8; 32], index: usize, bit: usize, side: u8) -> [u8; 32] {
+    let serialized = [
+        seed.as_slice(),
+        &(index as u64).to_be_bytes(),
+        &(bit as u16).to_be_bytes(),
+        &[side],
+    ]
+    .concat();
+    #[cfg(feature = "zeroize")]
+    let serialized = zeroize::Zeroizing::new(serialized);
+    hash_bytes::<D>(&serialized)
+}
+
+// The public key is the hash of every secret, both sides of each bit in turn
+fn ots_leaf_digest<D: TreeDigest>(public_key: &[[u8; 32]]) -> [u8; 32] {
+    let mut serialized = Vec::with_capacity(2 + 32 * public_key.len());
+    serialized.extend_from_slice(&[OTS_LEAF_TAG, V2_VERSION]);
+    for half in public_key {
+        serialized.extend_from_slice(half);
+    }
+    hash_bytes::<D>(&serialized)
+}
+
+fn message_bit(digest: &[u8; 32], bit: usize) -> u8 {
+    (digest[bit / 8] >> (7 - bit % 8)) & 1
+}
+
+pub struct SigningKey<D = Sha256> {
+    seed: [u8; 32],
+    tree: Node<D>,
+    // The next one-time key to use
+    next: usize,
+}
+
+impl<D: TreeDigest> SigningKey<D> {
+    // Hashes 2^(height + 9) secrets, so keep `height` to what will actually be signed
+    pub fn try_generate(seed: [u8; 32], height: usize) -> Result<Self, BuildError> {
+        Self::try_resume(seed, height, 0)
+    }
+
+    // Picks up after a restart at the index saved from `next_index`
+    pub fn try_resume(seed: [u8; 32], height: usize, next: usize) -> Result<Self, BuildError> {
+        assert!(next <= 1 << height, "index {} past the last one-time key", next);
+        let leaves = (0..1usize << height).map(|index| {
+            let public_key: Vec<[u8; 32]> = (0..OTS_BITS)
+                .flat_map(|bit| [0, 1].map(|side| hash_bytes::<D>(&ots_secret::<D>(&seed, index, bit, side))))
+                .collect();
+            Entry::Leaf {
+                value: 1,
+                commitment: ots_leaf_digest::<D>(&public_key),
+                encoding: Encoding::V2,
+                blinding: None,
+            }
+        });
+        Ok(Self {
+            seed,
+            tree: Node::try_from_leaves(leaves)?,
+            next,
+        })
+    }
+
+    pub fn public_key(&self) -> Commitment<D> {
+        self.tree.commit()
+    }
+
+    // To be stored before a signature leaves the signer, so a crash never reuses a key
+    pub fn next_index(&self) -> usize {
+        self.next
+    }
+
+    // One-time keys not used yet
+    pub fn remaining(&self) -> usize {
+        (1 << self.tree.height()) - self.next
+    }
+
+    // Uses up the next one-time key, or returns `None` once all are used
+    pub fn sign(&mut self, message: &[u8]) -> Option<MerkleSignature<D>> {
+        if self.remaining() == 0 {
+            return None;
+        }
+        let index = self.next;
+        self.next += 1;
+
+        let digest = hash_bytes::<D>(message);
+        let (revealed, complements) = (0..OTS_BITS)
+            .map(|bit| {
+                let side = message_bit(&digest, bit);
+                (
+                    ots_secret::<D>(&self.seed, index, bit, side),
+                    hash_bytes::<D>(&ots_secret::<D>(&self.seed, index, bit, 1 - side)),
+                )
+            })
+            .unzip();
+        Some(MerkleSignature {
+            revealed,
+            complements,
+            path: self.tree.prove(index),
+        })
+    }
+}
+
+pub struct MerkleSignature<D = Sha256> {
+    // The secret picked by each bit of the message digest
+    pub revealed: Vec<[u8; 32]>,
+    // The public half for the bit's other value
+    pub complements: Vec<[u8; 32]>,
+    // Its position is the one-time key's index
+    pub path: Proof<D>,
+}
+
+impl<D: TreeDigest> MerkleSignature<D> {
+    pub fn index(&self) -> usize {
+        self.path.index
+    }
+
+    pub fn verify(&self, public_key: &Commitment<D>, message: &[u8]) -> bool {
+        if self.revealed.len() != OTS_BITS || self.complements.len() != OTS_BITS {
+            return false;
+        }
+        let digest = hash_bytes::<D>(message);
+        let mut ots_key = Vec::with_capacity(2 * OTS_BITS);
+        for (bit, (revealed, complement)) in self.revealed.iter().zip(&self.complements).enumerate() {
+            let revealed = hash_bytes::<D>(revealed);
+            if message_bit(&digest, bit) == 0 {
+                ots_key.extend([revealed, *complement]);
+            } else {
+                ots_key.extend([*complement, revealed]);
+            }
+        }
+        let leaf = ots_leaf_digest::<D>(&ots_key);
+        self.path
+            .verify_path(public_key, |node| node.sum == 1 && node.hash == leaf)
+            .is_ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ExclusiveAllotmentProof;
+
+    #[test]
+    fn test_merkle_signature() {
+        let mut key = SigningKey::<Sha256>::try_generate([7; 32], 2).unwrap();
+        let public_key = key.public_key();
+        assert_eq!((public_key.sum, key.remaining()), (4, 4));
+
+        let messages: [&[u8]; 4] = [b"epoch 1", b"epoch 2", b"epoch 3", b""];
+        let signatures: Vec<_> = messages.iter().map(|message| key.sign(message).unwrap()).collect();
+        assert!(key.sign(b"epoch 5").is_none());
+        assert_eq!(key.next_index(), 4);
+        for (i, (signature, message)) in signatures.iter().zip(messages).enumerate() {
+            assert_eq!(signature.index(), i);
+            assert!(signature.verify(&public_key, message), "Failed key {}", i);
+            assert!(!signature.verify(&public_key, b"another message"));
+            // The one-time key is not a balance leaf
+            assert!(!signature.path.verify(&public_key));
+        }
+
+        // The same seed gives the same key, another seed does not
+        assert!(SigningKey::<Sha256>::try_generate([7; 32], 2).unwrap().public_key() == public_key);
+        let other = SigningKey::<Sha256>::try_generate([8; 32], 2).unwrap().public_key();
+        assert!(!signatures[0].verify(&other, messages[0]));
+        let mut resumed = SigningKey::<Sha256>::try_resume([7; 32], 2, 3).unwrap();
+        assert_eq!(resumed.sign(messages[3]).unwrap().revealed, signatures[3].revealed);
+        assert_eq!(resumed.remaining(), 0);
+
+        // The right secrets under another key's path, or a leaf claiming more than one key
+        let with_path = |path: Proof<Sha256>| MerkleSignature {
+            revealed: signatures[0].revealed.clone(),
+            complements: signatures[0].complements.clone(),
+            path,
+        };
+        assert!(!with_path(key.tree.prove(1)).verify(&public_key, messages[0]));
+        let mut inflated = with_path(key.tree.prove(0));
+        inflated.path.node.sum = 2;
+        assert!(!inflated.verify(&public_key, messages[0]));
+        let mut short = with_path(key.tree.prove(0));
+        short.revealed.pop();
+        assert!(!short.verify(&public_key, messages[0]));
+    }
+}
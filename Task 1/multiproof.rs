@@ -0,0 +1,200 @@
+// Inclusion proof for several positions at once. Siblings are listed level by
+// level, left to right, and only where the verifier cannot compute the node
+// itself, so paths that meet share everything above the meeting point.
+
+use alloc::vec::Vec;
+use core::fmt;
+
+use super::{branch_digest, leaf_digest, Blinding, Commitment, Encoding, Entry, Node, Sha256, TreeDigest};
+
+pub struct MultiProof<D = Sha256> {
+    // Sorted by position, without duplicates
+    pub leaves: Vec<(usize, Commitment<D>, Option<Blinding>)>,
+    pub siblings: Vec<Commitment<D>>,
+    pub height: usize,
+    pub encoding: Encoding,
+}
+
+// Implemented by hand for the same reason as `Commitment`
+impl<D> Clone for MultiProof<D> {
+    fn clone(&self) -> Self {
+        MultiProof {
+            leaves: self.leaves.clone(),
+            siblings: self.siblings.clone(),
+            height: self.height,
+            encoding: self.encoding,
+        }
+    }
+}
+
+impl<D> PartialEq for MultiProof<D> {
+    fn eq(&self, other: &Self) -> bool {
+        self.leaves == other.leaves
+            && self.siblings == other.siblings
+            && self.height == other.height
+            && self.encoding == other.encoding
+    }
+}
+
+impl<D> Eq for MultiProof<D> {}
+
+impl<D> fmt::Debug for MultiProof<D> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("MultiProof")
+            .field("leaves", &self.leaves)
+            .field("siblings", &self.siblings)
+            .field("height", &self.height)
+            .field("encoding", &self.encoding)
+            .finish()
+    }
+}
+
+impl<D: TreeDigest> Node<D> {
+    pub fn prove_many(&self, positions: &[usize]) -> MultiProof<D> {
+        let mut positions = positions.to_vec();
+        positions.sort_unstable();
+        positions.dedup();
+        assert!(
+            positions.iter().all(|position| position >> self.height() == 0),
+            "position out of range"
+        );
+
+        let leaves = positions
+            .iter()
+            .map(|&position| match self.leaf(position) {
+                leaf @ Entry::Leaf { blinding, .. } => (position, Commitment::from(leaf), blinding.clone()),
+                Entry::Branch { .. } => unreachable!(),
+            })
+            .collect();
+
+        let mut siblings = Vec::new();
+        let mut known = positions;
+        for level in 0..self.height() {
+            let mut i = 0;
+            while i < known.len() {
+                // Skip the sibling if the verifier will already have it
+                if known.get(i + 1) == Some(&(known[i] ^ 1)) {
+                    i += 2;
+                } else {
+                    siblings.push(Commitment::from(self.descendant(level, known[i] ^ 1)));
+                    i += 1;
+                }
+            }
+            known = known.iter().map(|index| index >> 1).collect();
+            known.dedup();
+        }
+
+        MultiProof {
+            leaves,
+            siblings,
+            height: self.height(),
+            encoding: self.encoding(),
+        }
+    }
+}
+
+impl<D: TreeDigest> MultiProof<D> {
+    pub fn verify(&self, root_commitment: &Commitment<D>) -> bool {
+        if self.leaves.is_empty() || self.height >= usize::BITS as usize {
+            return false;
+        }
+        let mut known = Vec::with_capacity(self.leaves.len());
+        for (position, node, blinding) in &self.leaves {
+            if position >> self.height != 0 || known.last().is_some_and(|(last, _)| last >= position) {
+                return false;
+            }
+            if node.hash != leaf_digest::<D>(self.encoding, node.sum, blinding.as_ref()) {
+                return false;
+            }
+            known.push((*position, *node));
+        }
+
+        let mut siblings = self.siblings.iter();
+        for height in 1..=self.height {
+            let mut parents = Vec::with_capacity(known.len());
+            let mut i = 0;
+            while i < known.len() {
+                let (index, commitment) = known[i];
+                let sibling = match known.get(i + 1) {
+                    Some(&(next, next_commitment)) if next == index ^ 1 => {
+                        i += 1;
+                        next_commitment
+                    }
+                    _ => match siblings.next() {
+                        Some(sibling) => *sibling,
+                        None => return false,
+                    },
+                };
+                i += 1;
+
+                let (left, right) = if (index & 1) == 0 {
+                    (commitment, sibling)
+                } else {
+                    (sibling, commitment)
+                };
+                let Some(sum) = left.sum.checked_add(right.sum) else {
+                    return false;
+                };
+                let hash = branch_digest::<D>(self.encoding, height, sum, &left.hash, &right.hash);
+                parents.push((index >> 1, Commitment::new(sum, hash)));
+            }
+            known = parents;
+        }
+
+        siblings.next().is_none() && known.len() == 1 && &known[0].1 == root_commitment
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::vec;
+
+    use super::*;
+    use crate::MerkleTree;
+
+    #[test]
+    fn test_multiproof() {
+        let values: Vec<u64> = (1..=16).collect();
+        let tree_root: Node = Node::new(values);
+        let root_commitment = tree_root.commit();
+
+        for positions in [
+            vec![0],
+            vec![0, 1],
+            vec![3, 12, 5, 3],
+            (0..16).collect(),
+            vec![15, 14, 7],
+        ] {
+            let proof = tree_root.prove_many(&positions);
+            assert!(proof.verify(&root_commitment), "Failed positions {:?}", positions);
+        }
+
+        // Adjacent leaves share every sibling above their parent
+        let single = tree_root.prove(4).siblings.len();
+        assert_eq!(tree_root.prove_many(&[4, 5]).siblings.len(), single - 1);
+        assert!(tree_root.prove_many(&(0..16).collect::<Vec<_>>()).siblings.is_empty());
+    }
+
+    #[test]
+    fn test_multiproof_tampered() {
+        let tree_root: Node = Node::new((1..=8).collect());
+        let root_commitment = tree_root.commit();
+        let proof = tree_root.prove_many(&[1, 6]);
+
+        let mut wrong_value = proof.clone();
+        wrong_value.leaves[0].1.sum += 1;
+        assert!(!wrong_value.verify(&root_commitment));
+
+        let mut wrong_position = proof.clone();
+        wrong_position.leaves[1].0 = 7;
+        assert!(!wrong_position.verify(&root_commitment));
+
+        let mut extra_sibling = proof.clone();
+        extra_sibling.siblings.push(root_commitment);
+        assert!(!extra_sibling.verify(&root_commitment));
+
+        let mut missing_sibling = proof;
+        missing_sibling.siblings.pop();
+        assert!(!missing_sibling.verify(&root_commitment));
+    }
+}
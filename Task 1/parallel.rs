@@ -0,0 +1,148 @@
+// Tree construction and proving across threads. Both halves of a subtree are
+// built with `rayon::join` until they are small enough that splitting costs more
+// than it saves, so the tree and its root are the same as the sequential
+// builder's. Proofs are made the same way in one walk down the tree, each branch
+// handing its path so far to both children, so a branch is visited once rather
+// than once per proof below it.
+
+use alloc::vec;
+use alloc::vec::Vec;
+
+use super::{leaf_entry, BuildError, Commitment, Encoding, Entry, Node, Proof, TreeDigest};
+
+// Subtrees with this many leaves, or this many positions to prove, or fewer are
+// handled on one thread
+const SEQUENTIAL_CUTOFF: usize = 1 << 12;
+
+impl<D: TreeDigest> Node<D> {
+    pub fn try_new_par(values: &[u64]) -> Result<Self, BuildError> {
+        Self::try_new_par_with_encoding(values, Encoding::default())
+    }
+
+    pub fn try_new_par_with_encoding(values: &[u64], encoding: Encoding) -> Result<Self, BuildError> {
+        // We only deal with 2^n values
+        if !values.len().is_power_of_two() {
+            return Err(BuildError::NotPowerOfTwo { len: values.len() });
+        }
+        Self::par_subtree(values, encoding)
+    }
+
+    fn par_subtree(values: &[u64], encoding: Encoding) -> Result<Self, BuildError> {
+        if values.len() <= SEQUENTIAL_CUTOFF {
+            let leaves = values.iter().map(|value| leaf_entry::<D>(*value, None, encoding));
+            return Self::try_from_leaves(leaves);
+        }
+        let (left, right) = values.split_at(values.len() / 2);
+        let (left, right) = rayon::join(
+            || Self::par_subtree(left, encoding),
+            || Self::par_subtree(right, encoding),
+        );
+        Node::try_new_branch(left?, right?)
+    }
+
+    pub fn prove_all(&self) -> Vec<Proof<D>> {
+        let positions: Vec<usize> = (0..1 << self.height()).collect();
+        self.par_prove(self.entries.len() - 1, &positions, Vec::new())
+    }
+
+    // Same proofs as `prove`, sorted by position and without duplicates
+    pub fn prove_many_parallel(&self, positions: &[usize]) -> Vec<Proof<D>> {
+        let mut positions = positions.to_vec();
+        positions.sort_unstable();
+        positions.dedup();
+        assert!(
+            positions.iter().all(|position| position >> self.height() == 0),
+            "position out of range"
+        );
+        if positions.is_empty() {
+            return Vec::new();
+        }
+        self.par_prove(self.entries.len() - 1, &positions, Vec::new())
+    }
+
+    // `positions` are sorted and all under `entry`, and `path` holds the siblings
+    // from the root down to it
+    fn par_prove(&self, entry: usize, positions: &[usize], path: Vec<Commitment<D>>) -> Vec<Proof<D>> {
+        let (height, left, right) = match &self.entries[entry] {
+            Entry::Branch {
+                height, left, right, ..
+            } => (*height, *left, *right),
+            leaf @ Entry::Leaf { blinding, .. } => {
+                let mut siblings = path;
+                siblings.reverse();
+                return vec![Proof {
+                    node: Commitment::from(leaf),
+                    siblings,
+                    index: positions[0],
+                    encoding: self.encoding(),
+                    blinding: blinding.clone(),
+                }];
+            }
+        };
+
+        let split = positions.partition_point(|position| (position & (1 << (height - 1))) == 0);
+        let (left_positions, right_positions) = positions.split_at(split);
+        let prove = |child: usize, positions: &[usize], sibling: usize| {
+            if positions.is_empty() {
+                return Vec::new();
+            }
+            let mut path = path.clone();
+            path.push(Commitment::from(&self.entries[sibling]));
+            self.par_prove(child, positions, path)
+        };
+        let (mut proofs, right_proofs) = if positions.len() > SEQUENTIAL_CUTOFF {
+            rayon::join(
+                || prove(left, left_positions, right),
+                || prove(right, right_positions, left),
+            )
+        } else {
+            (prove(left, left_positions, right), prove(right, right_positions, left))
+        };
+        proofs.extend(right_proofs);
+        proofs
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{MerkleTree, Sha256, SumCommitment};
+
+    #[test]
+    fn test_parallel_matches_sequential() {
+        let values: Vec<u64> = (0..1u64 << 14).map(|i| i * 31 % 1_000).collect();
+        for encoding in [Encoding::V0, Encoding::V1, Encoding::V2] {
+            let sequential = Node::<Sha256>::try_new_with_encoding(values.clone(), encoding).unwrap();
+            let parallel = Node::<Sha256>::try_new_par_with_encoding(&values, encoding).unwrap();
+            assert_eq!(parallel.commit(), sequential.commit());
+            assert!(parallel == sequential);
+        }
+
+        // Small inputs never leave the calling thread but give the same tree
+        let small = Node::<Sha256>::try_new_par(&[1, 2, 3, 4]).unwrap();
+        assert!(small == Node::<Sha256>::try_new(vec![1, 2, 3, 4]).unwrap());
+
+        let mut overflowing = values.clone();
+        overflowing[values.len() - 1] = u64::MAX;
+        assert_eq!(
+            Node::<Sha256>::try_new_par(&overflowing).map(|node| node.amount()),
+            Err(BuildError::SumOverflow)
+        );
+        assert_eq!(
+            Node::<Sha256>::try_new_par(&values[1..]).map(|node| node.amount()),
+            Err(BuildError::NotPowerOfTwo { len: values.len() - 1 })
+        );
+
+        let tree_root = Node::<Sha256>::try_new_par(&values).unwrap();
+        let proofs = tree_root.prove_all();
+        assert_eq!(proofs.len(), values.len());
+        for (i, proof) in proofs.iter().enumerate() {
+            assert!(*proof == tree_root.prove(i), "Failed position {}", i);
+        }
+        let some = tree_root.prove_many_parallel(&[9_000, 3, 9_000, 16_383, 0]);
+        let positions: Vec<usize> = some.iter().map(|proof| proof.index).collect();
+        assert_eq!(positions, vec![0, 3, 9_000, 16_383]);
+        assert!(some[2] == tree_root.prove(9_000));
+        assert!(tree_root.prove_many_parallel(&[]).is_empty());
+    }
+}
@@ -0,0 +1,473 @@
+// Patricia trie over 256-bit keys, e.g. hashes of account addresses as in
+// Ethereum's state trie, with every node committing to the sum below it. Keys are
+// walked four bits at a time: branches fan out on one nibble, extensions skip a
+// run of nibbles all keys below them share, and leaves hold the rest of one key.
+// A proof is the nodes met on the way down from the root, so it shows either the
+// value under a key or where the walk leaves the trie, i.e. that the key is absent.
+//
+// Leaf:      tag (u8) | version (u8) | nibble count (u8) | nibbles (one per byte) | value (u64)
+// Extension: tag (u8) | version (u8) | nibble count (u8) | nibbles | child sum (u64) | child hash
+// Branch:    tag (u8) | version (u8) | child bitmap (u16) | sum (u64) and hash of each child
+// An empty trie is a branch without children.
+
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+use core::fmt;
+
+use super::{hash_bytes, Commitment, Sha256, SumOverflow, TreeDigest, V2_VERSION};
+
+const TRIE_LEAF_TAG: u8 = 0x0a;
+const TRIE_EXTENSION_TAG: u8 = 0x0b;
+const TRIE_BRANCH_TAG: u8 = 0x0c;
+
+fn key_nibbles(key: &[u8; 32]) -> Vec<u8> {
+    key.iter().flat_map(|byte| [byte >> 4, byte & 0x0f]).collect()
+}
+
+fn common_prefix(a: &[u8], b: &[u8]) -> usize {
+    a.iter().zip(b).take_while(|(a, b)| a == b).count()
+}
+
+// A node as a proof shows it: children only by their commitments
+pub enum TrieStep<D = Sha256> {
+    Leaf { path: Vec<u8>, value: u64 },
+    Extension { path: Vec<u8>, child: Commitment<D> },
+    Branch { children: Box<[Option<Commitment<D>>; 16]> },
+}
+
+// Implemented by hand for the same reason as `Commitment`
+impl<D> Clone for TrieStep<D> {
+    fn clone(&self) -> Self {
+        match self {
+            TrieStep::Leaf { path, value } => TrieStep::Leaf {
+                path: path.clone(),
+                value: *value,
+            },
+            TrieStep::Extension { path, child } => TrieStep::Extension {
+                path: path.clone(),
+                child: *child,
+            },
+            TrieStep::Branch { children } => TrieStep::Branch {
+                children: children.clone(),
+            },
+        }
+    }
+}
+
+impl<D> fmt::Debug for TrieStep<D> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TrieStep::Leaf { path, value } => f
+                .debug_struct("Leaf")
+                .field("path", path)
+                .field("value", value)
+                .finish(),
+            TrieStep::Extension { path, child } => f
+                .debug_struct("Extension")
+                .field("path", path)
+                .field("child", child)
+                .finish(),
+            TrieStep::Branch { children } => f.debug_struct("Branch").field("children", children).finish(),
+        }
+    }
+}
+
+impl<D: TreeDigest> TrieStep<D> {
+    // `None` if the children's sums overflow, which no trie built here has
+    fn commit(&self) -> Option<Commitment<D>> {
+        let path_header = |tag: u8, path: &[u8]| {
+            let mut serialized = Vec::with_capacity(3 + path.len() + 40);
+            serialized.extend_from_slice(&[tag, V2_VERSION, path.len() as u8]);
+            serialized.extend_from_slice(path);
+            serialized
+        };
+        let (sum, serialized) = match self {
+            TrieStep::Leaf { path, value } => {
+                let mut serialized = path_header(TRIE_LEAF_TAG, path);
+                serialized.extend_from_slice(&value.to_be_bytes());
+                (*value, serialized)
+            }
+            TrieStep::Extension { path, child } => {
+                let mut serialized = path_header(TRIE_EXTENSION_TAG, path);
+                serialized.extend_from_slice(&child.sum.to_be_bytes());
+                serialized.extend_from_slice(&child.hash);
+                (child.sum, serialized)
+            }
+            TrieStep::Branch { children } => {
+                let mut bitmap = 0u16;
+                let mut sum = 0u64;
+                let mut serialized = Vec::with_capacity(4 + 16 * 40);
+                serialized.extend_from_slice(&[TRIE_BRANCH_TAG, V2_VERSION, 0, 0]);
+                for (nibble, child) in children.iter().enumerate() {
+                    if let Some(child) = child {
+                        bitmap |= 1 << nibble;
+                        sum = sum.checked_add(child.sum)?;
+                        serialized.extend_from_slice(&child.sum.to_be_bytes());
+                        serialized.extend_from_slice(&child.hash);
+                    }
+                }
+                serialized[2..4].copy_from_slice(&bitmap.to_be_bytes());
+                (sum, serialized)
+            }
+        };
+        Some(Commitment::new(sum, hash_bytes::<D>(&serialized)))
+    }
+}
+
+enum TrieNode<D> {
+    Leaf {
+        path: Vec<u8>,
+        value: u64,
+    },
+    Extension {
+        path: Vec<u8>,
+        child: Box<CommittedNode<D>>,
+    },
+    Branch {
+        children: [Option<Box<CommittedNode<D>>>; 16],
+    },
+}
+
+struct CommittedNode<D> {
+    node: TrieNode<D>,
+    commitment: Commitment<D>,
+}
+
+impl<D: TreeDigest> CommittedNode<D> {
+    fn new(node: TrieNode<D>) -> Box<Self> {
+        // Cannot overflow, the total was checked on insert
+        let commitment = Self::step(&node).commit().expect("sums checked on insert");
+        Box::new(Self { node, commitment })
+    }
+
+    fn step(node: &TrieNode<D>) -> TrieStep<D> {
+        match node {
+            TrieNode::Leaf { path, value } => TrieStep::Leaf {
+                path: path.clone(),
+                value: *value,
+            },
+            TrieNode::Extension { path, child } => TrieStep::Extension {
+                path: path.clone(),
+                child: child.commitment,
+            },
+            TrieNode::Branch { children } => TrieStep::Branch {
+                children: Box::new(core::array::from_fn(|nibble| {
+                    children[nibble].as_ref().map(|child| child.commitment)
+                })),
+            },
+        }
+    }
+
+    fn leaf(path: &[u8], value: u64) -> Box<Self> {
+        Self::new(TrieNode::Leaf {
+            path: path.to_vec(),
+            value,
+        })
+    }
+
+    // Skips the extension when there is nothing to skip
+    fn extended(path: &[u8], child: Box<Self>) -> Box<Self> {
+        if path.is_empty() {
+            return child;
+        }
+        Self::new(TrieNode::Extension {
+            path: path.to_vec(),
+            child,
+        })
+    }
+
+    // Two subtrees splitting on their first nibble, each without that nibble
+    fn fork(first: (u8, Box<Self>), second: (u8, Box<Self>)) -> Box<Self> {
+        let mut children: [Option<Box<Self>>; 16] = Default::default();
+        children[first.0 as usize] = Some(first.1);
+        children[second.0 as usize] = Some(second.1);
+        Self::new(TrieNode::Branch { children })
+    }
+
+    // `path` is the rest of a key, as long as every other key's path at this node
+    fn insert(node: Option<Box<Self>>, path: &[u8], new_value: u64) -> Box<Self> {
+        let Some(node) = node else {
+            return Self::leaf(path, new_value);
+        };
+        match node.node {
+            TrieNode::Leaf { path: leaf_path, value } => {
+                if leaf_path == path {
+                    return Self::leaf(path, new_value);
+                }
+                let shared = common_prefix(&leaf_path, path);
+                let fork = Self::fork(
+                    (leaf_path[shared], Self::leaf(&leaf_path[shared + 1..], value)),
+                    (path[shared], Self::leaf(&path[shared + 1..], new_value)),
+                );
+                Self::extended(&path[..shared], fork)
+            }
+            TrieNode::Extension {
+                path: extension_path,
+                child,
+            } => {
+                let shared = common_prefix(&extension_path, path);
+                if shared == extension_path.len() {
+                    let child = Self::insert(Some(child), &path[shared..], new_value);
+                    return Self::extended(&extension_path, child);
+                }
+                let fork = Self::fork(
+                    (
+                        extension_path[shared],
+                        Self::extended(&extension_path[shared + 1..], child),
+                    ),
+                    (path[shared], Self::leaf(&path[shared + 1..], new_value)),
+                );
+                Self::extended(&path[..shared], fork)
+            }
+            TrieNode::Branch { mut children } => {
+                let nibble = path[0] as usize;
+                children[nibble] = Some(Self::insert(children[nibble].take(), &path[1..], new_value));
+                Self::new(TrieNode::Branch { children })
+            }
+        }
+    }
+}
+
+pub struct PatriciaTrie<D = Sha256> {
+    root: Option<Box<CommittedNode<D>>>,
+    len: usize,
+}
+
+// Proves the value under `key`, or with `value` None that the key is absent
+pub struct TrieProof<D = Sha256> {
+    pub key: [u8; 32],
+    pub value: Option<u64>,
+    // From the root down to the node where the walk for `key` ends
+    pub steps: Vec<TrieStep<D>>,
+}
+
+impl<D> Clone for TrieProof<D> {
+    fn clone(&self) -> Self {
+        TrieProof {
+            key: self.key,
+            value: self.value,
+            steps: self.steps.clone(),
+        }
+    }
+}
+
+impl<D: TreeDigest> Default for PatriciaTrie<D> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<D: TreeDigest> PatriciaTrie<D> {
+    pub fn new() -> Self {
+        Self { root: None, len: 0 }
+    }
+
+    pub fn key_for(id: &[u8]) -> [u8; 32] {
+        hash_bytes::<D>(id)
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn get(&self, key: &[u8; 32]) -> Option<u64> {
+        match self.walk(key).last()? {
+            TrieStep::Leaf { path, value } if key_nibbles(key).ends_with(path) => Some(*value),
+            _ => None,
+        }
+    }
+
+    pub fn insert(&mut self, key: [u8; 32], value: u64) -> Result<Option<u64>, SumOverflow> {
+        let old = self.get(&key);
+        (self.commit().sum - old.unwrap_or(0))
+            .checked_add(value)
+            .ok_or(SumOverflow)?;
+        self.root = Some(CommittedNode::insert(self.root.take(), &key_nibbles(&key), value));
+        if old.is_none() {
+            self.len += 1;
+        }
+        Ok(old)
+    }
+
+    pub fn commit(&self) -> Commitment<D> {
+        match &self.root {
+            Some(root) => root.commitment,
+            None => empty_root::<D>(),
+        }
+    }
+
+    pub fn prove(&self, key: &[u8; 32]) -> TrieProof<D> {
+        let mut steps = self.walk(key);
+        if steps.is_empty() {
+            steps.push(TrieStep::Branch {
+                children: Box::new([None; 16]),
+            });
+        }
+        TrieProof {
+            key: *key,
+            value: self.get(key),
+            steps,
+        }
+    }
+
+    // The nodes from the root down to where `key` ends or leaves the trie
+    fn walk(&self, key: &[u8; 32]) -> Vec<TrieStep<D>> {
+        let nibbles = key_nibbles(key);
+        let mut steps = Vec::new();
+        let mut depth = 0;
+        let mut current = self.root.as_deref();
+        while let Some(node) = current {
+            steps.push(CommittedNode::step(&node.node));
+            current = match &node.node {
+                TrieNode::Leaf { .. } => None,
+                TrieNode::Extension { path, child } => {
+                    let next = nibbles[depth..].starts_with(path).then_some(child.as_ref());
+                    depth += path.len();
+                    next
+                }
+                TrieNode::Branch { children } => {
+                    depth += 1;
+                    children[nibbles[depth - 1] as usize].as_deref()
+                }
+            };
+        }
+        steps
+    }
+}
+
+fn empty_root<D: TreeDigest>() -> Commitment<D> {
+    let empty = TrieStep::<D>::Branch {
+        children: Box::new([None; 16]),
+    };
+    empty.commit().expect("an empty branch cannot overflow")
+}
+
+impl<D: TreeDigest> TrieProof<D> {
+    pub fn verify(&self, root_commitment: &Commitment<D>) -> bool {
+        let nibbles = key_nibbles(&self.key);
+        let mut expected = *root_commitment;
+        let mut depth = 0;
+        for (i, step) in self.steps.iter().enumerate() {
+            if step.commit() != Some(expected) {
+                return false;
+            }
+            let last = i + 1 == self.steps.len();
+            let rest = &nibbles[depth..];
+            // Where the walk for the key ends, and with what
+            let found = match step {
+                TrieStep::Leaf { path, value } => Some((path.as_slice() == rest).then_some(*value)),
+                TrieStep::Extension { path, child } => {
+                    if rest.starts_with(path) {
+                        expected = *child;
+                        depth += path.len();
+                        None
+                    } else {
+                        Some(None)
+                    }
+                }
+                TrieStep::Branch { children } => match rest.first().and_then(|nibble| children[*nibble as usize]) {
+                    Some(child) => {
+                        expected = child;
+                        depth += 1;
+                        None
+                    }
+                    None => Some(None),
+                },
+            };
+            if let Some(found) = found {
+                return last && found == self.value;
+            }
+        }
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::vec;
+
+    use super::*;
+    use crate::SumCommitment;
+
+    #[test]
+    fn test_patricia_trie() {
+        let mut trie = PatriciaTrie::<Sha256>::new();
+        assert!(trie.is_empty());
+        let empty_root = trie.commit();
+        assert_eq!(empty_root.amount(), 0);
+        let alice = PatriciaTrie::<Sha256>::key_for(b"alice");
+        assert!(trie.prove(&alice).verify(&empty_root));
+
+        // Keys sharing their first nibbles need an extension below the root branch
+        let mut shared = alice;
+        shared[1] ^= 0x01;
+        let mut accounts = vec![(alice, 10), (shared, 20)];
+        for name in ["bob", "carol", "dave", "erin"] {
+            accounts.push((PatriciaTrie::<Sha256>::key_for(name.as_bytes()), name.len() as u64));
+        }
+        for (key, value) in &accounts {
+            assert_eq!(trie.insert(*key, *value), Ok(None));
+        }
+        assert_eq!(trie.insert(alice, 15), Ok(Some(10)));
+        accounts[0].1 = 15;
+        assert_eq!(trie.len(), 6);
+        assert!(!trie.is_empty());
+
+        let root_commitment = trie.commit();
+        assert_eq!(root_commitment.amount(), 15 + 20 + 3 + 5 + 4 + 4);
+        for (key, value) in &accounts {
+            let proof = trie.prove(key);
+            assert_eq!(proof.value, Some(*value));
+            assert!(proof.verify(&root_commitment));
+        }
+        assert!(trie
+            .prove(&alice)
+            .steps
+            .iter()
+            .any(|step| matches!(step, TrieStep::Extension { .. })));
+
+        // Insertion order does not change the root
+        let mut reversed = PatriciaTrie::<Sha256>::new();
+        for (key, value) in accounts.iter().rev() {
+            reversed.insert(*key, *value).unwrap();
+        }
+        assert_eq!(reversed.commit(), root_commitment);
+
+        // Absent keys end at an empty branch slot, a diverging extension or another leaf
+        let mut near = alice;
+        near[31] ^= 0x01;
+        let mut diverging = alice;
+        diverging[0] ^= 0x10;
+        for key in [PatriciaTrie::<Sha256>::key_for(b"frank"), near, diverging] {
+            let proof = trie.prove(&key);
+            assert_eq!(proof.value, None);
+            assert!(proof.verify(&root_commitment));
+            let mut forged = proof.clone();
+            forged.value = Some(0);
+            assert!(!forged.verify(&root_commitment));
+        }
+
+        // A balance cannot be claimed under another key, changed, or proven by part of a walk
+        let mut moved = trie.prove(&alice);
+        moved.key = near;
+        assert!(!moved.verify(&root_commitment));
+        let mut inflated = trie.prove(&alice);
+        inflated.value = Some(16);
+        assert!(!inflated.verify(&root_commitment));
+        let mut cut = trie.prove(&alice);
+        cut.steps.pop();
+        cut.value = None;
+        assert!(!cut.verify(&root_commitment));
+        let mut extended = trie.prove(&alice);
+        extended.steps.push(extended.steps[0].clone());
+        assert!(!extended.verify(&root_commitment));
+
+        assert_eq!(trie.insert(near, u64::MAX), Err(SumOverflow));
+        assert_eq!(trie.commit(), root_commitment);
+        assert_eq!(trie.get(&near), None);
+    }
+}
@@ -0,0 +1,318 @@
+// Sum tree whose nodes carry Pedersen commitments v*B + r*B_blinding instead of
+// clear sums. Commitments add up the tree, so auditors can check every path
+// without learning any intermediate sum, and only the root is opened at the end.
+// Each user receives the opening of their own leaf. Every leaf also carries a
+// Bulletproof that it commits to a value in [0, 2^64), since a hidden negative
+// balance would otherwise silently reduce the total.
+
+use alloc::vec;
+use alloc::vec::Vec;
+use core::fmt;
+use core::marker::PhantomData;
+
+use bulletproofs::{BulletproofGens, PedersenGens, RangeProof};
+use curve25519_dalek_ng::ristretto::{CompressedRistretto, RistrettoPoint};
+use curve25519_dalek_ng::scalar::Scalar;
+use merlin::Transcript;
+use rand::{CryptoRng, RngCore};
+
+use super::{hash_bytes, Sha256, SumOverflow, TreeDigest, BRANCH_TAG, LEAF_TAG};
+
+const RANGE_BITS: usize = 64;
+const RANGE_TRANSCRIPT: &[u8] = b"merkle-sum-tree/leaf-range";
+
+fn pedersen_commit(value: u64, blinding: &Scalar) -> RistrettoPoint {
+    PedersenGens::default().commit(Scalar::from(value), *blinding)
+}
+
+fn hidden_leaf_digest<D: TreeDigest>(point: &CompressedRistretto) -> [u8; 32] {
+    hash_bytes::<D>(&[[LEAF_TAG].as_slice(), point.as_bytes()].concat())
+}
+
+fn hidden_branch_digest<D: TreeDigest>(
+    height: usize,
+    point: &CompressedRistretto,
+    left: &[u8; 32],
+    right: &[u8; 32],
+) -> [u8; 32] {
+    let serialized = [
+        [BRANCH_TAG].as_slice(),
+        (height as u64).to_be_bytes().as_slice(),
+        point.as_bytes(),
+        left.as_slice(),
+        right.as_slice(),
+    ]
+    .concat();
+    hash_bytes::<D>(&serialized)
+}
+
+pub struct PedersenTree<D = Sha256> {
+    values: Vec<u64>,
+    blindings: Vec<Scalar>,
+    range_proofs: Vec<RangeProof>,
+    // Level 0 holds the leaves and the last level the root
+    levels: Vec<Vec<(RistrettoPoint, [u8; 32])>>,
+    hasher: PhantomData<fn() -> D>,
+}
+
+pub struct PedersenRoot<D = Sha256> {
+    pub point: CompressedRistretto,
+    pub hash: [u8; 32],
+    hasher: PhantomData<fn() -> D>,
+}
+
+pub struct PedersenProof<D = Sha256> {
+    pub index: usize,
+    pub leaf: CompressedRistretto,
+    pub range_proof: RangeProof,
+    // Bottom-up
+    pub siblings: Vec<(CompressedRistretto, [u8; 32])>,
+    hasher: PhantomData<fn() -> D>,
+}
+
+// Implemented by hand for the same reason as `Commitment`
+impl<D> Clone for PedersenRoot<D> {
+    fn clone(&self) -> Self {
+        Self {
+            point: self.point,
+            hash: self.hash,
+            hasher: PhantomData,
+        }
+    }
+}
+
+impl<D> fmt::Debug for PedersenRoot<D> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("PedersenRoot")
+            .field("point", &self.point)
+            .field("hash", &self.hash)
+            .finish()
+    }
+}
+
+impl<D> Clone for PedersenProof<D> {
+    fn clone(&self) -> Self {
+        Self {
+            index: self.index,
+            leaf: self.leaf,
+            range_proof: self.range_proof.clone(),
+            siblings: self.siblings.clone(),
+            hasher: PhantomData,
+        }
+    }
+}
+
+impl<D> fmt::Debug for PedersenProof<D> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("PedersenProof")
+            .field("index", &self.index)
+            .field("leaf", &self.leaf)
+            .field("range_proof", &self.range_proof)
+            .field("siblings", &self.siblings)
+            .finish()
+    }
+}
+
+impl<D: TreeDigest> PedersenTree<D> {
+    // Pads to a power of two with zero leaves, each under its own random blinding
+    pub fn new<R: RngCore + CryptoRng>(mut values: Vec<u64>, rng: &mut R) -> Result<Self, SumOverflow> {
+        values
+            .iter()
+            .try_fold(0u64, |total, value| total.checked_add(*value))
+            .ok_or(SumOverflow)?;
+        values.resize(values.len().max(1).next_power_of_two(), 0);
+        let blindings: Vec<Scalar> = values.iter().map(|_| Scalar::random(rng)).collect();
+
+        let (bp_gens, pc_gens) = (BulletproofGens::new(RANGE_BITS, 1), PedersenGens::default());
+        let range_proofs = values
+            .iter()
+            .zip(&blindings)
+            .map(|(value, blinding)| {
+                let mut transcript = Transcript::new(RANGE_TRANSCRIPT);
+                let (proof, _) = RangeProof::prove_single_with_rng(
+                    &bp_gens,
+                    &pc_gens,
+                    &mut transcript,
+                    *value,
+                    blinding,
+                    RANGE_BITS,
+                    rng,
+                )
+                .expect("generators cover 64-bit values");
+                proof
+            })
+            .collect();
+
+        let leaves = values
+            .iter()
+            .zip(&blindings)
+            .map(|(value, blinding)| {
+                let point = pedersen_commit(*value, blinding);
+                (point, hidden_leaf_digest::<D>(&point.compress()))
+            })
+            .collect();
+        let mut levels: Vec<Vec<(RistrettoPoint, [u8; 32])>> = vec![leaves];
+        while levels.last().unwrap().len() > 1 {
+            let height = levels.len();
+            let parents = levels
+                .last()
+                .unwrap()
+                .chunks(2)
+                .map(|pair| {
+                    let point = pair[0].0 + pair[1].0;
+                    (
+                        point,
+                        hidden_branch_digest::<D>(height, &point.compress(), &pair[0].1, &pair[1].1),
+                    )
+                })
+                .collect();
+            levels.push(parents);
+        }
+
+        Ok(Self {
+            values,
+            blindings,
+            range_proofs,
+            levels,
+            hasher: PhantomData,
+        })
+    }
+
+    pub fn commit(&self) -> PedersenRoot<D> {
+        let (point, hash) = self.levels.last().unwrap()[0];
+        PedersenRoot {
+            point: point.compress(),
+            hash,
+            hasher: PhantomData,
+        }
+    }
+
+    pub fn prove(&self, position: usize) -> PedersenProof<D> {
+        let mut index = position;
+        let siblings = self.levels[..self.levels.len() - 1]
+            .iter()
+            .map(|level| {
+                let (point, hash) = level[index ^ 1];
+                index >>= 1;
+                (point.compress(), hash)
+            })
+            .collect();
+        PedersenProof {
+            index: position,
+            leaf: self.levels[0][position].0.compress(),
+            range_proof: self.range_proofs[position].clone(),
+            siblings,
+            hasher: PhantomData,
+        }
+    }
+
+    // Handed privately to the owner of the leaf
+    pub fn opening(&self, position: usize) -> (u64, Scalar) {
+        (self.values[position], self.blindings[position])
+    }
+
+    // Published at the end to reveal the total liabilities
+    pub fn open_root(&self) -> (u64, Scalar) {
+        // Cannot overflow, the total was checked on construction
+        (self.values.iter().sum(), self.blindings.iter().sum())
+    }
+}
+
+impl<D: TreeDigest> PedersenRoot<D> {
+    pub fn verify_opening(&self, total: u64, blinding: &Scalar) -> bool {
+        pedersen_commit(total, blinding).compress() == self.point
+    }
+}
+
+impl<D: TreeDigest> PedersenProof<D> {
+    // Checks the leaf's range proof and the path; amounts stay hidden
+    pub fn verify(&self, root: &PedersenRoot<D>) -> bool {
+        // Bits above the path would let the same proof verify at several positions
+        if self.index.checked_shr(self.siblings.len() as u32).unwrap_or(0) != 0 {
+            return false;
+        }
+        let mut transcript = Transcript::new(RANGE_TRANSCRIPT);
+        let in_range = self.range_proof.verify_single(
+            &BulletproofGens::new(RANGE_BITS, 1),
+            &PedersenGens::default(),
+            &mut transcript,
+            &self.leaf,
+            RANGE_BITS,
+        );
+        if in_range.is_err() {
+            return false;
+        }
+
+        let Some(mut point) = self.leaf.decompress() else {
+            return false;
+        };
+        let mut hash = hidden_leaf_digest::<D>(&self.leaf);
+        let mut index = self.index;
+        for (height, (sibling_point, sibling_hash)) in self.siblings.iter().enumerate() {
+            let Some(sibling) = sibling_point.decompress() else {
+                return false;
+            };
+            point += sibling;
+            let compressed = point.compress();
+            hash = if (index & 1) == 0 {
+                hidden_branch_digest::<D>(height + 1, &compressed, &hash, sibling_hash)
+            } else {
+                hidden_branch_digest::<D>(height + 1, &compressed, sibling_hash, &hash)
+            };
+            index >>= 1;
+        }
+        point.compress() == root.point && hash == root.hash
+    }
+
+    // Lets the owner check that their leaf commits to the balance they expect
+    pub fn verify_opening(&self, value: u64, blinding: &Scalar) -> bool {
+        pedersen_commit(value, blinding).compress() == self.leaf
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pedersen_tree() {
+        let mut rng = rand::thread_rng();
+        let values = vec![5, 10, 0, 7, 3];
+        let tree = PedersenTree::<Sha256>::new(values.clone(), &mut rng).unwrap();
+        let root = tree.commit();
+
+        for (i, value) in values.iter().enumerate() {
+            let proof = tree.prove(i);
+            assert!(proof.verify(&root), "Failed position {}", i);
+            let (opened, blinding) = tree.opening(i);
+            assert_eq!(opened, *value);
+            assert!(proof.verify_opening(opened, &blinding));
+            assert!(!proof.verify_opening(opened + 1, &blinding));
+        }
+
+        let (total, blinding) = tree.open_root();
+        assert_eq!(total, 25);
+        assert!(root.verify_opening(total, &blinding));
+        assert!(!root.verify_opening(total - 1, &blinding));
+
+        // Swapping in a commitment to a different amount breaks the path
+        let mut tampered = tree.prove(1);
+        tampered.leaf = pedersen_commit(1, &Scalar::one()).compress();
+        assert!(!tampered.verify(&root));
+
+        // A range proof made for another leaf does not carry over
+        let mut swapped = tree.prove(1);
+        swapped.range_proof = tree.prove(2).range_proof;
+        assert!(!swapped.verify(&root));
+
+        // Same leaf and path, claimed at a position past the tree
+        let mut aliased = tree.prove(1);
+        aliased.index |= 1 << aliased.siblings.len();
+        assert!(!aliased.verify(&root));
+
+        assert!(matches!(
+            PedersenTree::<Sha256>::new(vec![u64::MAX, 1], &mut rng),
+            Err(SumOverflow)
+        ));
+    }
+}
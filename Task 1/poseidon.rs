@@ -0,0 +1,110 @@
+// Poseidon over the BLS12-381 scalar field, for trees whose roots are checked
+// inside zk circuits where SHA-256 costs tens of thousands of constraints per
+// hash. It is a `TreeDigest` like any other, so `Node::<Poseidon>` picks it at
+// construction. The bytes hashed are packed big-endian into 31-byte field
+// elements after their length, then absorbed into a sponge with the parameters
+// from `poseidon_config`; a circuit hashes the same elements with the
+// ark-crypto-primitives sponge gadget.
+
+use alloc::vec::Vec;
+use std::sync::OnceLock;
+
+use ark_bls12_381::Fr;
+use ark_crypto_primitives::sponge::poseidon::{find_poseidon_ark_and_mds, PoseidonConfig, PoseidonSponge};
+use ark_crypto_primitives::sponge::CryptographicSponge;
+use ark_ff::{BigInteger, PrimeField};
+use sha2::digest::consts::U32;
+use sha2::digest::{FixedOutput, HashMarker, Output, OutputSizeUser, Update};
+
+// Width 3 with the round numbers recommended for a 255-bit field and x^5
+const RATE: usize = 2;
+const FULL_ROUNDS: usize = 8;
+const PARTIAL_ROUNDS: usize = 57;
+const ALPHA: u64 = 5;
+
+// The largest number of bytes that always fits below the field modulus
+const CHUNK: usize = 31;
+
+pub fn poseidon_config() -> &'static PoseidonConfig<Fr> {
+    static CONFIG: OnceLock<PoseidonConfig<Fr>> = OnceLock::new();
+    CONFIG.get_or_init(|| {
+        let (ark, mds) = find_poseidon_ark_and_mds::<Fr>(
+            Fr::MODULUS_BIT_SIZE as u64,
+            RATE,
+            FULL_ROUNDS as u64,
+            PARTIAL_ROUNDS as u64,
+            0,
+        );
+        PoseidonConfig::new(FULL_ROUNDS, PARTIAL_ROUNDS, ALPHA, mds, ark, RATE, 1)
+    })
+}
+
+// The field elements a message is absorbed as
+pub fn pack_bytes(bytes: &[u8]) -> Vec<Fr> {
+    let mut elements = Vec::with_capacity(1 + bytes.len().div_ceil(CHUNK));
+    // Without the length, trailing zero bytes would vanish into the last element
+    elements.push(Fr::from(bytes.len() as u64));
+    elements.extend(bytes.chunks(CHUNK).map(Fr::from_be_bytes_mod_order));
+    elements
+}
+
+// Buffers its input, since packing needs the length up front
+#[derive(Clone, Default)]
+pub struct Poseidon {
+    buffer: Vec<u8>,
+}
+
+impl HashMarker for Poseidon {}
+
+impl OutputSizeUser for Poseidon {
+    type OutputSize = U32;
+}
+
+impl Update for Poseidon {
+    fn update(&mut self, data: &[u8]) {
+        self.buffer.extend_from_slice(data);
+    }
+}
+
+impl FixedOutput for Poseidon {
+    fn finalize_into(self, out: &mut Output<Self>) {
+        let mut sponge = PoseidonSponge::new(poseidon_config());
+        sponge.absorb(&pack_bytes(&self.buffer));
+        let hash: Fr = sponge.squeeze_field_elements(1)[0];
+        out.copy_from_slice(&hash.into_bigint().to_bytes_be());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::vec;
+
+    use super::*;
+    use crate::{ExclusiveAllotmentProof, MerkleTree, Node, Sha256};
+
+    #[test]
+    fn test_poseidon_tree() {
+        let tree_root = Node::<Poseidon>::new(vec![3, 1, 4, 1, 5, 9, 2, 6]);
+        let root_commitment = tree_root.commit();
+        assert_eq!(root_commitment.sum, 31);
+        assert_ne!(
+            root_commitment.hash,
+            Node::<Sha256>::new(vec![3, 1, 4, 1, 5, 9, 2, 6]).commit().hash
+        );
+        assert!(root_commitment == Node::<Poseidon>::new(vec![3, 1, 4, 1, 5, 9, 2, 6]).commit());
+        for i in 0..8 {
+            assert!(tree_root.prove(i).verify(&root_commitment), "Failed position {}", i);
+        }
+        let mut proof = tree_root.prove(5);
+        proof.node.sum = 8;
+        assert!(!proof.verify(&root_commitment));
+
+        // Every output is a canonical field element, so it can be a circuit input as is
+        let hash = Fr::from_be_bytes_mod_order(&root_commitment.hash);
+        assert_eq!(hash.into_bigint().to_bytes_be(), root_commitment.hash);
+
+        assert_eq!(pack_bytes(&[0; 31]).len(), 2);
+        assert_eq!(pack_bytes(&[0; 32]).len(), 3);
+        assert_ne!(pack_bytes(&[1]), pack_bytes(&[1, 0]));
+    }
+}
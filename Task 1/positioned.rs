@@ -0,0 +1,199 @@
+// Leaves that commit to their own position and to the number of leaves in the
+// tree, next to the balance and blinding. A proof then only verifies for the slot
+// and tree size it was issued for: it cannot be replayed at another position, two
+// equal balances never share a leaf hash, and a path cut short cannot pass a
+// subtree off as a smaller tree. Branches are V2 branches, and the leaves get
+// their own tag, so roots of plain trees are unaffected and still verify as before.
+//
+// Leaf: tag (u8) | version (u8) | position (u64) | leaf count (u64) | value (u64)
+//       | salt (32 bytes) | user id (32 bytes), the last two only when present
+
+use alloc::vec::Vec;
+
+use subtle::ConstantTimeEq;
+
+use super::{
+    hash_bytes, Blinding, BuildError, Commitment, Encoding, Entry, MerkleTree, Node, Proof, Sha256, SumCommitment,
+    SumOverflow, TreeDigest, VerifyError, V2_VERSION,
+};
+
+const POSITIONED_LEAF_TAG: u8 = 0x07;
+
+fn positioned_leaf_digest<D: TreeDigest>(
+    position: u64,
+    count: u64,
+    value: u64,
+    blinding: Option<&Blinding>,
+) -> [u8; 32] {
+    let (salt, user_id): (&[u8], &[u8]) = match blinding {
+        Some(Blinding { salt, user_id }) => (salt, user_id.as_ref().map_or(&[], |id| id.as_slice())),
+        None => (&[], &[]),
+    };
+    let serialized = [
+        [POSITIONED_LEAF_TAG, V2_VERSION].as_slice(),
+        &position.to_be_bytes(),
+        &count.to_be_bytes(),
+        &value.to_be_bytes(),
+        salt,
+        user_id,
+    ]
+    .concat();
+    #[cfg(feature = "zeroize")]
+    let serialized = zeroize::Zeroizing::new(serialized);
+    hash_bytes::<D>(&serialized)
+}
+
+pub struct PositionedTree<D = Sha256> {
+    tree: Node<D>,
+}
+
+impl<D: TreeDigest> PositionedTree<D> {
+    pub fn try_new(values: Vec<u64>) -> Result<Self, BuildError> {
+        Self::try_new_blinded(values.into_iter().map(|value| (value, None)).collect())
+    }
+
+    pub fn try_new_blinded(leaves: Vec<(u64, Option<Blinding>)>) -> Result<Self, BuildError> {
+        let count = leaves.len() as u64;
+        let leaves = leaves
+            .into_iter()
+            .enumerate()
+            .map(|(position, (value, blinding))| Entry::Leaf {
+                value,
+                commitment: positioned_leaf_digest::<D>(position as u64, count, value, blinding.as_ref()),
+                encoding: Encoding::V2,
+                blinding,
+            });
+        Ok(Self {
+            tree: Node::try_from_leaves(leaves)?,
+        })
+    }
+
+    pub fn len(&self) -> usize {
+        1 << self.tree.height()
+    }
+
+    // A tree always has at least one leaf
+    pub fn is_empty(&self) -> bool {
+        false
+    }
+
+    pub fn commit(&self) -> Commitment<D> {
+        self.tree.commit()
+    }
+
+    pub fn prove(&self, position: usize) -> PositionedProof<D> {
+        PositionedProof {
+            path: self.tree.prove(position),
+        }
+    }
+
+    // Keeps the leaf's blinding, like `Node::update`
+    pub fn update(&mut self, position: usize, new_value: u64) -> Result<(), SumOverflow> {
+        assert!(position < self.len(), "position {} out of range", position);
+        let old_value = self.tree.leaf(position).amount();
+        (self.tree.amount() - old_value)
+            .checked_add(new_value)
+            .ok_or(SumOverflow)?;
+        let count = self.len() as u64;
+        self.tree.update_path(position, |leaf| {
+            if let Entry::Leaf {
+                value,
+                commitment,
+                blinding,
+                ..
+            } = leaf
+            {
+                *value = new_value;
+                *commitment = positioned_leaf_digest::<D>(position as u64, count, new_value, blinding.as_ref());
+            }
+        });
+        Ok(())
+    }
+}
+
+pub struct PositionedProof<D = Sha256> {
+    // Its node is the positioned leaf, so it does not verify as a plain proof
+    pub path: Proof<D>,
+}
+
+impl<D: TreeDigest> PositionedProof<D> {
+    pub fn position(&self) -> usize {
+        self.path.index
+    }
+
+    pub fn verify(&self, root_commitment: &Commitment<D>) -> bool {
+        self.verify_detailed(root_commitment).is_ok()
+    }
+
+    // The position and leaf count hashed into the leaf are the ones the path claims
+    pub fn verify_detailed(&self, root_commitment: &Commitment<D>) -> Result<(), VerifyError> {
+        self.path.verify_path(root_commitment, |node| {
+            let Some(count) = 1u64.checked_shl(self.path.siblings.len() as u32) else {
+                return false;
+            };
+            let leaf =
+                positioned_leaf_digest::<D>(self.path.index as u64, count, node.sum, self.path.blinding.as_ref());
+            node.hash.ct_eq(&leaf).into()
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::vec;
+
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    use super::*;
+    use crate::ExclusiveAllotmentProof;
+
+    #[test]
+    fn test_positioned_tree() {
+        let mut tree = PositionedTree::<Sha256>::try_new(vec![5, 5, 5, 5]).unwrap();
+        let root_commitment = tree.commit();
+        assert_eq!((tree.len(), root_commitment.sum), (4, 20));
+        assert!(!tree.is_empty());
+        for i in 0..4 {
+            let proof = tree.prove(i);
+            assert_eq!(proof.position(), i);
+            assert!(proof.verify(&root_commitment), "Failed position {}", i);
+            assert!(!proof.path.verify(&root_commitment));
+        }
+        // Equal balances still get their own leaf hashes
+        assert!(tree.prove(0).path.node != tree.prove(2).path.node);
+
+        // Claiming another slot with the same siblings fails at the leaf
+        let mut moved = tree.prove(1);
+        moved.path.index = 0;
+        assert!(!moved.verify(&root_commitment));
+
+        // Nor does a path cut short verify against the subtree it now ends at
+        let mut truncated = tree.prove(1);
+        truncated.path.siblings.pop();
+        let subtree: Commitment<Sha256> = tree.tree.descendant(1, 0).into();
+        assert!(!truncated.verify(&subtree));
+        let plain: Node<Sha256> = Node::new(vec![5, 5, 5, 5]);
+        let mut plain_truncated = plain.prove(1);
+        plain_truncated.siblings.pop();
+        assert!(plain_truncated.verify(&plain.descendant(1, 0).into()));
+
+        // Updates keep the position and leaf count in the leaf
+        tree.update(3, 9).unwrap();
+        assert_eq!(tree.commit().sum, 24);
+        assert!(tree.prove(3).verify(&tree.commit()));
+        assert!(!tree.prove(3).verify(&root_commitment));
+        assert_eq!(tree.update(0, u64::MAX), Err(SumOverflow));
+
+        let mut rng = StdRng::seed_from_u64(7);
+        let blinded: Vec<(u64, Option<Blinding>)> = (1..=8)
+            .map(|value| (value, Some(Blinding::random(&mut rng, Some([value as u8; 32])))))
+            .collect();
+        let tree = PositionedTree::<Sha256>::try_new_blinded(blinded).unwrap();
+        assert!((0..8).all(|i| tree.prove(i).verify(&tree.commit())));
+        assert_eq!(
+            PositionedTree::<Sha256>::try_new(vec![1, 2, 3]).err(),
+            Some(BuildError::NotPowerOfTwo { len: 3 })
+        );
+    }
+}
@@ -0,0 +1,112 @@
+// Python module for driving trees from notebooks. Roots and proofs are handed
+// out in the canonical byte encoding from codec.rs, so they can be stored or
+// passed to the other bindings unchanged.
+//
+//   tree = merkle_sum_tree.SumTree([10, 25, 0, 7])
+//   merkle_sum_tree.verify(tree.prove(1), tree.commit())
+
+use alloc::borrow::Cow;
+use alloc::string::ToString;
+use alloc::vec::Vec;
+
+use pyo3::exceptions::{PyIndexError, PyOverflowError, PyValueError};
+use pyo3::prelude::*;
+
+use super::{BuildError, Commitment, DecodeError, ExclusiveAllotmentProof, MerkleTree, Node, Proof, SumCommitment};
+
+fn decode_error(err: DecodeError) -> PyErr {
+    PyValueError::new_err(err.to_string())
+}
+
+fn build_error(err: BuildError) -> PyErr {
+    match err {
+        BuildError::SumOverflow => PyOverflowError::new_err(err.to_string()),
+        _ => PyValueError::new_err(err.to_string()),
+    }
+}
+
+#[pyclass(name = "SumTree", frozen)]
+struct PySumTree(Node);
+
+#[pymethods]
+impl PySumTree {
+    // The number of values must be a power of two, see `padded` otherwise
+    #[new]
+    fn new(values: Vec<u64>) -> PyResult<Self> {
+        Node::try_new(values).map(PySumTree).map_err(build_error)
+    }
+
+    // Fills up with zero leaves to the next power of two
+    #[staticmethod]
+    fn padded(values: Vec<u64>) -> PyResult<Self> {
+        Node::try_new_padded(values).map(PySumTree).map_err(build_error)
+    }
+
+    fn __len__(&self) -> usize {
+        1 << self.0.height()
+    }
+
+    #[getter]
+    fn sum(&self) -> u64 {
+        self.0.amount()
+    }
+
+    fn commit(&self) -> Cow<'static, [u8]> {
+        Cow::Owned(self.0.commit().to_bytes())
+    }
+
+    fn prove(&self, position: usize) -> PyResult<Cow<'static, [u8]>> {
+        if position >> self.0.height() != 0 {
+            return Err(PyIndexError::new_err("position out of range"));
+        }
+        Ok(Cow::Owned(self.0.prove(position).to_bytes()))
+    }
+}
+
+#[pyfunction]
+fn verify(proof: &[u8], root: &[u8]) -> PyResult<bool> {
+    let proof: Proof = Proof::from_bytes(proof).map_err(decode_error)?;
+    let root_commitment = Commitment::from_bytes(root).map_err(decode_error)?;
+    Ok(proof.verify(&root_commitment))
+}
+
+// Position and balance of an encoded proof
+#[pyfunction]
+fn decode_proof(proof: &[u8]) -> PyResult<(usize, u64)> {
+    let proof: Proof = Proof::from_bytes(proof).map_err(decode_error)?;
+    Ok((proof.position(), proof.node.amount()))
+}
+
+#[pymodule]
+fn merkle_sum_tree(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add_class::<PySumTree>()?;
+    m.add_function(wrap_pyfunction!(verify, m)?)?;
+    m.add_function(wrap_pyfunction!(decode_proof, m)?)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::vec;
+
+    use super::*;
+
+    #[test]
+    fn test_python_api() {
+        let tree = PySumTree::padded(vec![10, 25, 0]).unwrap();
+        assert_eq!((tree.__len__(), tree.sum()), (4, 35));
+        let root = tree.commit();
+        for position in 0..4 {
+            assert!(verify(&tree.prove(position).unwrap(), &root).unwrap());
+        }
+        assert_eq!(decode_proof(&tree.prove(1).unwrap()).unwrap(), (1, 25));
+
+        let other = PySumTree::new(vec![10, 25, 0, 1]).unwrap();
+        assert!(!verify(&tree.prove(0).unwrap(), &other.commit()).unwrap());
+
+        assert!(PySumTree::new(vec![1, 2, 3]).is_err());
+        assert!(PySumTree::new(vec![u64::MAX, 1]).is_err());
+        assert!(tree.prove(4).is_err());
+        assert!(verify(&root, &root).is_err());
+    }
+}
@@ -0,0 +1,187 @@
+// Constraints that check an inclusion proof inside a SNARK circuit, so a user can
+// show their balance is in the published root (and, with the circuit below, that
+// it is under a bound) without revealing the proof itself. Only SHA-256 trees are
+// supported, as that is the hash with a gadget. The depth, encoding and kind of
+// blinding are part of the circuit's shape; the index, balance, blinding and
+// siblings stay private.
+
+use alloc::vec::Vec;
+use core::cmp::Ordering;
+
+use ark_crypto_primitives::crh::sha256::constraints::{DigestVar, Sha256Gadget};
+use ark_ff::{PrimeField, ToConstraintField};
+use ark_r1cs_std::alloc::AllocVar;
+use ark_r1cs_std::boolean::Boolean;
+use ark_r1cs_std::eq::EqGadget;
+use ark_r1cs_std::fields::fp::FpVar;
+use ark_r1cs_std::select::CondSelectGadget;
+use ark_r1cs_std::uint64::UInt64;
+use ark_r1cs_std::uint8::UInt8;
+use ark_relations::r1cs::{ConstraintSynthesizer, ConstraintSystemRef, SynthesisError};
+
+use super::{Commitment, Encoding, Proof, Sha256, BRANCH_TAG, LEAF_TAG, V2_VERSION};
+
+fn tag(encoding: Encoding, tag: u8) -> Vec<u8> {
+    match encoding {
+        Encoding::V0 => Vec::new(),
+        Encoding::V1 => alloc::vec![tag],
+        Encoding::V2 => alloc::vec![tag, V2_VERSION],
+    }
+}
+
+fn constant_bytes<F: PrimeField>(bytes: &[u8]) -> Vec<UInt8<F>> {
+    bytes.iter().map(|&byte| UInt8::constant(byte)).collect()
+}
+
+// Enforces that `proof` leads to the root with hash `root_hash` and sum `root_sum`,
+// and returns the leaf balance for further statements about it
+pub fn enforce_proof<F: PrimeField>(
+    cs: ConstraintSystemRef<F>,
+    proof: &Proof<Sha256>,
+    root_hash: &[UInt8<F>],
+    root_sum: &FpVar<F>,
+) -> Result<UInt64<F>, SynthesisError> {
+    let value = UInt64::new_witness(cs.clone(), || Ok(proof.node.sum))?;
+    let mut leaf = constant_bytes(&tag(proof.encoding, LEAF_TAG));
+    leaf.extend(value.to_bytes_be()?);
+    if let Some(blinding) = &proof.blinding {
+        leaf.extend(UInt8::new_witness_vec(cs.clone(), &blinding.salt)?);
+        if let Some(user_id) = &blinding.user_id {
+            leaf.extend(UInt8::new_witness_vec(cs.clone(), user_id)?);
+        }
+    }
+    let mut hash = Sha256Gadget::digest(&leaf)?;
+    let (mut sum, mut native_sum) = (value.clone(), proof.node.sum);
+
+    for (level, sibling) in proof.siblings.iter().enumerate() {
+        let height = level + 1;
+        let is_right = Boolean::new_witness(cs.clone(), || Ok((proof.index >> level) & 1 == 1))?;
+        let sibling_hash = DigestVar::new_witness(cs.clone(), || Ok(sibling.hash.to_vec()))?;
+        let sibling_sum = UInt64::new_witness(cs.clone(), || Ok(sibling.sum))?;
+
+        // Both sums are below 2^64, so equality in the field rules out a wrapped sum
+        let parent_sum = UInt64::new_witness(cs.clone(), || Ok(native_sum.wrapping_add(sibling.sum)))?;
+        parent_sum
+            .to_fp()?
+            .enforce_equal(&(sum.to_fp()? + sibling_sum.to_fp()?))?;
+
+        let left = DigestVar::conditionally_select(&is_right, &sibling_hash, &hash)?;
+        let right = DigestVar::conditionally_select(&is_right, &hash, &sibling_hash)?;
+        let height_bytes = match proof.encoding {
+            Encoding::V0 | Encoding::V1 => height.to_be_bytes().to_vec(),
+            Encoding::V2 => (height as u64).to_be_bytes().to_vec(),
+        };
+        let mut branch = constant_bytes(&tag(proof.encoding, BRANCH_TAG));
+        branch.extend(constant_bytes(&height_bytes));
+        branch.extend(parent_sum.to_bytes_be()?);
+        branch.extend(left.0);
+        branch.extend(right.0);
+        hash = Sha256Gadget::digest(&branch)?;
+        sum = parent_sum;
+        native_sum = native_sum.wrapping_add(sibling.sum);
+    }
+
+    hash.0[..].enforce_equal(root_hash)?;
+    sum.to_fp()?.enforce_equal(root_sum)?;
+    Ok(value)
+}
+
+// Proves a balance is included in `root_commitment` and strictly below `bound`.
+// The root and the bound are the public inputs, in the order of `public_inputs`.
+pub struct AllotmentCircuit {
+    pub proof: Proof<Sha256>,
+    pub root_commitment: Commitment<Sha256>,
+    pub bound: u64,
+}
+
+impl AllotmentCircuit {
+    pub fn public_inputs<F: PrimeField>(&self) -> Vec<F> {
+        let mut inputs: Vec<F> = self.root_commitment.hash.to_field_elements().unwrap();
+        inputs.push(F::from(self.root_commitment.sum));
+        inputs.push(F::from(self.bound));
+        inputs
+    }
+}
+
+impl<F: PrimeField> ConstraintSynthesizer<F> for AllotmentCircuit {
+    fn generate_constraints(self, cs: ConstraintSystemRef<F>) -> Result<(), SynthesisError> {
+        let root_hash = UInt8::new_input_vec(cs.clone(), &self.root_commitment.hash)?;
+        let root_sum = FpVar::new_input(cs.clone(), || Ok(F::from(self.root_commitment.sum)))?;
+        let bound = FpVar::new_input(cs.clone(), || Ok(F::from(self.bound)))?;
+        let value = enforce_proof(cs, &self.proof, &root_hash, &root_sum)?;
+        value.to_fp()?.enforce_cmp(&bound, Ordering::Less, false)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::vec;
+
+    use ark_bls12_381::Fr;
+    use ark_relations::r1cs::ConstraintSystem;
+
+    use super::*;
+    use crate::builder::TreeBuilder;
+    use crate::{Blinding, MerkleTree, Node};
+
+    fn satisfied(circuit: AllotmentCircuit) -> bool {
+        let cs = ConstraintSystem::<Fr>::new_ref();
+        let inputs = circuit.public_inputs::<Fr>();
+        circuit.generate_constraints(cs.clone()).unwrap();
+        // The public inputs follow the constant one
+        assert_eq!(cs.borrow().unwrap().instance_assignment[1..], inputs[..]);
+        cs.is_satisfied().unwrap()
+    }
+
+    #[test]
+    fn test_allotment_circuit() {
+        let tree_root: Node = Node::new(vec![3, 14, 15, 92]);
+        let root_commitment = tree_root.commit();
+        for i in 0..4 {
+            let circuit = AllotmentCircuit {
+                proof: tree_root.prove(i),
+                root_commitment,
+                bound: 100,
+            };
+            assert!(satisfied(circuit), "Failed position {}", i);
+        }
+
+        let circuit = |proof, bound| AllotmentCircuit {
+            proof,
+            root_commitment,
+            bound,
+        };
+        // The bound is strict
+        assert!(!satisfied(circuit(tree_root.prove(2), 15)));
+        assert!(satisfied(circuit(tree_root.prove(2), 16)));
+
+        let mut tampered = tree_root.prove(1);
+        tampered.siblings[1].hash[0] ^= 1;
+        assert!(!satisfied(circuit(tampered, 100)));
+        let mut moved = tree_root.prove(1);
+        moved.index = 0;
+        assert!(!satisfied(circuit(moved, 100)));
+        let mut inflated = tree_root.prove(1);
+        inflated.node.sum = 20;
+        assert!(!satisfied(circuit(inflated, 100)));
+
+        // A sibling whose sum wraps the total around is rejected
+        let mut wrapped = tree_root.prove(0);
+        wrapped.siblings[0].sum = u64::MAX - 2;
+        assert!(!satisfied(circuit(wrapped, 100)));
+
+        let mut rng = rand::thread_rng();
+        let blinded = TreeBuilder::<Sha256>::new()
+            .encoding(Encoding::V1)
+            .blinded_leaf(7, Blinding::random(&mut rng, Some([9; 32])))
+            .leaf(1)
+            .build()
+            .unwrap();
+        let circuit = AllotmentCircuit {
+            proof: blinded.prove(0),
+            root_commitment: blinded.commit(),
+            bound: 8,
+        };
+        assert!(satisfied(circuit));
+    }
+}
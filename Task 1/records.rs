@@ -0,0 +1,171 @@
+// Leaves committing to a whole record rather than a bare balance, e.g. an
+// (asset id, amount) pair or balances in several currencies. Each leaf hashes the
+// record's canonical bytes next to the amount it contributes, while branches and
+// sums are the usual ones over the amounts alone.
+
+use alloc::vec;
+use alloc::vec::Vec;
+use core::marker::PhantomData;
+
+use super::{
+    hash_bytes, BuildError, Commitment, Encoding, Entry, MerkleTree, Node, Proof, Sha256, TreeDigest, VerifyError,
+    V2_VERSION,
+};
+
+// Kept apart from plain leaves, whose blinding bytes could otherwise pass for a record
+const RECORD_TAG: u8 = 0x06;
+
+pub trait SummableLeaf {
+    // The part of the record that is summed up the tree
+    fn amount(&self) -> u64;
+
+    // The rest of the record; the amount is hashed anyway. Must not depend on the platform.
+    fn write_canonical(&self, bytes: &mut Vec<u8>);
+}
+
+// An asset identifier and an amount of that asset
+impl SummableLeaf for (u32, u64) {
+    fn amount(&self) -> u64 {
+        self.1
+    }
+
+    fn write_canonical(&self, bytes: &mut Vec<u8>) {
+        bytes.extend_from_slice(&self.0.to_be_bytes());
+    }
+}
+
+fn record_digest<D: TreeDigest, L: SummableLeaf>(record: &L) -> [u8; 32] {
+    let mut bytes = vec![RECORD_TAG, V2_VERSION];
+    bytes.extend_from_slice(&record.amount().to_be_bytes());
+    record.write_canonical(&mut bytes);
+    #[cfg(feature = "zeroize")]
+    let bytes = zeroize::Zeroizing::new(bytes);
+    hash_bytes::<D>(&bytes)
+}
+
+// The records are kept next to the tree, since its leaves only hold their hashes
+pub struct RecordTree<L, D = Sha256> {
+    records: Vec<L>,
+    tree: Node<D>,
+}
+
+impl<L: SummableLeaf + Clone, D: TreeDigest> RecordTree<L, D> {
+    pub fn try_new(records: Vec<L>) -> Result<Self, BuildError> {
+        let leaves = records.iter().map(|record| Entry::Leaf {
+            value: record.amount(),
+            commitment: record_digest::<D, L>(record),
+            encoding: Encoding::V2,
+            blinding: None,
+        });
+        let tree = Node::try_from_leaves(leaves)?;
+        Ok(Self { records, tree })
+    }
+
+    pub fn records(&self) -> &[L] {
+        &self.records
+    }
+
+    pub fn commit(&self) -> Commitment<D> {
+        self.tree.commit()
+    }
+
+    pub fn prove(&self, position: usize) -> RecordProof<L, D> {
+        RecordProof {
+            record: self.records[position].clone(),
+            path: self.tree.prove(position),
+            hasher: PhantomData,
+        }
+    }
+}
+
+pub struct RecordProof<L, D = Sha256> {
+    pub record: L,
+    // Its node is the record's amount and hash
+    pub path: Proof<D>,
+    hasher: PhantomData<fn() -> D>,
+}
+
+impl<L: SummableLeaf, D: TreeDigest> RecordProof<L, D> {
+    pub fn verify(&self, root_commitment: &Commitment<D>) -> bool {
+        self.verify_detailed(root_commitment).is_ok()
+    }
+
+    pub fn verify_detailed(&self, root_commitment: &Commitment<D>) -> Result<(), VerifyError> {
+        self.path.verify_path(root_commitment, |node| {
+            node.sum == self.record.amount() && node.hash == record_digest::<D, L>(&self.record)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ExclusiveAllotmentProof;
+
+    // Balances in three currencies, with the tree summing their value in a reference unit
+    #[derive(Clone)]
+    struct Wallet {
+        balances: [u64; 3],
+        valuation: u64,
+    }
+
+    impl SummableLeaf for Wallet {
+        fn amount(&self) -> u64 {
+            self.valuation
+        }
+
+        fn write_canonical(&self, bytes: &mut Vec<u8>) {
+            for balance in self.balances {
+                bytes.extend_from_slice(&balance.to_be_bytes());
+            }
+        }
+    }
+
+    #[test]
+    fn test_record_tree() {
+        let records = vec![(1u32, 10u64), (2, 5), (1, 7), (3, 0)];
+        let tree = RecordTree::<_, Sha256>::try_new(records.clone()).unwrap();
+        let root_commitment = tree.commit();
+        assert_eq!(root_commitment.sum, 22);
+        assert_eq!(tree.records(), records.as_slice());
+        for (i, record) in records.iter().enumerate() {
+            let proof = tree.prove(i);
+            assert_eq!(proof.record, *record);
+            assert!(proof.verify(&root_commitment), "Failed position {}", i);
+            // Record leaves are not plain balance leaves
+            assert!(!proof.path.verify(&root_commitment));
+        }
+
+        // Changing the asset keeps the amount but not the hash
+        let mut moved = tree.prove(0);
+        moved.record.0 = 2;
+        assert_eq!(
+            moved.verify_detailed(&root_commitment),
+            Err(VerifyError::HashMismatch { height: 0 })
+        );
+        let mut inflated = tree.prove(1);
+        inflated.record.1 = 6;
+        assert!(!inflated.verify(&root_commitment));
+
+        let wallets = vec![
+            Wallet {
+                balances: [1, 0, 2],
+                valuation: 30,
+            },
+            Wallet {
+                balances: [0, 4, 0],
+                valuation: 12,
+            },
+        ];
+        let tree = RecordTree::<_, Sha256>::try_new(wallets).unwrap();
+        assert_eq!(tree.commit().sum, 42);
+        let proof = tree.prove(1);
+        assert_eq!(proof.record.balances, [0, 4, 0]);
+        assert!(proof.verify(&tree.commit()));
+
+        assert_eq!(
+            RecordTree::<(u32, u64), Sha256>::try_new(vec![(1, 1); 3]).err(),
+            Some(BuildError::NotPowerOfTwo { len: 3 })
+        );
+    }
+}
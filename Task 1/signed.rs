@@ -0,0 +1,208 @@
+// Sum tree over signed balances, for ledgers with debit entries. Leaves may be
+// negative, but no branch sum may drop below zero, so a subtree can never hide
+// debts larger than its credits and the root is an ordinary `Commitment`.
+// Construction either rejects negative leaves or accepts them as debits and
+// reports where they are.
+
+use alloc::vec;
+use alloc::vec::Vec;
+use core::fmt;
+use core::marker::PhantomData;
+
+use super::{branch_digest, hash_bytes, Commitment, Encoding, Sha256, TreeDigest, V2_VERSION};
+
+// Signed leaves get their own tag so an i64 can never pass for the u64 with the same bytes
+const SIGNED_LEAF_TAG: u8 = 0x05;
+
+fn signed_leaf_digest<D: TreeDigest>(value: i64) -> [u8; 32] {
+    hash_bytes::<D>(&[[SIGNED_LEAF_TAG, V2_VERSION].as_slice(), &value.to_be_bytes()].concat())
+}
+
+// Branch sums are never negative, so branches hash like V2 branches
+fn signed_branch<D: TreeDigest>(
+    height: usize,
+    left: (i64, [u8; 32]),
+    right: (i64, [u8; 32]),
+) -> Option<(i64, [u8; 32])> {
+    let sum = left.0.checked_add(right.0).filter(|sum| *sum >= 0)?;
+    Some((
+        sum,
+        branch_digest::<D>(Encoding::V2, height, sum as u64, &left.1, &right.1),
+    ))
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum SignedPolicy {
+    RejectNegative,
+    // Negative leaves are allowed as long as every branch sum stays non-negative
+    AllowDebits,
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum SignedError {
+    NegativeLeaf { position: usize },
+    // The branch at `index` among those of height `height` sums below zero or overflows
+    InvalidSum { height: usize, index: usize },
+    NotPowerOfTwo { len: usize },
+}
+
+impl fmt::Display for SignedError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SignedError::NegativeLeaf { position } => write!(f, "leaf {} is negative", position),
+            SignedError::InvalidSum { height, index } => {
+                write!(f, "branch {} at height {} is negative or overflows", index, height)
+            }
+            SignedError::NotPowerOfTwo { len } => write!(f, "{} leaves is not a power of two", len),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for SignedError {}
+
+pub struct SignedTree<D = Sha256> {
+    // Every level from the leaves up to the root, as (sum, hash)
+    levels: Vec<Vec<(i64, [u8; 32])>>,
+    debits: Vec<usize>,
+    hasher: PhantomData<fn() -> D>,
+}
+
+impl<D: TreeDigest> SignedTree<D> {
+    pub fn try_new(values: Vec<i64>, policy: SignedPolicy) -> Result<Self, SignedError> {
+        if !values.len().is_power_of_two() {
+            return Err(SignedError::NotPowerOfTwo { len: values.len() });
+        }
+        let debits: Vec<usize> = (0..values.len()).filter(|&i| values[i] < 0).collect();
+        if let (SignedPolicy::RejectNegative, Some(&position)) = (policy, debits.first()) {
+            return Err(SignedError::NegativeLeaf { position });
+        }
+
+        let leaves = values
+            .iter()
+            .map(|&value| (value, signed_leaf_digest::<D>(value)))
+            .collect();
+        let mut levels: Vec<Vec<(i64, [u8; 32])>> = vec![leaves];
+        while levels.last().unwrap().len() > 1 {
+            let height = levels.len();
+            let below = levels.last().unwrap();
+            let level = below
+                .chunks(2)
+                .enumerate()
+                .map(|(index, pair)| {
+                    signed_branch::<D>(height, pair[0], pair[1]).ok_or(SignedError::InvalidSum { height, index })
+                })
+                .collect::<Result<Vec<_>, _>>()?;
+            levels.push(level);
+        }
+        Ok(Self {
+            levels,
+            debits,
+            hasher: PhantomData,
+        })
+    }
+
+    // Positions of the negative leaves
+    pub fn debits(&self) -> &[usize] {
+        &self.debits
+    }
+
+    pub fn commit(&self) -> Commitment<D> {
+        let (sum, hash) = self.levels.last().unwrap()[0];
+        Commitment::new(sum as u64, hash)
+    }
+
+    pub fn prove(&self, position: usize) -> SignedProof<D> {
+        let height = self.levels.len() - 1;
+        assert!(position >> height == 0, "position {} out of range", position);
+        SignedProof {
+            value: self.levels[0][position].0,
+            siblings: (0..height)
+                .map(|level| self.levels[level][(position >> level) ^ 1])
+                .collect(),
+            index: position,
+            hasher: PhantomData,
+        }
+    }
+}
+
+pub struct SignedProof<D = Sha256> {
+    pub value: i64,
+    // (sum, hash) of each sibling from the leaf up; leaf siblings may be negative
+    pub siblings: Vec<(i64, [u8; 32])>,
+    pub index: usize,
+    hasher: PhantomData<fn() -> D>,
+}
+
+impl<D: TreeDigest> SignedProof<D> {
+    // Fails if any sum on the path, the root's included, is negative or overflows
+    pub fn verify(&self, root_commitment: &Commitment<D>) -> bool {
+        if self.index.checked_shr(self.siblings.len() as u32).unwrap_or(0) != 0 {
+            return false;
+        }
+        let mut node = (self.value, signed_leaf_digest::<D>(self.value));
+        for (level, sibling) in self.siblings.iter().enumerate() {
+            let (left, right) = if (self.index >> level) & 1 == 0 {
+                (node, *sibling)
+            } else {
+                (*sibling, node)
+            };
+            match signed_branch::<D>(level + 1, left, right) {
+                Some(branch) => node = branch,
+                None => return false,
+            }
+        }
+        node.0 >= 0 && node.0 as u64 == root_commitment.sum && node.1 == root_commitment.hash
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{MerkleTree, Node};
+
+    #[test]
+    fn test_signed_tree() {
+        let values = vec![50, -20, 10, 0, -5, 30, 7, -7];
+        let tree = SignedTree::<Sha256>::try_new(values.clone(), SignedPolicy::AllowDebits).unwrap();
+        assert_eq!(tree.debits(), &[1, 4, 7]);
+        let root_commitment = tree.commit();
+        assert_eq!(root_commitment.sum, 65);
+        for (i, value) in values.iter().enumerate() {
+            let proof = tree.prove(i);
+            assert_eq!(proof.value, *value);
+            assert!(proof.verify(&root_commitment), "Failed position {}", i);
+        }
+
+        // A signed leaf never hashes like the unsigned leaf with the same bytes
+        let unsigned: Node = Node::new(vec![5, 0]);
+        let signed = SignedTree::<Sha256>::try_new(vec![5, 0], SignedPolicy::RejectNegative).unwrap();
+        assert_eq!(signed.commit().sum, unsigned.commit().sum);
+        assert_ne!(signed.commit().hash, unsigned.commit().hash);
+
+        assert_eq!(
+            SignedTree::<Sha256>::try_new(values.clone(), SignedPolicy::RejectNegative).err(),
+            Some(SignedError::NegativeLeaf { position: 1 })
+        );
+        assert_eq!(
+            SignedTree::<Sha256>::try_new(vec![1, 2, -4, 1], SignedPolicy::AllowDebits).err(),
+            Some(SignedError::InvalidSum { height: 1, index: 1 })
+        );
+        assert_eq!(
+            SignedTree::<Sha256>::try_new(vec![i64::MAX, 1], SignedPolicy::AllowDebits).err(),
+            Some(SignedError::InvalidSum { height: 1, index: 0 })
+        );
+        assert_eq!(
+            SignedTree::<Sha256>::try_new(vec![1, 2, 3], SignedPolicy::AllowDebits).err(),
+            Some(SignedError::NotPowerOfTwo { len: 3 })
+        );
+
+        // A sibling claiming a debit large enough to push a sum below zero is rejected
+        let mut proof = tree.prove(0);
+        proof.siblings[0].0 = -60;
+        assert!(!proof.verify(&root_commitment));
+        let mut proof = tree.prove(3);
+        proof.value = 1;
+        assert!(!proof.verify(&root_commitment));
+    }
+}
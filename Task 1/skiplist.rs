@@ -0,0 +1,371 @@
+// Authenticated skip list with sums, a second implementation of `MerkleTree` and
+// `ExclusiveAllotmentProof` next to the binary tree. Each element gets a tower of
+// random height; the tower node at level l covers the element and those after it
+// up to the next tower reaching level l, and commits to the level l - 1 nodes it
+// covers. Read that way the list is a tree with two children per node on average,
+// so inserting anywhere, not just at the end, rehashes O(log n) nodes expected and
+// never rebuilds a subtree. Nodes commit to their leaf counts, which pins a proof
+// to its position.
+//
+// Heights are drawn from a hash of the insertion count, so the same inserts in
+// the same order give the same root. A zero head leaf, the skip list's sentinel,
+// comes before position 0 and is as tall as any tower.
+//
+// Leaf: as a V2 leaf of the binary tree
+// Node: tag (u8) | version (u8) | level (u8) | leaf count (u64), sum (u64) and hash of each child
+
+use alloc::vec;
+use alloc::vec::Vec;
+use core::mem;
+
+use super::{
+    hash_bytes, leaf_digest, BuildError, Commitment, Encoding, ExclusiveAllotmentProof, MerkleTree, Sha256,
+    SumOverflow, TreeDigest, V2_VERSION,
+};
+
+const SKIP_NODE_TAG: u8 = 0x0f;
+
+// `None` if the children's sums overflow
+fn skip_node_commitment<D: TreeDigest>(
+    level: u8,
+    children: impl IntoIterator<Item = (u64, Commitment<D>)>,
+) -> Option<Commitment<D>> {
+    let mut serialized = vec![SKIP_NODE_TAG, V2_VERSION, level];
+    let mut sum = 0u64;
+    for (count, child) in children {
+        sum = sum.checked_add(child.sum)?;
+        serialized.extend_from_slice(&count.to_be_bytes());
+        serialized.extend_from_slice(&child.sum.to_be_bytes());
+        serialized.extend_from_slice(&child.hash);
+    }
+    Some(Commitment::new(sum, hash_bytes::<D>(&serialized)))
+}
+
+fn skip_leaf<D: TreeDigest>(value: u64) -> Commitment<D> {
+    Commitment::new(value, leaf_digest::<D>(Encoding::V2, value, None))
+}
+
+#[derive(Debug)]
+enum SkipNode<D> {
+    Leaf(Commitment<D>),
+    Tower {
+        level: u8,
+        children: Vec<SkipNode<D>>,
+        count: u64,
+        commitment: Commitment<D>,
+    },
+}
+
+impl<D: TreeDigest> SkipNode<D> {
+    fn tower(level: u8, children: Vec<SkipNode<D>>) -> Self {
+        // Cannot overflow, the total was checked before the change
+        let commitment = skip_node_commitment(level, children.iter().map(|child| (child.count(), child.commitment())))
+            .expect("sums checked before the change");
+        SkipNode::Tower {
+            level,
+            count: children.iter().map(SkipNode::count).sum(),
+            children,
+            commitment,
+        }
+    }
+
+    fn level(&self) -> u8 {
+        match self {
+            SkipNode::Leaf(_) => 0,
+            SkipNode::Tower { level, .. } => *level,
+        }
+    }
+
+    fn count(&self) -> u64 {
+        match self {
+            SkipNode::Leaf(_) => 1,
+            SkipNode::Tower { count, .. } => *count,
+        }
+    }
+
+    fn commitment(&self) -> Commitment<D> {
+        match self {
+            SkipNode::Leaf(commitment) | SkipNode::Tower { commitment, .. } => *commitment,
+        }
+    }
+
+    // The child holding leaf `index`, and the leaves before that child
+    fn child_at(children: &[SkipNode<D>], index: u64) -> (usize, u64) {
+        let mut start = 0;
+        let mut child = 0;
+        while start + children[child].count() <= index {
+            start += children[child].count();
+            child += 1;
+        }
+        (child, start)
+    }
+
+    // Places `leaf` right after leaf `after`. Returns the part split off from its
+    // tower onwards when the new tower reaches this level.
+    fn insert(&mut self, after: u64, leaf: Commitment<D>, height: u8) -> Option<SkipNode<D>> {
+        let SkipNode::Tower { level, children, .. } = self else {
+            unreachable!("leaves are handled by their parent")
+        };
+        let level = *level;
+        let mut children = mem::take(children);
+        let (child, start) = Self::child_at(&children, after);
+        let new_child = if level == 1 {
+            children.insert(child + 1, SkipNode::Leaf(leaf));
+            Some(child + 1)
+        } else {
+            children[child].insert(after - start, leaf, height).map(|split| {
+                children.insert(child + 1, split);
+                child + 1
+            })
+        };
+        let split = match new_child {
+            Some(index) if height >= level => Some(Self::tower(level, children.split_off(index))),
+            _ => None,
+        };
+        *self = Self::tower(level, children);
+        split
+    }
+
+    fn update(&mut self, index: u64, leaf: Commitment<D>) {
+        match self {
+            SkipNode::Leaf(commitment) => *commitment = leaf,
+            SkipNode::Tower { level, children, .. } => {
+                let (child, start) = Self::child_at(children, index);
+                children[child].update(index - start, leaf);
+                *self = Self::tower(*level, mem::take(children));
+            }
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct SkipList<D = Sha256> {
+    root: SkipNode<D>,
+    // Elements inserted so far, which draws the next height
+    inserted: u64,
+}
+
+// The other children of one node on the path, each with its leaf count
+pub struct SkipLevel<D = Sha256> {
+    pub left: Vec<(u64, Commitment<D>)>,
+    pub right: Vec<(u64, Commitment<D>)>,
+}
+
+pub struct SkipProof<D = Sha256> {
+    pub node: Commitment<D>,
+    pub position: usize,
+    // From the leaf's parent up to the root
+    pub levels: Vec<SkipLevel<D>>,
+}
+
+impl<D: TreeDigest> SkipList<D> {
+    pub fn len(&self) -> usize {
+        self.root.count() as usize - 1
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    // Geometric with ratio 1/2, from the leading zero bits of a hash
+    fn next_height(&mut self) -> u8 {
+        let draw = hash_bytes::<D>(&[[SKIP_NODE_TAG].as_slice(), &self.inserted.to_be_bytes()].concat());
+        self.inserted += 1;
+        u32::from_be_bytes(draw[..4].try_into().unwrap()).leading_zeros() as u8
+    }
+
+    // Moves the other elements at `position` and after one place along
+    pub fn insert(&mut self, position: usize, value: u64) -> Result<(), SumOverflow> {
+        assert!(position <= self.len(), "position {} out of range", position);
+        self.commit().sum.checked_add(value).ok_or(SumOverflow)?;
+        let height = self.next_height();
+        // The head's tower stays the tallest, so the root never splits
+        while self.root.level() <= height {
+            let level = self.root.level() + 1;
+            // A placeholder until the old root is moved under the new one
+            let root = mem::replace(&mut self.root, SkipNode::Leaf(skip_leaf(0)));
+            self.root = SkipNode::tower(level, vec![root]);
+        }
+        // Counting the head, the new leaf goes right after leaf `position`
+        self.root.insert(position as u64, skip_leaf(value), height);
+        Ok(())
+    }
+
+    pub fn push(&mut self, value: u64) -> Result<(), SumOverflow> {
+        self.insert(self.len(), value)
+    }
+
+    pub fn update(&mut self, position: usize, value: u64) -> Result<(), SumOverflow> {
+        assert!(position < self.len(), "position {} out of range", position);
+        let old = self.prove(position).node.sum;
+        (self.commit().sum - old).checked_add(value).ok_or(SumOverflow)?;
+        self.root.update(position as u64 + 1, skip_leaf(value));
+        Ok(())
+    }
+}
+
+impl<D: TreeDigest> MerkleTree<Commitment<D>, SkipProof<D>> for SkipList<D> {
+    fn new(values: Vec<u64>) -> Self {
+        Self::try_new(values).expect("cannot build skip list")
+    }
+
+    fn try_new(values: Vec<u64>) -> Result<Self, BuildError> {
+        let mut list = Self {
+            root: SkipNode::tower(1, vec![SkipNode::Leaf(skip_leaf(0))]),
+            inserted: 0,
+        };
+        for value in values {
+            list.push(value).map_err(|_| BuildError::SumOverflow)?;
+        }
+        Ok(list)
+    }
+
+    fn commit(&self) -> Commitment<D> {
+        self.root.commitment()
+    }
+
+    fn prove(&self, position: usize) -> SkipProof<D> {
+        assert!(position < self.len(), "position {} out of range", position);
+        let mut levels = Vec::new();
+        let mut index = position as u64 + 1;
+        let mut current = &self.root;
+        while let SkipNode::Tower { children, .. } = current {
+            let (child, start) = SkipNode::child_at(children, index);
+            let sibling = |node: &SkipNode<D>| (node.count(), node.commitment());
+            levels.push(SkipLevel {
+                left: children[..child].iter().map(sibling).collect(),
+                right: children[child + 1..].iter().map(sibling).collect(),
+            });
+            index -= start;
+            current = &children[child];
+        }
+        levels.reverse();
+        SkipProof {
+            node: current.commitment(),
+            position,
+            levels,
+        }
+    }
+}
+
+impl<D: TreeDigest> ExclusiveAllotmentProof<Commitment<D>> for SkipProof<D> {
+    fn position(&self) -> usize {
+        self.position
+    }
+
+    // A level holds any number of siblings, so they are counted bottom-up and left
+    // to right across levels rather than by height
+    fn sibling(&self, height: u8) -> Option<Commitment<D>> {
+        self.levels
+            .iter()
+            .flat_map(|level| level.left.iter().chain(&level.right))
+            .nth(height as usize)
+            .map(|(_, sibling)| *sibling)
+    }
+
+    fn verify(&self, root_commitment: &Commitment<D>) -> bool {
+        if self.node != skip_leaf(self.node.sum) {
+            return false;
+        }
+        let leaves = |siblings: &[(u64, Commitment<D>)]| {
+            siblings
+                .iter()
+                .try_fold(0u64, |total, (count, _)| total.checked_add(*count))
+        };
+        let (mut count, mut commitment) = (1u64, self.node);
+        // Leaves before this one, counting the head
+        let mut before = 0u64;
+        for (level, siblings) in self.levels.iter().enumerate() {
+            let Ok(level) = u8::try_from(level + 1) else {
+                return false;
+            };
+            let (Some(left), Some(right)) = (leaves(&siblings.left), leaves(&siblings.right)) else {
+                return false;
+            };
+            let children = siblings
+                .left
+                .iter()
+                .copied()
+                .chain([(count, commitment)])
+                .chain(siblings.right.iter().copied());
+            let (Some(node), Some(total), Some(after_left)) = (
+                skip_node_commitment(level, children),
+                count.checked_add(left).and_then(|total| total.checked_add(right)),
+                before.checked_add(left),
+            ) else {
+                return false;
+            };
+            (count, commitment, before) = (total, node, after_left);
+        }
+        commitment == *root_commitment && before.checked_sub(1) == Some(self.position as u64)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_skip_list() {
+        let values: Vec<u64> = (1..=50).collect();
+        let mut list = SkipList::<Sha256>::new(values.clone());
+        let root_commitment = list.commit();
+        assert_eq!((list.len(), root_commitment.sum), (50, 1275));
+        for position in 0..50 {
+            let proof = list.prove(position);
+            assert_eq!(proof.node.sum, position as u64 + 1);
+            assert!(proof.verify(&root_commitment), "Failed position {}", position);
+            assert!(proof.sibling(0).is_some());
+        }
+        // Expected height is logarithmic, not linear
+        assert!(list.prove(25).levels.len() < 20);
+
+        // Same inserts in the same order, same root
+        assert_eq!(SkipList::<Sha256>::new(values).commit(), root_commitment);
+
+        // A proof claiming another position, balance or sibling fails
+        let mut moved = list.prove(10);
+        moved.position = 11;
+        assert!(!moved.verify(&root_commitment));
+        let mut inflated = list.prove(10);
+        inflated.node = skip_leaf(12);
+        assert!(!inflated.verify(&root_commitment));
+        let mut recounted = list.prove(10);
+        let level = recounted
+            .levels
+            .iter_mut()
+            .find(|level| !level.left.is_empty())
+            .unwrap();
+        level.left[0].0 += 1;
+        assert!(!recounted.verify(&root_commitment));
+
+        // Inserting in the middle moves the later elements along
+        list.insert(20, 1000).unwrap();
+        list.insert(0, 7).unwrap();
+        assert_eq!((list.len(), list.commit().sum), (52, 1275 + 1007));
+        assert_eq!(list.prove(0).node.sum, 7);
+        assert_eq!(list.prove(21).node.sum, 1000);
+        assert_eq!(list.prove(22).node.sum, 21);
+        for position in 0..52 {
+            assert!(
+                list.prove(position).verify(&list.commit()),
+                "Failed position {}",
+                position
+            );
+        }
+        assert!(!list.prove(0).verify(&root_commitment));
+
+        list.update(21, 5).unwrap();
+        assert_eq!(list.commit().sum, 1275 + 12);
+        assert!(list.prove(21).verify(&list.commit()));
+        assert_eq!(list.update(0, u64::MAX), Err(SumOverflow));
+        assert_eq!(list.insert(3, u64::MAX), Err(SumOverflow));
+
+        let empty = SkipList::<Sha256>::new(Vec::new());
+        assert!(empty.is_empty());
+        assert_eq!(
+            SkipList::<Sha256>::try_new(vec![u64::MAX, 1]).err(),
+            Some(BuildError::SumOverflow)
+        );
+    }
+}
@@ -0,0 +1,179 @@
+// `NodeStore` on a sled tree, keyed by the big-endian node index so that the
+// last key is always the root.
+//
+// Leaf:   0 (u8) | encoding (u8) | value (u64) | hash (32 bytes) | blinding, as in proofs
+// Branch: 1 (u8) | encoding (u8) | height (u64) | sum (u64) | left (u64) | right (u64)
+//         | hash (32 bytes)
+
+use alloc::vec::Vec;
+use std::io;
+
+use super::codec::{write_blinding, DecodeError, Reader};
+use super::storage::NodeStore;
+use super::{Encoding, Entry};
+
+pub struct SledStore {
+    tree: sled::Tree,
+    len: usize,
+}
+
+impl SledStore {
+    pub fn open(tree: sled::Tree) -> sled::Result<Self> {
+        let len = match tree.last()? {
+            Some((key, _)) => index_from_key(&key)? + 1,
+            None => 0,
+        };
+        Ok(Self { tree, len })
+    }
+
+    pub fn flush(&self) -> sled::Result<()> {
+        self.tree.flush().map(|_| ())
+    }
+}
+
+// Corrupt records surface as I/O errors, the same way snapshots report them
+fn corrupt(e: DecodeError) -> sled::Error {
+    sled::Error::Io(io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+fn read_index(reader: &mut Reader) -> Result<usize, DecodeError> {
+    let index = reader.u64()?;
+    usize::try_from(index).map_err(|_| DecodeError::IndexOutOfRange(index))
+}
+
+fn index_from_key(key: &[u8]) -> sled::Result<usize> {
+    let mut reader = Reader::new(key);
+    let index = read_index(&mut reader).map_err(corrupt)?;
+    reader.finish().map_err(corrupt)?;
+    Ok(index)
+}
+
+fn encode_entry(entry: &Entry) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    match entry {
+        Entry::Leaf {
+            value,
+            commitment,
+            encoding,
+            blinding,
+        } => {
+            bytes.push(0);
+            bytes.push(encoding.to_byte());
+            bytes.extend_from_slice(&value.to_be_bytes());
+            bytes.extend_from_slice(commitment);
+            write_blinding(&mut bytes, blinding.as_ref());
+        }
+        Entry::Branch {
+            height,
+            sum,
+            left,
+            right,
+            commitment,
+            encoding,
+        } => {
+            bytes.push(1);
+            bytes.push(encoding.to_byte());
+            for field in [*height as u64, *sum, *left as u64, *right as u64] {
+                bytes.extend_from_slice(&field.to_be_bytes());
+            }
+            bytes.extend_from_slice(commitment);
+        }
+    }
+    bytes
+}
+
+fn decode_entry(bytes: &[u8]) -> Result<Entry, DecodeError> {
+    let mut reader = Reader::new(bytes);
+    let tag = reader.u8()?;
+    let encoding = Encoding::from_byte(reader.u8()?)?;
+    let entry = match tag {
+        0 => Entry::Leaf {
+            value: reader.u64()?,
+            commitment: reader.array::<32>()?,
+            encoding,
+            blinding: reader.blinding()?,
+        },
+        1 => {
+            let height = read_index(&mut reader)?;
+            let sum = reader.u64()?;
+            let (left, right) = (read_index(&mut reader)?, read_index(&mut reader)?);
+            Entry::Branch {
+                height,
+                sum,
+                left,
+                right,
+                commitment: reader.array::<32>()?,
+                encoding,
+            }
+        }
+        tag => return Err(DecodeError::InvalidTag { field: "node", tag }),
+    };
+    reader.finish()?;
+    Ok(entry)
+}
+
+impl NodeStore for SledStore {
+    type Error = sled::Error;
+
+    fn len(&self) -> usize {
+        self.len
+    }
+
+    fn get(&self, index: usize) -> sled::Result<Entry> {
+        let bytes = self
+            .tree
+            .get((index as u64).to_be_bytes())?
+            .ok_or_else(|| corrupt(DecodeError::IndexOutOfRange(index as u64)))?;
+        decode_entry(&bytes).map_err(corrupt)
+    }
+
+    fn put(&mut self, index: usize, entry: Entry) -> sled::Result<()> {
+        assert!(index <= self.len, "node {} put past the end of the store", index);
+        self.tree.insert((index as u64).to_be_bytes(), encode_entry(&entry))?;
+        if index == self.len {
+            self.len += 1;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::vec;
+
+    use super::*;
+    use crate::storage::StoredTree;
+    use crate::{Blinding, ExclusiveAllotmentProof, MerkleTree, Node, Sha256};
+
+    #[test]
+    fn test_sled_store() {
+        let db = sled::Config::new().temporary(true).open().unwrap();
+        let values: Vec<u64> = (0..64).map(|i| i * 3).collect();
+        let store = SledStore::open(db.open_tree("nodes").unwrap()).unwrap();
+        let stored = StoredTree::<_, Sha256>::build(store, values.iter().copied()).unwrap();
+        stored.into_store().flush().unwrap();
+
+        // Reopening reads the root back from disk
+        let store = SledStore::open(db.open_tree("nodes").unwrap()).unwrap();
+        assert_eq!(store.len(), 2 * values.len() - 1);
+        let stored = StoredTree::<_, Sha256>::open(store).unwrap();
+        let node: Node = Node::new(values);
+        assert_eq!(stored.commit(), node.commit());
+        for i in [0, 1, 31, 63] {
+            let proof = stored.prove(i).unwrap();
+            assert_eq!(proof, node.prove(i));
+            assert!(proof.verify(&node.commit()));
+        }
+
+        // Blinded leaves round-trip as well
+        let mut rng = rand::thread_rng();
+        let blinded: Node = Node::try_new_blinded(vec![(5, Blinding::random(&mut rng, Some([9; 32]))); 2]).unwrap();
+        for entry in &blinded.entries {
+            assert_eq!(&decode_entry(&encode_entry(entry)).unwrap(), entry);
+        }
+
+        let mut truncated = encode_entry(&blinded.entries[2]);
+        truncated.pop();
+        assert_eq!(decode_entry(&truncated), Err(DecodeError::UnexpectedEnd));
+    }
+}
@@ -0,0 +1,196 @@
+// Snapshot of a built tree, so a restart does not have to re-hash every balance.
+// The stored hashes are trusted as-is: a snapshot is local state, while proofs
+// served from the loaded tree are still checked against the published root.
+//
+// Header: magic (4 bytes) | version (u8) | encoding (u8)
+// Nodes in pre-order:
+//   leaf:   0 (u8) | value (u64) | hash (32 bytes) | blinding, as in proofs
+//   branch: 1 (u8) | height (u64) | sum (u64) | hash (32 bytes) | left | right
+// The digest type is not recorded and must match the one the tree was built with.
+
+use alloc::vec::Vec;
+use core::marker::PhantomData;
+use std::io::{self, Read, Write};
+
+use super::codec::{write_blinding, DecodeError, Reader};
+use super::{Encoding, Entry, Node, SumCommitment};
+
+const SNAPSHOT_MAGIC: &[u8; 4] = b"MSTS";
+const SNAPSHOT_VERSION: u8 = 1;
+
+impl<D> Node<D> {
+    pub fn save<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        let mut bytes = Vec::new();
+        bytes.extend_from_slice(SNAPSHOT_MAGIC);
+        bytes.push(SNAPSHOT_VERSION);
+        bytes.push(self.encoding().to_byte());
+        self.write_node(self.entries.len() - 1, &mut bytes);
+        writer.write_all(&bytes)
+    }
+
+    pub fn load<R: Read>(mut reader: R) -> io::Result<Self> {
+        let mut bytes = Vec::new();
+        reader.read_to_end(&mut bytes)?;
+        Self::from_snapshot(&bytes).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+
+    fn from_snapshot(bytes: &[u8]) -> Result<Self, DecodeError> {
+        let mut reader = Reader::new(bytes);
+        if reader.array::<4>()? != *SNAPSHOT_MAGIC {
+            return Err(DecodeError::InvalidTag {
+                field: "snapshot magic",
+                tag: bytes[0],
+            });
+        }
+        let version = reader.u8()?;
+        if version != SNAPSHOT_VERSION {
+            return Err(DecodeError::UnsupportedVersion(version));
+        }
+        let encoding = Encoding::from_byte(reader.u8()?)?;
+        let mut entries = Vec::new();
+        Self::read_entry(&mut reader, encoding, None, &mut entries)?;
+        reader.finish()?;
+        Ok(Node {
+            entries,
+            hasher: PhantomData,
+        })
+    }
+
+    fn write_node(&self, index: usize, bytes: &mut Vec<u8>) {
+        match &self.entries[index] {
+            Entry::Leaf {
+                value,
+                commitment,
+                blinding,
+                ..
+            } => {
+                bytes.push(0);
+                bytes.extend_from_slice(&value.to_be_bytes());
+                bytes.extend_from_slice(commitment);
+                write_blinding(bytes, blinding.as_ref());
+            }
+            Entry::Branch {
+                height,
+                sum,
+                left,
+                right,
+                commitment,
+                ..
+            } => {
+                bytes.push(1);
+                bytes.extend_from_slice(&(*height as u64).to_be_bytes());
+                bytes.extend_from_slice(&sum.to_be_bytes());
+                bytes.extend_from_slice(commitment);
+                self.write_node(*left, bytes);
+                self.write_node(*right, bytes);
+            }
+        }
+    }
+
+    // Appends the node and its subtree to `entries` in post-order and returns its
+    // index. `expected_height` is None only for the root
+    fn read_entry(
+        reader: &mut Reader,
+        encoding: Encoding,
+        expected_height: Option<usize>,
+        entries: &mut Vec<Entry>,
+    ) -> Result<usize, DecodeError> {
+        let entry = match reader.u8()? {
+            0 => {
+                if expected_height.is_some_and(|height| height != 0) {
+                    return Err(DecodeError::InconsistentNode);
+                }
+                let value = reader.u64()?;
+                let commitment = reader.array::<32>()?;
+                let blinding = reader.blinding()?;
+                Entry::Leaf {
+                    value,
+                    commitment,
+                    encoding,
+                    blinding,
+                }
+            }
+            1 => {
+                // Bounding the height also bounds the recursion depth
+                let height = reader.u64()?;
+                if height == 0 || height >= usize::BITS as u64 {
+                    return Err(DecodeError::InconsistentNode);
+                }
+                let height = height as usize;
+                if expected_height.is_some_and(|expected| expected != height) {
+                    return Err(DecodeError::InconsistentNode);
+                }
+                let sum = reader.u64()?;
+                let commitment = reader.array::<32>()?;
+                let left = Self::read_entry(reader, encoding, Some(height - 1), entries)?;
+                let right = Self::read_entry(reader, encoding, Some(height - 1), entries)?;
+                if entries[left].amount().checked_add(entries[right].amount()) != Some(sum) {
+                    return Err(DecodeError::InconsistentNode);
+                }
+                Entry::Branch {
+                    height,
+                    sum,
+                    left,
+                    right,
+                    commitment,
+                    encoding,
+                }
+            }
+            tag => return Err(DecodeError::InvalidTag { field: "node", tag }),
+        };
+        entries.push(entry);
+        Ok(entries.len() - 1)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::boxed::Box;
+    use alloc::vec;
+
+    use sha2::Sha256;
+
+    use super::*;
+    use crate::{Blinding, ExclusiveAllotmentProof, MerkleTree};
+
+    #[test]
+    fn test_save_load() {
+        let mut rng = rand::thread_rng();
+        let leaves = (1..=8u64)
+            .map(|value| (value, Blinding::random(&mut rng, None)))
+            .collect();
+        let blinded: Node = Node::try_new_blinded(leaves).unwrap();
+        let legacy: Node = Node::try_new_with_encoding(vec![1, 2, 3, 4], Encoding::V0).unwrap();
+
+        for tree_root in [&blinded, &legacy] {
+            let mut snapshot = Vec::new();
+            tree_root.save(&mut snapshot).unwrap();
+            let loaded: Node = Node::load(snapshot.as_slice()).unwrap();
+            assert_eq!(&loaded, tree_root);
+
+            let root_commitment = tree_root.commit();
+            assert_eq!(loaded.commit(), root_commitment);
+            assert!(loaded.prove(3).verify(&root_commitment));
+        }
+    }
+
+    #[test]
+    fn test_load_rejects_corrupt_snapshot() {
+        let tree_root: Node = Node::new(vec![1, 2, 3, 4]);
+        let mut snapshot = Vec::new();
+        tree_root.save(&mut snapshot).unwrap();
+
+        assert!(Node::<Sha256>::load(&snapshot[..snapshot.len() - 1]).is_err());
+        assert!(Node::<Sha256>::load(&b"NOPE"[..]).is_err());
+
+        // Root sum no longer matches its children: header, node tag, then the height
+        let mut tampered = snapshot.clone();
+        tampered[4 + 1 + 1 + 1 + 8 + 7] ^= 1;
+        let err = Node::<Sha256>::load(tampered.as_slice()).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert_eq!(
+            err.into_inner().unwrap().downcast::<DecodeError>().unwrap(),
+            Box::new(DecodeError::InconsistentNode)
+        );
+    }
+}
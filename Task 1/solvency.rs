@@ -0,0 +1,154 @@
+// Proof of solvency: a published liabilities root together with a signed
+// attestation of on-chain reserves, accepted only when the reserves cover the
+// total the root commits to. The signature scheme is left to the caller through
+// the `signature` traits, e.g. Ed25519 for a custodian or BLS from Task 2.
+
+use alloc::vec::Vec;
+use core::fmt;
+
+use signature::{Signer, Verifier};
+
+use super::{hash_bytes, Commitment, Sha256, SumCommitment, TreeDigest};
+
+const RESERVES_DOMAIN: &[u8] = b"merkle-sum-tree/reserves/v1";
+
+// Reserves as signed by whoever controls or audits the on-chain wallets
+pub struct ReserveAttestation<S> {
+    pub total: u64,
+    // Block height or timestamp the reserves were measured at
+    pub as_of: u64,
+    pub signature: S,
+}
+
+pub struct SolvencyStatement<S, D = Sha256> {
+    pub liabilities: Commitment<D>,
+    pub reserves: ReserveAttestation<S>,
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum SolvencyError {
+    BadSignature,
+    Insolvent { reserves: u64, liabilities: u64 },
+}
+
+impl fmt::Display for SolvencyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SolvencyError::BadSignature => write!(f, "reserve attestation signature does not verify"),
+            SolvencyError::Insolvent { reserves, liabilities } => {
+                write!(
+                    f,
+                    "reserves of {} do not cover liabilities of {}",
+                    reserves, liabilities
+                )
+            }
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for SolvencyError {}
+
+fn reserves_message(total: u64, as_of: u64) -> Vec<u8> {
+    [RESERVES_DOMAIN, &as_of.to_be_bytes(), &total.to_be_bytes()].concat()
+}
+
+impl<S> ReserveAttestation<S> {
+    pub fn sign(signer: &impl Signer<S>, total: u64, as_of: u64) -> Self {
+        Self {
+            total,
+            as_of,
+            signature: signer.sign(&reserves_message(total, as_of)),
+        }
+    }
+
+    pub fn verify(&self, verifier: &impl Verifier<S>) -> Result<(), SolvencyError> {
+        verifier
+            .verify(&reserves_message(self.total, self.as_of), &self.signature)
+            .map_err(|_| SolvencyError::BadSignature)
+    }
+}
+
+impl<S, D: TreeDigest> SolvencyStatement<S, D> {
+    // Refuses to produce a statement the exchange could not back
+    pub fn new(liabilities: Commitment<D>, reserves: ReserveAttestation<S>) -> Result<Self, SolvencyError> {
+        let statement = Self { liabilities, reserves };
+        statement.check_coverage()?;
+        Ok(statement)
+    }
+
+    // Returns the surplus of reserves over liabilities
+    pub fn verify(&self, verifier: &impl Verifier<S>) -> Result<u64, SolvencyError> {
+        self.reserves.verify(verifier)?;
+        self.check_coverage()
+    }
+
+    // Identifies the statement, e.g. for publishing alongside the root
+    pub fn digest(&self) -> [u8; 32] {
+        hash_bytes::<D>(
+            &[
+                reserves_message(self.reserves.total, self.reserves.as_of).as_slice(),
+                &self.liabilities.sum.to_be_bytes(),
+                &self.liabilities.hash,
+            ]
+            .concat(),
+        )
+    }
+
+    fn check_coverage(&self) -> Result<u64, SolvencyError> {
+        self.reserves
+            .total
+            .checked_sub(self.liabilities.amount())
+            .ok_or(SolvencyError::Insolvent {
+                reserves: self.reserves.total,
+                liabilities: self.liabilities.amount(),
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::vec;
+
+    use ed25519_dalek::{Signature, SigningKey};
+
+    use super::*;
+    use crate::{MerkleTree, Node};
+
+    #[test]
+    fn test_solvency() {
+        let root = Node::<Sha256>::new(vec![300, 700]).commit();
+
+        let custodian = SigningKey::from_bytes(&[7; 32]);
+        let verifier = custodian.verifying_key();
+
+        let reserves = ReserveAttestation::<Signature>::sign(&custodian, 1_200, 840_000);
+        let statement = SolvencyStatement::new(root, reserves).unwrap();
+        assert_eq!(statement.verify(&verifier), Ok(200));
+
+        // The digest covers the reserves, when they were measured and the root
+        let remeasured = ReserveAttestation::<Signature>::sign(&custodian, 1_200, 840_001);
+        assert_ne!(SolvencyStatement::new(root, remeasured).unwrap().digest(), statement.digest());
+        let resigned = ReserveAttestation::<Signature>::sign(&custodian, 1_200, 840_000);
+        assert_eq!(SolvencyStatement::new(root, resigned).unwrap().digest(), statement.digest());
+
+        // Reserves short of the liabilities cannot be turned into a statement
+        let short = ReserveAttestation::<Signature>::sign(&custodian, 999, 840_000);
+        assert_eq!(
+            SolvencyStatement::new(root, short).err(),
+            Some(SolvencyError::Insolvent {
+                reserves: 999,
+                liabilities: 1_000
+            })
+        );
+
+        // Inflating the attested total breaks the signature
+        let mut inflated = statement;
+        inflated.reserves.total = 5_000;
+        assert_eq!(inflated.verify(&verifier), Err(SolvencyError::BadSignature));
+
+        let other = SigningKey::from_bytes(&[8; 32]).verifying_key();
+        let reserves = ReserveAttestation::<Signature>::sign(&custodian, 1_200, 840_000);
+        assert_eq!(reserves.verify(&other), Err(SolvencyError::BadSignature));
+    }
+}
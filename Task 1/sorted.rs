@@ -0,0 +1,258 @@
+// Trees over accounts sorted by identifier, which can also prove an account is
+// absent: two committed leaves at adjacent positions whose identifiers enclose the
+// missing one. A sentinel leaf with the lowest identifier opens the tree and the
+// padding leaves carry the highest, so every identifier has neighbours on both
+// sides. Sentinels hold 0 and leave the total unchanged.
+//
+// The verifier checks the two leaves are adjacent and in order; that the whole
+// tree is sorted is what the builder enforces, and what an auditor holding the
+// leaves can check. An exclusion proof shows both neighbours' identifiers and
+// balances to whoever receives it.
+//
+// Leaf: tag (u8) | version (u8) | account id (32 bytes) | value (u64)
+
+use alloc::vec::Vec;
+use core::fmt;
+
+use subtle::ConstantTimeEq;
+
+use super::{
+    hash_bytes, BuildError, Commitment, Encoding, Entry, MerkleTree, Node, Proof, Sha256, SumOverflow, TreeDigest,
+    V2_VERSION,
+};
+
+const SORTED_LEAF_TAG: u8 = 0x09;
+// Reserved for the sentinels, so no account may use them
+const LOWEST_ID: [u8; 32] = [0x00; 32];
+const HIGHEST_ID: [u8; 32] = [0xff; 32];
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum SortedError {
+    // Rows are counted from 0 in the order the accounts were given
+    DuplicateId { first: usize, repeated: usize },
+    ReservedId { row: usize },
+    SumOverflow,
+}
+
+impl fmt::Display for SortedError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SortedError::DuplicateId { first, repeated } => {
+                write!(f, "account {} repeats the identifier of account {}", repeated, first)
+            }
+            SortedError::ReservedId { row } => write!(f, "account {} uses a sentinel identifier", row),
+            SortedError::SumOverflow => write!(f, "{}", SumOverflow),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for SortedError {}
+
+impl From<SumOverflow> for SortedError {
+    fn from(_: SumOverflow) -> Self {
+        SortedError::SumOverflow
+    }
+}
+
+fn sorted_leaf_digest<D: TreeDigest>(id: &[u8; 32], value: u64) -> [u8; 32] {
+    let serialized = [[SORTED_LEAF_TAG, V2_VERSION].as_slice(), id, &value.to_be_bytes()].concat();
+    hash_bytes::<D>(&serialized)
+}
+
+pub struct SortedTree<D = Sha256> {
+    // Sentinels included, in leaf order
+    ids: Vec<[u8; 32]>,
+    // Accounts, not counting sentinels
+    len: usize,
+    tree: Node<D>,
+}
+
+impl<D: TreeDigest> SortedTree<D> {
+    // Accounts may come in any order; they are sorted by identifier here
+    pub fn try_new(accounts: Vec<([u8; 32], u64)>) -> Result<Self, SortedError> {
+        let mut rows: Vec<(usize, [u8; 32], u64)> = accounts
+            .into_iter()
+            .enumerate()
+            .map(|(row, (id, value))| (row, id, value))
+            .collect();
+        rows.sort_by(|a, b| a.1.cmp(&b.1).then(a.0.cmp(&b.0)));
+        for pair in rows.windows(2) {
+            if pair[0].1 == pair[1].1 {
+                return Err(SortedError::DuplicateId {
+                    first: pair[0].0,
+                    repeated: pair[1].0,
+                });
+            }
+        }
+        if let Some(&(row, ..)) = rows.iter().find(|(_, id, _)| *id == LOWEST_ID || *id == HIGHEST_ID) {
+            return Err(SortedError::ReservedId { row });
+        }
+
+        let len = rows.len();
+        // At least one highest sentinel, so the last account has a right neighbour
+        let width = (len + 2).next_power_of_two();
+        let mut leaves = Vec::with_capacity(width);
+        leaves.push((LOWEST_ID, 0));
+        leaves.extend(rows.into_iter().map(|(_, id, value)| (id, value)));
+        leaves.resize(width, (HIGHEST_ID, 0));
+
+        let tree = Node::try_from_leaves(leaves.iter().map(|(id, value)| Entry::Leaf {
+            value: *value,
+            commitment: sorted_leaf_digest::<D>(id, *value),
+            encoding: Encoding::V2,
+            blinding: None,
+        }))
+        .map_err(BuildError::into_overflow)?;
+        Ok(Self {
+            ids: leaves.into_iter().map(|(id, _)| id).collect(),
+            len,
+            tree,
+        })
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn commit(&self) -> Commitment<D> {
+        self.tree.commit()
+    }
+
+    fn prove_position(&self, position: usize) -> SortedProof<D> {
+        SortedProof {
+            id: self.ids[position],
+            path: self.tree.prove(position),
+        }
+    }
+
+    // `None` if the account is not in the tree
+    pub fn prove(&self, id: &[u8; 32]) -> Option<SortedProof<D>> {
+        let position = self.ids[1..=self.len].binary_search(id).ok()? + 1;
+        Some(self.prove_position(position))
+    }
+
+    // `None` if the account is in the tree, or is a sentinel
+    pub fn prove_absent(&self, id: &[u8; 32]) -> Option<ExclusionProof<D>> {
+        if *id == LOWEST_ID || *id == HIGHEST_ID {
+            return None;
+        }
+        // The sentinels bound the search, so the upper neighbour is at least position 1
+        let upper = self.ids.binary_search(id).err()?;
+        Some(ExclusionProof {
+            lower: self.prove_position(upper - 1),
+            upper: self.prove_position(upper),
+        })
+    }
+}
+
+pub struct SortedProof<D = Sha256> {
+    pub id: [u8; 32],
+    // Its node is the sorted leaf, so it does not verify as a plain proof
+    pub path: Proof<D>,
+}
+
+impl<D: TreeDigest> SortedProof<D> {
+    pub fn position(&self) -> usize {
+        self.path.index
+    }
+
+    pub fn verify(&self, root_commitment: &Commitment<D>) -> bool {
+        self.path
+            .verify_path(root_commitment, |node| {
+                node.hash.ct_eq(&sorted_leaf_digest::<D>(&self.id, node.sum)).into()
+            })
+            .is_ok()
+    }
+}
+
+pub struct ExclusionProof<D = Sha256> {
+    pub lower: SortedProof<D>,
+    pub upper: SortedProof<D>,
+}
+
+impl<D: TreeDigest> ExclusionProof<D> {
+    pub fn verify(&self, root_commitment: &Commitment<D>, id: &[u8; 32]) -> bool {
+        // Paths of one length, so both leaves are at the bottom of the same tree
+        let adjacent = self.lower.path.siblings.len() == self.upper.path.siblings.len()
+            && self.lower.position().checked_add(1) == Some(self.upper.position());
+        adjacent
+            && self.lower.id < *id
+            && *id < self.upper.id
+            && self.lower.verify(root_commitment)
+            && self.upper.verify(root_commitment)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::vec;
+
+    use super::*;
+    use crate::ExclusiveAllotmentProof;
+
+    fn id(byte: u8) -> [u8; 32] {
+        [byte; 32]
+    }
+
+    #[test]
+    fn test_sorted_tree() {
+        let tree = SortedTree::<Sha256>::try_new(vec![(id(40), 4), (id(10), 1), (id(30), 3), (id(20), 2)]).unwrap();
+        let root_commitment = tree.commit();
+        assert_eq!((tree.len(), root_commitment.sum), (4, 10));
+
+        for (i, byte) in [10, 20, 30, 40].into_iter().enumerate() {
+            let proof = tree.prove(&id(byte)).unwrap();
+            assert_eq!((proof.position(), proof.path.node.sum), (i + 1, byte as u64 / 10));
+            assert!(proof.verify(&root_commitment), "Failed account {}", byte);
+            assert!(!proof.path.verify(&root_commitment));
+            assert!(tree.prove_absent(&id(byte)).is_none());
+        }
+
+        // Below the first account, between two, and past the last
+        for (byte, neighbour) in [(5, 10), (25, 20), (45, 40)] {
+            let absent = tree.prove_absent(&id(byte)).unwrap();
+            assert!(tree.prove(&id(byte)).is_none());
+            assert!(absent.verify(&root_commitment, &id(byte)), "Failed gap {}", byte);
+            assert!(!absent.verify(&root_commitment, &id(neighbour)));
+        }
+        assert_eq!(tree.prove_absent(&id(5)).unwrap().lower.id, LOWEST_ID);
+        assert_eq!(tree.prove_absent(&id(45)).unwrap().upper.id, HIGHEST_ID);
+        assert!(tree.prove_absent(&HIGHEST_ID).is_none());
+
+        // Leaves that are both committed but not neighbours hide the ones between them
+        let skipping = ExclusionProof {
+            lower: tree.prove(&id(10)).unwrap(),
+            upper: tree.prove(&id(30)).unwrap(),
+        };
+        assert!(!skipping.verify(&root_commitment, &id(20)));
+        let mut moved = skipping;
+        moved.upper.path.index = 2;
+        assert!(!moved.verify(&root_commitment, &id(20)));
+        // Nor may a neighbour claim another identifier
+        let mut renamed = tree.prove_absent(&id(15)).unwrap();
+        renamed.lower.id = id(12);
+        assert!(!renamed.verify(&root_commitment, &id(13)));
+
+        let empty = SortedTree::<Sha256>::try_new(Vec::new()).unwrap();
+        assert!(empty.is_empty());
+        assert!(empty.prove_absent(&id(1)).unwrap().verify(&empty.commit(), &id(1)));
+
+        assert_eq!(
+            SortedTree::<Sha256>::try_new(vec![(id(1), 1), (id(2), 2), (id(1), 3)]).err(),
+            Some(SortedError::DuplicateId { first: 0, repeated: 2 })
+        );
+        assert_eq!(
+            SortedTree::<Sha256>::try_new(vec![(id(1), 1), (HIGHEST_ID, 2)]).err(),
+            Some(SortedError::ReservedId { row: 1 })
+        );
+        assert_eq!(
+            SortedTree::<Sha256>::try_new(vec![(id(1), u64::MAX), (id(2), 1)]).err(),
+            Some(SortedError::SumOverflow)
+        );
+    }
+}
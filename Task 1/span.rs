@@ -0,0 +1,182 @@
+// Proof for a contiguous block of leaves that reveals only the block's total. The
+// block is covered by the fewest aligned subtrees, whose commitments stand in for
+// the leaves, and only the siblings on the block's two edges are added, so the
+// proof grows with the tree height and not with the length of the block.
+
+use alloc::vec::Vec;
+use core::ops::Range;
+
+use super::{branch_digest, Commitment, Encoding, Node, Sha256, TreeDigest};
+
+// The largest aligned subtrees covering `range`, left to right, as (height, index)
+fn cover(range: &Range<usize>, height: usize) -> Vec<(usize, usize)> {
+    let mut nodes = Vec::new();
+    let mut start = range.start;
+    while start < range.end {
+        let mut level = (start.trailing_zeros() as usize).min(height);
+        while start + (1 << level) > range.end {
+            level -= 1;
+        }
+        nodes.push((level, start >> level));
+        start += 1 << level;
+    }
+    nodes
+}
+
+pub struct SpanProof<D = Sha256> {
+    pub range: Range<usize>,
+    pub height: usize,
+    // One per subtree of the cover, left to right
+    pub nodes: Vec<Commitment<D>>,
+    // Level by level, the left edge's before the right edge's
+    pub siblings: Vec<Commitment<D>>,
+    pub encoding: Encoding,
+}
+
+impl<D: TreeDigest> Node<D> {
+    pub fn prove_range(&self, range: Range<usize>) -> SpanProof<D> {
+        assert!(
+            range.start < range.end && range.end <= 1 << self.height(),
+            "{:?} out of range",
+            range
+        );
+        let cover = cover(&range, self.height());
+        let nodes = cover
+            .iter()
+            .map(|&(level, index)| self.descendant(level, index).into())
+            .collect();
+
+        // Only the outermost nodes known at a level can lack their sibling
+        let mut siblings = Vec::new();
+        for level in 0..self.height() {
+            let mut known = cover
+                .iter()
+                .filter(|(height, _)| *height <= level)
+                .map(|&(height, index)| index >> (level - height));
+            let Some(first) = known.next() else {
+                continue;
+            };
+            let last = known.next_back().unwrap_or(first);
+            if first & 1 == 1 {
+                siblings.push(self.descendant(level, first - 1).into());
+            }
+            if last & 1 == 0 {
+                siblings.push(self.descendant(level, last + 1).into());
+            }
+        }
+
+        SpanProof {
+            range,
+            height: self.height(),
+            nodes,
+            siblings,
+            encoding: self.encoding(),
+        }
+    }
+}
+
+impl<D: TreeDigest> SpanProof<D> {
+    pub fn verify(&self, root_commitment: &Commitment<D>) -> bool {
+        self.verified_sum(root_commitment).is_some()
+    }
+
+    // The total of the block, if the proof holds
+    pub fn verified_sum(&self, root_commitment: &Commitment<D>) -> Option<u64> {
+        if self.height >= usize::BITS as usize
+            || self.range.start >= self.range.end
+            || (self.range.end - 1) >> self.height != 0
+        {
+            return None;
+        }
+        let cover = cover(&self.range, self.height);
+        if cover.len() != self.nodes.len() {
+            return None;
+        }
+        let total = self
+            .nodes
+            .iter()
+            .try_fold(0u64, |total, node| total.checked_add(node.sum))?;
+
+        let mut known: Vec<(usize, Commitment<D>)> = Vec::new();
+        let mut siblings = self.siblings.iter();
+        for height in 0..=self.height {
+            // A cover node is never one computed from below, so it just joins its level
+            for (&(level, index), node) in cover.iter().zip(&self.nodes) {
+                if level == height {
+                    known.push((index, *node));
+                }
+            }
+            known.sort_unstable_by_key(|(index, _)| *index);
+            if height == self.height {
+                break;
+            }
+
+            let mut parents = Vec::with_capacity(known.len());
+            let mut i = 0;
+            while i < known.len() {
+                let (index, commitment) = known[i];
+                let sibling = match known.get(i + 1) {
+                    Some(&(next, next_commitment)) if next == index ^ 1 => {
+                        i += 1;
+                        next_commitment
+                    }
+                    _ => *siblings.next()?,
+                };
+                i += 1;
+
+                let (left, right) = if (index & 1) == 0 {
+                    (commitment, sibling)
+                } else {
+                    (sibling, commitment)
+                };
+                let sum = left.sum.checked_add(right.sum)?;
+                let hash = branch_digest::<D>(self.encoding, height + 1, sum, &left.hash, &right.hash);
+                parents.push((index >> 1, Commitment::new(sum, hash)));
+            }
+            known = parents;
+        }
+
+        (siblings.next().is_none() && known.len() == 1 && &known[0].1 == root_commitment).then_some(total)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::MerkleTree;
+
+    #[test]
+    fn test_span_proof() {
+        let tree_root: Node = Node::new((1..=16).collect());
+        let root_commitment = tree_root.commit();
+
+        for range in [0..16, 0..1, 15..16, 3..11, 4..8, 1..15, 6..7, 8..16] {
+            let proof = tree_root.prove_range(range.clone());
+            assert_eq!(
+                proof.verified_sum(&root_commitment),
+                Some(range.clone().map(|i| i as u64 + 1).sum()),
+                "Failed {:?}",
+                range
+            );
+        }
+
+        // Two siblings per level at most, however long the block
+        let proof = tree_root.prove_range(1..15);
+        assert_eq!(proof.nodes.len(), 6);
+        assert_eq!(proof.siblings.len(), 2);
+        assert!(tree_root.prove_range(0..16).siblings.is_empty());
+
+        let mut shifted = tree_root.prove_range(3..11);
+        shifted.range = 4..12;
+        assert!(!shifted.verify(&root_commitment));
+        let mut inflated = tree_root.prove_range(3..11);
+        inflated.nodes[0].sum += 1;
+        assert!(!inflated.verify(&root_commitment));
+        let mut extra_sibling = tree_root.prove_range(3..11);
+        extra_sibling.siblings.push(root_commitment);
+        assert!(!extra_sibling.verify(&root_commitment));
+        let mut missing_sibling = tree_root.prove_range(3..11);
+        missing_sibling.siblings.pop();
+        assert!(!missing_sibling.verify(&root_commitment));
+    }
+}
@@ -0,0 +1,269 @@
+// Sum tree over the full 256-bit key space, e.g. hashes of account identifiers.
+// Absent keys are empty leaves, so a proof can show either that a key holds a
+// balance or that it holds nothing. Only occupied leaves are stored; empty
+// subtrees are a fixed hash per height.
+
+use alloc::collections::BTreeMap;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::fmt;
+
+use super::{branch_digest, hash_bytes, Commitment, Encoding, Sha256, SumOverflow, TreeDigest, LEAF_TAG};
+
+const EMPTY_TAG: u8 = 0x03;
+const KEY_BITS: usize = 256;
+
+type SparseLeaf = ([u8; 32], u64);
+
+// Leaves commit to their key, so a balance cannot be moved to another key
+fn sparse_leaf_digest<D: TreeDigest>(key: &[u8; 32], value: u64) -> [u8; 32] {
+    hash_bytes::<D>(&[[LEAF_TAG].as_slice(), key, &value.to_be_bytes()].concat())
+}
+
+// Commitment of an empty subtree at each height, from the empty leaf up to the root
+fn empty_subtrees<D: TreeDigest>() -> Vec<Commitment<D>> {
+    let mut empty = vec![Commitment::new(0, hash_bytes::<D>(&[EMPTY_TAG]))];
+    for height in 1..=KEY_BITS {
+        let below = empty[height - 1].hash;
+        empty.push(Commitment::new(
+            0,
+            branch_digest::<D>(Encoding::V2, height, 0, &below, &below),
+        ));
+    }
+    empty
+}
+
+// Bit `index` of the key, most significant first; the root splits on bit 0
+fn key_bit(key: &[u8; 32], index: usize) -> bool {
+    (key[index / 8] >> (7 - index % 8)) & 1 == 1
+}
+
+pub struct SparseMerkleSumTree<D = Sha256> {
+    leaves: BTreeMap<[u8; 32], u64>,
+    sum: u64,
+    empty: Vec<Commitment<D>>,
+}
+
+// Proves the value under `key`, or with `value` None that the key is absent
+pub struct SparseProof<D = Sha256> {
+    pub key: [u8; 32],
+    pub value: Option<u64>,
+    // Bit `h` is set when the sibling at height `h` is an empty subtree and left out
+    pub empty_siblings: [u8; 32],
+    // The remaining siblings, bottom-up
+    pub siblings: Vec<Commitment<D>>,
+}
+
+// Implemented by hand for the same reason as `Commitment`
+impl<D> Clone for SparseProof<D> {
+    fn clone(&self) -> Self {
+        SparseProof {
+            key: self.key,
+            value: self.value,
+            empty_siblings: self.empty_siblings,
+            siblings: self.siblings.clone(),
+        }
+    }
+}
+
+impl<D> fmt::Debug for SparseProof<D> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SparseProof")
+            .field("key", &self.key)
+            .field("value", &self.value)
+            .field("empty_siblings", &self.empty_siblings)
+            .field("siblings", &self.siblings)
+            .finish()
+    }
+}
+
+impl<D: TreeDigest> Default for SparseMerkleSumTree<D> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<D: TreeDigest> SparseMerkleSumTree<D> {
+    pub fn new() -> Self {
+        Self {
+            leaves: BTreeMap::new(),
+            sum: 0,
+            empty: empty_subtrees::<D>(),
+        }
+    }
+
+    pub fn key_for(id: &[u8]) -> [u8; 32] {
+        hash_bytes::<D>(id)
+    }
+
+    pub fn get(&self, key: &[u8; 32]) -> Option<u64> {
+        self.leaves.get(key).copied()
+    }
+
+    pub fn insert(&mut self, key: [u8; 32], value: u64) -> Result<Option<u64>, SumOverflow> {
+        let old = self.get(&key);
+        self.sum = (self.sum - old.unwrap_or(0)).checked_add(value).ok_or(SumOverflow)?;
+        self.leaves.insert(key, value);
+        Ok(old)
+    }
+
+    pub fn remove(&mut self, key: &[u8; 32]) -> Option<u64> {
+        let old = self.leaves.remove(key)?;
+        self.sum -= old;
+        Some(old)
+    }
+
+    pub fn commit(&self) -> Commitment<D> {
+        let leaves: Vec<_> = self.leaves.iter().map(|(key, value)| (*key, *value)).collect();
+        self.subtree(KEY_BITS, &leaves)
+    }
+
+    pub fn prove(&self, key: &[u8; 32]) -> SparseProof<D> {
+        let leaves: Vec<_> = self.leaves.iter().map(|(key, value)| (*key, *value)).collect();
+        let mut empty_siblings = [0u8; 32];
+        let mut siblings = Vec::new();
+
+        let mut range = leaves.as_slice();
+        for height in (1..=KEY_BITS).rev() {
+            let (left, right) = split(range, KEY_BITS - height);
+            let (path, other) = if key_bit(key, KEY_BITS - height) {
+                (right, left)
+            } else {
+                (left, right)
+            };
+            // Stored by the height of the sibling, one below the node being split
+            if other.is_empty() {
+                empty_siblings[(height - 1) / 8] |= 1 << ((height - 1) % 8);
+            } else {
+                siblings.push(self.subtree(height - 1, other));
+            }
+            range = path;
+        }
+        siblings.reverse();
+
+        SparseProof {
+            key: *key,
+            value: self.get(key),
+            empty_siblings,
+            siblings,
+        }
+    }
+
+    // `leaves` are sorted and share the top `KEY_BITS - height` bits
+    fn subtree(&self, height: usize, leaves: &[SparseLeaf]) -> Commitment<D> {
+        match leaves {
+            [] => self.empty[height],
+            [(key, value)] if height == 0 => Commitment::new(*value, sparse_leaf_digest::<D>(key, *value)),
+            _ => {
+                let (left, right) = split(leaves, KEY_BITS - height);
+                let left = self.subtree(height - 1, left);
+                let right = self.subtree(height - 1, right);
+                // Cannot overflow, the total was checked on insert
+                let sum = left.sum + right.sum;
+                Commitment::new(
+                    sum,
+                    branch_digest::<D>(Encoding::V2, height, sum, &left.hash, &right.hash),
+                )
+            }
+        }
+    }
+}
+
+fn split(leaves: &[SparseLeaf], bit: usize) -> (&[SparseLeaf], &[SparseLeaf]) {
+    leaves.split_at(leaves.partition_point(|(key, _)| !key_bit(key, bit)))
+}
+
+impl<D: TreeDigest> SparseProof<D> {
+    pub fn verify(&self, root_commitment: &Commitment<D>) -> bool {
+        let empty = empty_subtrees::<D>();
+        let mut commitment = match self.value {
+            Some(value) => Commitment::new(value, sparse_leaf_digest::<D>(&self.key, value)),
+            None => empty[0],
+        };
+
+        let mut siblings = self.siblings.iter();
+        for (height, empty_sibling) in empty.iter().enumerate().take(KEY_BITS) {
+            let sibling = if (self.empty_siblings[height / 8] >> (height % 8)) & 1 == 1 {
+                *empty_sibling
+            } else {
+                match siblings.next() {
+                    Some(sibling) => *sibling,
+                    None => return false,
+                }
+            };
+            let (left, right) = if key_bit(&self.key, KEY_BITS - 1 - height) {
+                (sibling, commitment)
+            } else {
+                (commitment, sibling)
+            };
+            let Some(sum) = left.sum.checked_add(right.sum) else {
+                return false;
+            };
+            let hash = branch_digest::<D>(Encoding::V2, height + 1, sum, &left.hash, &right.hash);
+            commitment = Commitment::new(sum, hash);
+        }
+
+        siblings.next().is_none() && &commitment == root_commitment
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::SumCommitment;
+
+    #[test]
+    fn test_membership_and_absence() {
+        let mut tree = SparseMerkleSumTree::<Sha256>::new();
+        let alice = SparseMerkleSumTree::<Sha256>::key_for(b"alice");
+        let bob = SparseMerkleSumTree::<Sha256>::key_for(b"bob");
+        let carol = SparseMerkleSumTree::<Sha256>::key_for(b"carol");
+        assert_eq!(tree.insert(alice, 10), Ok(None));
+        assert_eq!(tree.insert(bob, 0), Ok(None));
+        assert_eq!(tree.insert(alice, 15), Ok(Some(10)));
+
+        let root_commitment = tree.commit();
+        assert_eq!(root_commitment.amount(), 15);
+
+        let proof = tree.prove(&alice);
+        assert_eq!(proof.value, Some(15));
+        assert!(proof.verify(&root_commitment));
+        // Nearly every sibling of a sparse path is empty
+        assert!(proof.siblings.len() <= 2);
+
+        // A zero balance is still present, unlike a missing key
+        let proof = tree.prove(&bob);
+        assert_eq!(proof.value, Some(0));
+        assert!(proof.verify(&root_commitment));
+        let mut absent = proof.clone();
+        absent.value = None;
+        assert!(!absent.verify(&root_commitment));
+
+        let proof = tree.prove(&carol);
+        assert_eq!(proof.value, None);
+        assert!(proof.verify(&root_commitment));
+        let mut forged = proof.clone();
+        forged.value = Some(0);
+        assert!(!forged.verify(&root_commitment));
+
+        // A balance cannot be claimed under another key
+        let mut moved = tree.prove(&alice);
+        moved.key = carol;
+        assert!(!moved.verify(&root_commitment));
+
+        assert_eq!(tree.remove(&alice), Some(15));
+        assert_eq!(tree.commit().amount(), 0);
+        assert!(tree.prove(&alice).verify(&tree.commit()));
+        assert!(!tree.prove(&alice).verify(&root_commitment));
+    }
+
+    #[test]
+    fn test_sparse_overflow() {
+        let mut tree = SparseMerkleSumTree::<Sha256>::new();
+        tree.insert([1; 32], u64::MAX).unwrap();
+        assert_eq!(tree.insert([2; 32], 1), Err(SumOverflow));
+        assert_eq!(tree.get(&[2; 32]), None);
+        assert_eq!(tree.insert([1; 32], 7), Ok(Some(u64::MAX)));
+        assert_eq!(tree.commit().amount(), 7);
+    }
+}
@@ -0,0 +1,221 @@
+// Trees kept in a `NodeStore` rather than in memory, so a tree larger than RAM
+// can be built on disk and proved against. Nodes use the same post-order layout
+// and indices as `Node`, and building only holds the roots of the complete
+// subtrees in memory. A plain `Vec` is the in-memory store.
+
+use alloc::vec::Vec;
+use core::convert::Infallible;
+use core::fmt;
+use core::marker::PhantomData;
+
+use super::{join_entries, leaf_entry, Commitment, Encoding, Entry, Proof, Sha256, SumOverflow, TreeDigest};
+
+pub trait NodeStore {
+    type Error;
+
+    // Number of nodes stored, which is also the index the next one is put at
+    fn len(&self) -> usize;
+
+    fn get(&self, index: usize) -> Result<Entry, Self::Error>;
+
+    // `index` is either an existing node to overwrite or `len()`
+    fn put(&mut self, index: usize, entry: Entry) -> Result<(), Self::Error>;
+
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl NodeStore for Vec<Entry> {
+    type Error = Infallible;
+
+    fn len(&self) -> usize {
+        Vec::len(self)
+    }
+
+    fn get(&self, index: usize) -> Result<Entry, Infallible> {
+        Ok(self[index].clone())
+    }
+
+    fn put(&mut self, index: usize, entry: Entry) -> Result<(), Infallible> {
+        if index == Vec::len(self) {
+            self.push(entry);
+        } else {
+            self[index] = entry;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum StoreError<E> {
+    SumOverflow,
+    // The store holds no tree to open
+    Empty,
+    Backend(E),
+}
+
+impl<E: fmt::Display> fmt::Display for StoreError<E> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StoreError::SumOverflow => write!(f, "{}", SumOverflow),
+            StoreError::Empty => write!(f, "node store is empty"),
+            StoreError::Backend(e) => write!(f, "node store failed: {}", e),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl<E: std::error::Error + 'static> std::error::Error for StoreError<E> {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            StoreError::Backend(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl<E> From<SumOverflow> for StoreError<E> {
+    fn from(_: SumOverflow) -> Self {
+        StoreError::SumOverflow
+    }
+}
+
+pub struct StoredTree<S, D = Sha256> {
+    store: S,
+    // Kept in memory so committing never touches the store
+    root: Entry,
+    hasher: PhantomData<fn() -> D>,
+}
+
+impl<S: NodeStore, D: TreeDigest> StoredTree<S, D> {
+    // Streams 2^n values into an empty store
+    pub fn build(store: S, values: impl IntoIterator<Item = u64>) -> Result<Self, StoreError<S::Error>> {
+        Self::build_with_encoding(store, values, Encoding::default())
+    }
+
+    pub fn build_with_encoding(
+        mut store: S,
+        values: impl IntoIterator<Item = u64>,
+        encoding: Encoding,
+    ) -> Result<Self, StoreError<S::Error>> {
+        assert!(store.is_empty(), "store already holds a tree");
+        // Complete subtrees still waiting for a sibling, with their index
+        let mut roots: Vec<(usize, Entry)> = Vec::new();
+
+        for value in values {
+            let mut node = (store.len(), leaf_entry::<D>(value, None, encoding));
+            store.put(node.0, node.1.clone()).map_err(StoreError::Backend)?;
+            // bubble up new leaf
+            while roots.last().is_some_and(|(_, root)| root.height() == node.1.height()) {
+                let (index, sibling) = roots.pop().unwrap();
+                let branch = join_entries::<D>((index, &sibling), (node.0, &node.1))?;
+                node = (store.len(), branch);
+                store.put(node.0, node.1.clone()).map_err(StoreError::Backend)?;
+            }
+            roots.push(node);
+        }
+
+        // We only deal with 2^n values
+        assert!(roots.len() == 1);
+        Ok(Self {
+            store,
+            root: roots.pop().unwrap().1,
+            hasher: PhantomData,
+        })
+    }
+
+    // Picks up a tree built earlier into the same store
+    pub fn open(store: S) -> Result<Self, StoreError<S::Error>> {
+        if store.is_empty() {
+            return Err(StoreError::Empty);
+        }
+        let root = store.get(store.len() - 1).map_err(StoreError::Backend)?;
+        Ok(Self {
+            store,
+            root,
+            hasher: PhantomData,
+        })
+    }
+
+    pub fn into_store(self) -> S {
+        self.store
+    }
+
+    pub fn commit(&self) -> Commitment<D> {
+        Commitment::from(&self.root)
+    }
+
+    // Same proofs as `Node::prove`, reading one level of the path at a time
+    pub fn prove(&self, position: usize) -> Result<Proof<D>, S::Error> {
+        assert!(
+            position >> self.root.height() == 0,
+            "position {} out of range",
+            position
+        );
+        let mut siblings = Vec::new();
+
+        let mut current = self.root.clone();
+        let (node, blinding) = loop {
+            match current {
+                Entry::Branch {
+                    height, left, right, ..
+                } => {
+                    let mask = 1usize << (height - 1);
+                    let (next, sibling) = if (position & mask) == 0 {
+                        (left, right)
+                    } else {
+                        (right, left)
+                    };
+                    siblings.push(Commitment::from(&self.store.get(sibling)?));
+                    current = self.store.get(next)?;
+                }
+                Entry::Leaf { ref blinding, .. } => break (Commitment::from(&current), blinding.clone()),
+            }
+        };
+
+        siblings.reverse();
+
+        Ok(Proof {
+            node,
+            siblings,
+            index: position,
+            encoding: self.root.encoding(),
+            blinding,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ExclusiveAllotmentProof, MerkleTree, Node};
+
+    #[test]
+    fn test_stored_tree_matches_node() {
+        let values: Vec<u64> = (1..=16).collect();
+        let node: Node = Node::new(values.clone());
+        let stored = StoredTree::<Vec<Entry>, Sha256>::build(Vec::new(), values.iter().copied()).unwrap();
+        assert_eq!(stored.commit(), node.commit());
+        for i in 0..values.len() {
+            let proof = stored.prove(i).unwrap();
+            assert_eq!(proof, node.prove(i));
+            assert!(proof.verify(&stored.commit()));
+        }
+
+        // The store holds exactly the arena the in-memory builder would produce
+        let store = stored.into_store();
+        assert!(store == node.entries);
+        let reopened = StoredTree::<_, Sha256>::open(store).unwrap();
+        assert_eq!(reopened.commit(), node.commit());
+
+        assert!(matches!(
+            StoredTree::<Vec<Entry>, Sha256>::open(Vec::new()),
+            Err(StoreError::Empty)
+        ));
+        assert!(matches!(
+            StoredTree::<Vec<Entry>, Sha256>::build(Vec::new(), [u64::MAX, 1]),
+            Err(StoreError::SumOverflow)
+        ));
+    }
+}
@@ -0,0 +1,178 @@
+// Commitments to aligned blocks of leaves and proofs that such a block is part of
+// the whole tree. A sharded prover can own one block as a tree of its own and hand
+// out ordinary proofs against its root, while a coordinator joins the shards and
+// proves each shard root into the global one; `NestedProof` chains the two.
+
+use alloc::vec::Vec;
+use core::ops::Range;
+
+use super::{check_path, Commitment, Encoding, Entry, Node, Proof, Sha256, TreeDigest, VerifyError};
+
+impl<D: TreeDigest> Node<D> {
+    // Height and index of the node covering exactly `range`
+    fn subtree_position(&self, range: &Range<usize>) -> (usize, usize) {
+        let len = range.len();
+        assert!(
+            len.is_power_of_two() && range.start & (len - 1) == 0,
+            "{:?} is not an aligned block of leaves",
+            range
+        );
+        assert!(range.end <= 1 << self.height(), "{:?} out of range", range);
+        (len.trailing_zeros() as usize, range.start / len)
+    }
+
+    // `range` must have a power-of-two length and start at a multiple of it
+    pub fn commit_subtree(&self, range: Range<usize>) -> Commitment<D> {
+        let (height, index) = self.subtree_position(&range);
+        self.descendant(height, index).into()
+    }
+
+    pub fn prove_subtree(&self, range: Range<usize>) -> SubtreeProof<D> {
+        let (level, index) = self.subtree_position(&range);
+        let mut siblings = Vec::new();
+        let mut current = self.root();
+        while let Entry::Branch {
+            height, left, right, ..
+        } = current
+        {
+            if *height == level {
+                break;
+            }
+            let (next, sibling) = if (index & (1usize << (height - 1 - level))) == 0 {
+                (left, right)
+            } else {
+                (right, left)
+            };
+            siblings.push(Commitment::from(&self.entries[*sibling]));
+            current = &self.entries[*next];
+        }
+        siblings.reverse();
+        SubtreeProof {
+            subtree: current.into(),
+            height: level,
+            index,
+            siblings,
+            encoding: self.encoding(),
+        }
+    }
+}
+
+pub struct SubtreeProof<D = Sha256> {
+    pub subtree: Commitment<D>,
+    pub height: usize,
+    // Among the nodes of `height`, so the block starts at leaf `index << height`
+    pub index: usize,
+    pub siblings: Vec<Commitment<D>>,
+    pub encoding: Encoding,
+}
+
+impl<D: TreeDigest> SubtreeProof<D> {
+    pub fn leaves(&self) -> Range<usize> {
+        self.index << self.height..(self.index + 1) << self.height
+    }
+
+    pub fn verify(&self, root_commitment: &Commitment<D>) -> bool {
+        self.verify_detailed(root_commitment).is_ok()
+    }
+
+    pub fn verify_detailed(&self, root_commitment: &Commitment<D>) -> Result<(), VerifyError> {
+        if self.siblings.len() < usize::BITS as usize && self.index >> self.siblings.len() != 0 {
+            return Err(VerifyError::LengthMismatch);
+        }
+        check_path(
+            self.encoding,
+            self.subtree,
+            self.height,
+            self.index,
+            &self.siblings,
+            root_commitment,
+        )
+    }
+}
+
+// A leaf proven into its block and the block proven into the root
+pub struct NestedProof<D = Sha256> {
+    pub leaf: Proof<D>,
+    pub subtree: SubtreeProof<D>,
+}
+
+impl<D: TreeDigest> NestedProof<D> {
+    // The leaf's position in the whole tree
+    pub fn position(&self) -> usize {
+        self.subtree.leaves().start + self.leaf.index
+    }
+
+    pub fn verify(&self, root_commitment: &Commitment<D>) -> bool {
+        self.verify_detailed(root_commitment).is_ok()
+    }
+
+    pub fn verify_detailed(&self, root_commitment: &Commitment<D>) -> Result<(), VerifyError> {
+        // Both halves must describe the same block, and in the same encoding
+        if self.leaf.siblings.len() != self.subtree.height || self.leaf.encoding != self.subtree.encoding {
+            return Err(VerifyError::LengthMismatch);
+        }
+        self.leaf.verify_detailed(&self.subtree.subtree)?;
+        self.subtree.verify_detailed(root_commitment)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::MerkleTree;
+
+    #[test]
+    fn test_nested_proofs() {
+        let tree_root: Node = Node::new((1..=16).collect());
+        let root_commitment = tree_root.commit();
+        assert!(tree_root.commit_subtree(0..16) == root_commitment);
+        assert_eq!(tree_root.commit_subtree(4..8).sum, 5 + 6 + 7 + 8);
+        assert_eq!(tree_root.commit_subtree(9..10).sum, 10);
+
+        for range in [0..16, 8..16, 4..8, 6..8, 13..14] {
+            let proof = tree_root.prove_subtree(range.clone());
+            assert_eq!(proof.leaves(), range);
+            assert!(proof.verify(&root_commitment), "Failed {:?}", range);
+        }
+        let mut moved = tree_root.prove_subtree(4..8);
+        moved.index = 2;
+        assert_eq!(
+            moved.verify_detailed(&root_commitment),
+            Err(VerifyError::HashMismatch { height: 4 })
+        );
+        let mut long = tree_root.prove_subtree(4..8);
+        long.index = 4;
+        assert_eq!(long.verify_detailed(&root_commitment), Err(VerifyError::LengthMismatch));
+
+        // Each shard builds its own tree, and the coordinator joins them
+        let shards: Vec<Node> = (0..4)
+            .map(|shard| Node::new((4 * shard + 1..=4 * shard + 4).collect()))
+            .collect();
+        let joined = Node::try_new_branch(
+            Node::try_new_branch(shards[0].clone(), shards[1].clone()).unwrap(),
+            Node::try_new_branch(shards[2].clone(), shards[3].clone()).unwrap(),
+        )
+        .unwrap();
+        assert!(joined.commit() == root_commitment);
+        let nested = NestedProof {
+            leaf: shards[2].prove(1),
+            subtree: joined.prove_subtree(8..12),
+        };
+        assert_eq!(nested.position(), 9);
+        assert!(nested.verify(&root_commitment));
+
+        let wrong_shard = NestedProof {
+            leaf: shards[1].prove(1),
+            subtree: joined.prove_subtree(8..12),
+        };
+        assert!(!wrong_shard.verify(&root_commitment));
+        let wrong_depth = NestedProof {
+            leaf: shards[2].prove(1),
+            subtree: joined.prove_subtree(8..16),
+        };
+        assert_eq!(
+            wrong_depth.verify_detailed(&root_commitment),
+            Err(VerifyError::LengthMismatch)
+        );
+    }
+}
@@ -0,0 +1,181 @@
+// Proptest strategies and invariants for code built on `MerkleTree` and
+// `ExclusiveAllotmentProof`, so other implementations and wrappers can be held
+// to the same properties as the trees here. Strategies only go through the
+// traits; corruption works on whatever bytes a proof encodes to.
+//
+// Trees get 2^n leaves, which every implementation accepts, each below 2^32 so no
+// sum of up to 2^32 of them overflows.
+
+use alloc::vec::Vec;
+use core::fmt::Debug;
+
+use proptest::prelude::*;
+use proptest::test_runner::TestCaseError;
+
+use super::{ExclusiveAllotmentProof, MerkleTree, SumCommitment};
+
+pub fn leaf_values(max_height: u32) -> impl Strategy<Value = Vec<u64>> {
+    (0..=max_height).prop_flat_map(|height| prop::collection::vec(0..=u64::from(u32::MAX), 1 << height))
+}
+
+// Leaf values with one of the positions
+pub fn values_and_position(max_height: u32) -> impl Strategy<Value = (Vec<u64>, usize)> {
+    leaf_values(max_height).prop_flat_map(|values| {
+        let len = values.len();
+        (Just(values), 0..len)
+    })
+}
+
+pub fn trees<C, P, T>(max_height: u32) -> impl Strategy<Value = T>
+where
+    C: SumCommitment,
+    P: ExclusiveAllotmentProof<C>,
+    T: MerkleTree<C, P> + Debug,
+{
+    leaf_values(max_height).prop_map(T::new)
+}
+
+pub fn trees_and_positions<C, P, T>(max_height: u32) -> impl Strategy<Value = (T, usize)>
+where
+    C: SumCommitment,
+    P: ExclusiveAllotmentProof<C>,
+    T: MerkleTree<C, P> + Debug,
+{
+    values_and_position(max_height).prop_map(|(values, position)| (T::new(values), position))
+}
+
+// Leaf values, the position proven and another position given a different value
+#[derive(Clone, Debug)]
+pub struct Mutation {
+    pub values: Vec<u64>,
+    pub position: usize,
+    pub changed: usize,
+    pub new_value: u64,
+}
+
+impl Mutation {
+    pub fn mutated_values(&self) -> Vec<u64> {
+        let mut values = self.values.clone();
+        values[self.changed] = self.new_value;
+        values
+    }
+}
+
+pub fn mutations(max_height: u32) -> impl Strategy<Value = Mutation> {
+    values_and_position(max_height).prop_flat_map(|(values, position)| {
+        let len = values.len();
+        (Just(values), Just(position), 0..len, 1..=u64::from(u32::MAX)).prop_map(
+            |(values, position, changed, delta)| Mutation {
+                // Wrapping within 2^32 keeps the new value in range and never equal to the old one
+                new_value: (values[changed] + delta) % (u64::from(u32::MAX) + 1),
+                values,
+                position,
+                changed,
+            },
+        )
+    })
+}
+
+// A byte of an encoding XORed with a nonzero mask
+#[derive(Copy, Clone, Debug)]
+pub struct Corruption {
+    // Taken modulo the length of the encoding
+    pub offset: usize,
+    pub mask: u8,
+}
+
+impl Corruption {
+    pub fn apply(&self, bytes: &mut [u8]) {
+        let offset = self.offset % bytes.len();
+        bytes[offset] ^= self.mask;
+    }
+}
+
+pub fn corruptions() -> impl Strategy<Value = Corruption> {
+    (any::<usize>(), 1..=u8::MAX).prop_map(|(offset, mask)| Corruption { offset, mask })
+}
+
+// The proof for `position` carries the position and verifies against the root
+pub fn check_proof_verifies<C, P, T>(tree: &T, position: usize) -> Result<(), TestCaseError>
+where
+    C: SumCommitment,
+    P: ExclusiveAllotmentProof<C>,
+    T: MerkleTree<C, P>,
+{
+    let proof = tree.prove(position);
+    prop_assert_eq!(proof.position(), position);
+    prop_assert!(
+        proof.verify(&tree.commit()),
+        "proof for position {} does not verify",
+        position
+    );
+    Ok(())
+}
+
+// A proof from before a change does not verify against the root after it, even
+// when the change is at another position
+pub fn check_stale_proof_rejected<C, P, T>(mutation: &Mutation) -> Result<(), TestCaseError>
+where
+    C: SumCommitment,
+    P: ExclusiveAllotmentProof<C>,
+    T: MerkleTree<C, P>,
+{
+    let proof = T::new(mutation.values.clone()).prove(mutation.position);
+    let changed = T::new(mutation.mutated_values()).commit();
+    prop_assert!(
+        !proof.verify(&changed),
+        "proof for position {} verifies after position {} changed",
+        mutation.position,
+        mutation.changed
+    );
+    Ok(())
+}
+
+// `accepts` decodes and verifies; it must take the encoding as given and refuse it
+// with any single byte corrupted
+pub fn check_corruption_rejected(
+    bytes: &[u8],
+    corruption: Corruption,
+    accepts: impl Fn(&[u8]) -> bool,
+) -> Result<(), TestCaseError> {
+    prop_assert!(accepts(bytes), "the encoding is refused before any corruption");
+    let mut corrupted = bytes.to_vec();
+    corruption.apply(&mut corrupted);
+    prop_assert!(
+        !accepts(&corrupted),
+        "accepted with byte {} XORed with {:#04x}",
+        corruption.offset % bytes.len(),
+        corruption.mask
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::skiplist::{SkipList, SkipProof};
+    use crate::{Commitment, Node, Proof, Sha256};
+
+    proptest! {
+        #[test]
+        fn test_tree_invariants(
+            (tree, position) in trees_and_positions::<_, Proof, Node>(6),
+            (list, list_position) in trees_and_positions::<_, SkipProof, SkipList>(6),
+            mutation in mutations(6),
+            corruption in corruptions(),
+        ) {
+            check_proof_verifies(&tree, position)?;
+            check_proof_verifies(&list, list_position)?;
+            check_stale_proof_rejected::<_, Proof, Node>(&mutation)?;
+            check_stale_proof_rejected::<_, SkipProof, SkipList>(&mutation)?;
+
+            let root_commitment = tree.commit();
+            check_corruption_rejected(&tree.prove(position).to_bytes(), corruption, |bytes| {
+                Proof::<Sha256>::from_bytes(bytes).is_ok_and(|proof| proof.verify(&root_commitment))
+            })?;
+            check_corruption_rejected(&root_commitment.to_bytes(), corruption, |bytes| {
+                Commitment::<Sha256>::from_bytes(bytes).is_ok_and(|root| tree.prove(position).verify(&root))
+            })?;
+        }
+    }
+}
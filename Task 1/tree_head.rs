@@ -0,0 +1,171 @@
+// Signed tree heads: the operator signs the root hash, total, leaf count, time
+// and version of each published tree with Ed25519, so a proof is only ever
+// checked against a root the operator stands behind. Verifiers check the head's
+// signature first and only then the inclusion proof.
+//
+// Signed message: domain | version (u64) | timestamp (u64) | leaf count (u64)
+//                 | sum (u64) | hash (32 bytes)
+// Keys are the raw 32-byte Ed25519 secret and public keys.
+
+use core::fmt;
+
+use ed25519_dalek::{Signature, Signer, SigningKey, VerifyingKey};
+
+use super::{Commitment, ExclusiveAllotmentProof, Proof, Sha256, TreeDigest};
+
+const TREE_HEAD_DOMAIN: &[u8] = b"merkle-sum-tree/tree-head/v1";
+const TREE_HEAD_MESSAGE_LEN: usize = TREE_HEAD_DOMAIN.len() + 4 * 8 + 32;
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum TreeHeadError {
+    InvalidKey,
+    BadSignature,
+    // The proof is for a position past the signed leaf count, i.e. padding
+    PositionOutOfRange(usize),
+    InvalidProof,
+}
+
+impl fmt::Display for TreeHeadError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TreeHeadError::InvalidKey => write!(f, "not a valid Ed25519 key"),
+            TreeHeadError::BadSignature => write!(f, "tree head signature does not verify"),
+            TreeHeadError::PositionOutOfRange(position) => {
+                write!(f, "position {} is past the signed leaf count", position)
+            }
+            TreeHeadError::InvalidProof => write!(f, "proof does not lead to the signed root"),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for TreeHeadError {}
+
+pub fn load_signing_key(bytes: &[u8]) -> Result<SigningKey, TreeHeadError> {
+    let secret: [u8; 32] = bytes.try_into().map_err(|_| TreeHeadError::InvalidKey)?;
+    Ok(SigningKey::from_bytes(&secret))
+}
+
+pub fn load_verifying_key(bytes: &[u8]) -> Result<VerifyingKey, TreeHeadError> {
+    let public: [u8; 32] = bytes.try_into().map_err(|_| TreeHeadError::InvalidKey)?;
+    VerifyingKey::from_bytes(&public).map_err(|_| TreeHeadError::InvalidKey)
+}
+
+pub struct SignedRoot<D = Sha256> {
+    pub root: Commitment<D>,
+    // Accounts in the tree, not counting padding leaves
+    pub leaves: u64,
+    // Seconds since the Unix epoch
+    pub timestamp: u64,
+    // Increases with every tree the operator publishes
+    pub version: u64,
+    pub signature: Signature,
+}
+
+impl<D: TreeDigest> SignedRoot<D> {
+    pub fn sign(key: &SigningKey, root: Commitment<D>, leaves: u64, timestamp: u64, version: u64) -> Self {
+        let signature = key.sign(&tree_head_message(&root, leaves, timestamp, version));
+        Self {
+            root,
+            leaves,
+            timestamp,
+            version,
+            signature,
+        }
+    }
+
+    // Strict verification, so no other signature over the same head is accepted
+    pub fn verify(&self, key: &VerifyingKey) -> Result<(), TreeHeadError> {
+        let message = tree_head_message(&self.root, self.leaves, self.timestamp, self.version);
+        key.verify_strict(&message, &self.signature)
+            .map_err(|_| TreeHeadError::BadSignature)
+    }
+
+    pub fn verify_inclusion(&self, key: &VerifyingKey, proof: &Proof<D>) -> Result<(), TreeHeadError> {
+        self.verify(key)?;
+        if proof.position() as u64 >= self.leaves {
+            return Err(TreeHeadError::PositionOutOfRange(proof.position()));
+        }
+        if !proof.verify(&self.root) {
+            return Err(TreeHeadError::InvalidProof);
+        }
+        Ok(())
+    }
+}
+
+fn tree_head_message<D>(
+    root: &Commitment<D>,
+    leaves: u64,
+    timestamp: u64,
+    version: u64,
+) -> [u8; TREE_HEAD_MESSAGE_LEN] {
+    let mut message = [0u8; TREE_HEAD_MESSAGE_LEN];
+    let fields = [
+        TREE_HEAD_DOMAIN,
+        &version.to_be_bytes(),
+        &timestamp.to_be_bytes(),
+        &leaves.to_be_bytes(),
+        &root.sum.to_be_bytes(),
+        &root.hash,
+    ];
+    let mut offset = 0;
+    for field in fields {
+        message[offset..offset + field.len()].copy_from_slice(field);
+        offset += field.len();
+    }
+    message
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::vec;
+
+    use crate::{MerkleTree, Node};
+
+    #[test]
+    fn test_signed_root() {
+        // The fourth leaf pads the three accounts to a power of two
+        let tree_root: Node = Node::new(vec![10, 20, 30, 0]);
+        let key = load_signing_key(&[7; 32]).unwrap();
+        let verifier = load_verifying_key(key.verifying_key().as_bytes()).unwrap();
+        let head = SignedRoot::sign(&key, tree_root.commit(), 3, 1_700_000_000, 4);
+        assert_eq!(head.verify(&verifier), Ok(()));
+        for i in 0..3 {
+            assert_eq!(head.verify_inclusion(&verifier, &tree_root.prove(i)), Ok(()));
+        }
+        // The padding leaf is in the tree but not among the signed accounts
+        assert_eq!(
+            head.verify_inclusion(&verifier, &tree_root.prove(3)),
+            Err(TreeHeadError::PositionOutOfRange(3))
+        );
+        let other: Node = Node::new(vec![10, 20, 31, 0]);
+        assert_eq!(
+            head.verify_inclusion(&verifier, &other.prove(0)),
+            Err(TreeHeadError::InvalidProof)
+        );
+
+        // Every signed field is covered, and a head signed by another key is refused
+        // before any proof is looked at
+        let mut replayed = SignedRoot::sign(&key, tree_root.commit(), 3, 1_700_000_000, 4);
+        replayed.version = 5;
+        assert_eq!(replayed.verify(&verifier), Err(TreeHeadError::BadSignature));
+        let mut shrunk = SignedRoot::sign(&key, tree_root.commit(), 3, 1_700_000_000, 4);
+        shrunk.leaves = 2;
+        assert_eq!(shrunk.verify(&verifier), Err(TreeHeadError::BadSignature));
+        let forged = SignedRoot::sign(
+            &load_signing_key(&[8; 32]).unwrap(),
+            tree_root.commit(),
+            3,
+            1_700_000_000,
+            4,
+        );
+        assert_eq!(
+            forged.verify_inclusion(&verifier, &other.prove(0)),
+            Err(TreeHeadError::BadSignature)
+        );
+
+        assert_eq!(load_signing_key(&[7; 31]).err(), Some(TreeHeadError::InvalidKey));
+        assert_eq!(load_verifying_key(&[7; 33]).err(), Some(TreeHeadError::InvalidKey));
+    }
+}
@@ -0,0 +1,64 @@
+// Digests wider than the 32 bytes a commitment holds, e.g. SHA-512, BLAKE2b or
+// SHA3-512, cut down to their first 32 bytes, so they back a tree like any
+// `TreeDigest` and every proof, codec and store keeps working unchanged. Digests
+// narrower than 32 bytes are rejected at compile time. Note `Truncated<Sha512>`
+// is not SHA-512/256, which uses its own initial values; `sha2::Sha512_256` is
+// already a `TreeDigest` as it is.
+
+use sha2::digest::consts::U32;
+use sha2::digest::typenum::{IsGreaterOrEqual, True};
+use sha2::digest::{FixedOutput, HashMarker, Output, OutputSizeUser, Update};
+
+#[derive(Clone, Default)]
+pub struct Truncated<D>(D);
+
+impl<D: HashMarker> HashMarker for Truncated<D> {}
+
+impl<D> OutputSizeUser for Truncated<D> {
+    type OutputSize = U32;
+}
+
+impl<D: Update> Update for Truncated<D> {
+    fn update(&mut self, data: &[u8]) {
+        self.0.update(data);
+    }
+}
+
+impl<D: FixedOutput> FixedOutput for Truncated<D>
+where
+    D::OutputSize: IsGreaterOrEqual<U32, Output = True>,
+{
+    fn finalize_into(self, out: &mut Output<Self>) {
+        let full = self.0.finalize_fixed();
+        out.copy_from_slice(&full[..32]);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::vec::Vec;
+
+    use sha2::digest::Digest;
+    use sha2::{Sha256, Sha512, Sha512_256};
+
+    use super::*;
+    use crate::{hash_bytes, ExclusiveAllotmentProof, MerkleTree, Node};
+
+    #[test]
+    fn test_truncated_digest() {
+        assert_eq!(hash_bytes::<Truncated<Sha512>>(b"leaf"), Sha512::digest(b"leaf")[..32]);
+        // Cutting a 32-byte digest changes nothing
+        assert_eq!(hash_bytes::<Truncated<Sha256>>(b"leaf"), hash_bytes::<Sha256>(b"leaf"));
+
+        let values: Vec<u64> = (1..=8).collect();
+        let wide: Node<Truncated<Sha512>> = Node::new(values.clone());
+        let root_commitment = wide.commit();
+        assert_eq!(root_commitment.sum, 36);
+        assert!((0..8).all(|i| wide.prove(i).verify(&root_commitment)));
+        assert!(root_commitment.hash != Node::<Sha256>::new(values.clone()).commit().hash);
+
+        let standard: Node<Sha512_256> = Node::new(values);
+        assert!(standard.prove(3).verify(&standard.commit()));
+        assert!(standard.commit().hash != root_commitment.hash);
+    }
+}
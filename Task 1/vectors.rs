@@ -0,0 +1,169 @@
+// Deterministic test vectors for the hashing rules and proof encodings, so other
+// implementations (a JS verifier, auditor tooling) can check they agree with this
+// crate byte for byte. `mst vectors` prints them and test_vectors.json is the
+// published copy, which the test below holds this crate to.
+//
+// Only V2 is covered, since V0 and V1 hash heights as a native usize. Hashes, salts,
+// user ids and encoded proofs are lowercase hex. Values and sums are decimal
+// strings, as JSON numbers lose precision above 2^53 in most parsers.
+
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::vec;
+use alloc::vec::Vec;
+use core::fmt::Write;
+
+use serde_json::{json, Value};
+
+use super::{hash_bytes, Blinding, Commitment, Entry, MerkleTree, Node, Sha256};
+
+const VECTORS_VERSION: u64 = 1;
+
+fn to_hex(bytes: &[u8]) -> String {
+    let mut hex = String::with_capacity(2 * bytes.len());
+    for byte in bytes {
+        write!(hex, "{:02x}", byte).unwrap();
+    }
+    hex
+}
+
+fn commitment_json(commitment: &Commitment) -> Value {
+    json!({ "sum": commitment.sum.to_string(), "hash": to_hex(&commitment.hash) })
+}
+
+// (name, values, whether leaves are blinded)
+fn cases() -> Vec<(&'static str, Vec<u64>, bool)> {
+    vec![
+        ("single_leaf", vec![7], false),
+        ("four_leaves", vec![1, 2, 3, 4], false),
+        ("eight_leaves_with_zeros", vec![0, 5, 0, 0, 9, 1, 0, 3], false),
+        ("large_sums", vec![u64::MAX - 1, 1], false),
+        ("blinded", vec![100, 250, 0, 5], true),
+    ]
+}
+
+pub fn test_vectors() -> Value {
+    let cases: Vec<Value> = cases()
+        .into_iter()
+        .map(|(name, values, blinded)| {
+            let tree_root = if blinded {
+                // Salts and user ids are derived from the position so the vectors never change
+                let leaves = values
+                    .iter()
+                    .enumerate()
+                    .map(|(i, value)| {
+                        let blinding = Blinding {
+                            salt: hash_bytes::<Sha256>(format!("salt {}", i).as_bytes()),
+                            user_id: Some(hash_bytes::<Sha256>(format!("user {}", i).as_bytes())),
+                        };
+                        (*value, blinding)
+                    })
+                    .collect();
+                Node::<Sha256>::try_new_blinded(leaves).unwrap()
+            } else {
+                Node::<Sha256>::try_new(values.clone()).unwrap()
+            };
+
+            let leaves: Vec<Value> = (0..values.len())
+                .map(|position| match tree_root.leaf(position) {
+                    Entry::Leaf {
+                        value,
+                        commitment,
+                        blinding,
+                        ..
+                    } => json!({
+                        "value": value.to_string(),
+                        "salt": blinding.as_ref().map(|blinding| to_hex(&blinding.salt)),
+                        "user_id": blinding.as_ref().and_then(|blinding| blinding.user_id).map(|id| to_hex(&id)),
+                        "hash": to_hex(commitment),
+                    }),
+                    Entry::Branch { .. } => unreachable!(),
+                })
+                .collect();
+            let proofs: Vec<Value> = (0..values.len())
+                .map(|position| {
+                    let proof = tree_root.prove(position);
+                    json!({
+                        "position": position,
+                        "siblings": proof.siblings.iter().map(commitment_json).collect::<Vec<_>>(),
+                        "bytes": to_hex(&proof.to_bytes()),
+                        "compact": to_hex(&proof.to_compact_bytes()),
+                    })
+                })
+                .collect();
+
+            json!({
+                "name": name,
+                "leaves": leaves,
+                "root": commitment_json(&tree_root.commit()),
+                "proofs": proofs,
+            })
+        })
+        .collect();
+
+    json!({
+        "version": VECTORS_VERSION,
+        "digest": "sha256",
+        "encoding": "V2",
+        "cases": cases,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{leaf_digest, Encoding, ExclusiveAllotmentProof, Proof};
+
+    fn from_hex(hex: &Value) -> Vec<u8> {
+        let hex = hex.as_str().unwrap();
+        (0..hex.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).unwrap())
+            .collect()
+    }
+
+    fn decimal(value: &Value) -> u64 {
+        value.as_str().unwrap().parse().unwrap()
+    }
+
+    fn commitment(value: &Value) -> Commitment {
+        Commitment::new(decimal(&value["sum"]), from_hex(&value["hash"]).try_into().unwrap())
+    }
+
+    #[test]
+    fn test_published_vectors() {
+        let published: Value = serde_json::from_str(include_str!("test_vectors.json")).unwrap();
+        assert_eq!(published, test_vectors());
+
+        // Check the file on its own as another implementation would, from the
+        // hashing rules and encodings rather than by rebuilding the tree
+        for case in published["cases"].as_array().unwrap() {
+            let root = commitment(&case["root"]);
+            let leaves = case["leaves"].as_array().unwrap();
+            let mut total = 0u64;
+            for (leaf, proof) in leaves.iter().zip(case["proofs"].as_array().unwrap()) {
+                let value = decimal(&leaf["value"]);
+                let blinding = (!leaf["salt"].is_null()).then(|| Blinding {
+                    salt: from_hex(&leaf["salt"]).try_into().unwrap(),
+                    user_id: Some(from_hex(&leaf["user_id"]).try_into().unwrap()),
+                });
+                let hash = leaf_digest::<Sha256>(Encoding::V2, value, blinding.as_ref());
+                assert_eq!(to_hex(&hash), leaf["hash"], "Failed {}", case["name"]);
+                total += value;
+
+                let decoded = Proof::<Sha256>::from_bytes(&from_hex(&proof["bytes"])).unwrap();
+                let siblings: Vec<Commitment> = proof["siblings"].as_array().unwrap().iter().map(commitment).collect();
+                assert_eq!(decoded.siblings, siblings);
+                assert!(
+                    decoded.verify(&root),
+                    "Failed {} at {}",
+                    case["name"],
+                    proof["position"]
+                );
+                let compact = Proof::<Sha256>::from_compact_bytes(&from_hex(&proof["compact"]), None).unwrap();
+                assert!(compact == decoded);
+            }
+            assert_eq!(total, root.sum);
+        }
+    }
+}
@@ -0,0 +1,308 @@
+// Wide tree whose nodes commit to their children with KZG polynomial commitments
+// over BLS12-381 rather than by hashing them in pairs, so a path is a few openings
+// per level instead of a sibling per child: with 256 children per node, 2^24 leaves
+// are three levels deep. Each node commits to two polynomials over the width-th
+// roots of unity, one through its children's sums and one through their digests.
+// A sum polynomial of degree below the width sums to width * p(0) over the domain,
+// so a single opening at 0 shows the node's total.
+//
+// Openings only show the sums of the path's own children. The other children's
+// sums are field elements, so unlike a hash-based sum tree nothing here keeps them
+// from being "negative" and cancelling liabilities out; that needs range proofs
+// on the hidden sums, which this tree does not provide.
+//
+// Leaf digest: tag (u8) | version (u8) | value (u64)
+// Node digest: tag (u8) | version (u8) | sum (u64) | sums commitment | digests commitment,
+//              commitments compressed (48 bytes). Digests enter the field big-endian, mod r.
+
+use alloc::vec;
+use alloc::vec::Vec;
+
+use ark_bls12_381::{Bls12_381, Fr, G1Affine, G1Projective, G2Affine};
+use ark_ec::pairing::Pairing;
+use ark_ec::{AffineRepr, CurveGroup, VariableBaseMSM};
+use ark_ff::{Field, One, PrimeField, Zero};
+use ark_poly::{EvaluationDomain, Radix2EvaluationDomain};
+use ark_serialize::CanonicalSerialize;
+
+use super::{hash_bytes, Commitment, Sha256, SumOverflow, V2_VERSION};
+
+const VERKLE_LEAF_TAG: u8 = 0x0d;
+const VERKLE_NODE_TAG: u8 = 0x0e;
+
+fn verkle_leaf_digest(value: u64) -> [u8; 32] {
+    hash_bytes::<Sha256>(&[[VERKLE_LEAF_TAG, V2_VERSION].as_slice(), &value.to_be_bytes()].concat())
+}
+
+fn verkle_node_digest(sum: u64, sums: &G1Affine, digests: &G1Affine) -> [u8; 32] {
+    let mut serialized = vec![VERKLE_NODE_TAG, V2_VERSION];
+    serialized.extend_from_slice(&sum.to_be_bytes());
+    sums.serialize_compressed(&mut serialized)
+        .expect("writing to a vector cannot fail");
+    digests
+        .serialize_compressed(&mut serialized)
+        .expect("writing to a vector cannot fail");
+    hash_bytes::<Sha256>(&serialized)
+}
+
+fn to_field(hash: &[u8; 32]) -> Fr {
+    Fr::from_be_bytes_mod_order(hash)
+}
+
+// Powers of a secret tau from a trusted setup, as many as a node has children
+pub struct KzgSetup {
+    // [tau^i]G1 for i below the width
+    powers: Vec<G1Affine>,
+    tau_g2: G2Affine,
+}
+
+impl KzgSetup {
+    // From a powers-of-tau ceremony; the width is the number of G1 powers
+    pub fn from_powers(powers: Vec<G1Affine>, tau_g2: G2Affine) -> Self {
+        assert!(
+            powers.len() >= 2 && powers.len().is_power_of_two(),
+            "width {} is not a power of two",
+            powers.len()
+        );
+        Self { powers, tau_g2 }
+    }
+
+    // Whoever knows `secret` can open any commitment to anything: tests and demos only
+    pub fn insecure_from_secret(secret: &[u8], width: usize) -> Self {
+        let tau = to_field(&hash_bytes::<Sha256>(secret));
+        let mut powers = Vec::with_capacity(width);
+        let mut power = Fr::one();
+        for _ in 0..width {
+            powers.push((G1Affine::generator() * power).into_affine());
+            power *= tau;
+        }
+        Self::from_powers(powers, (G2Affine::generator() * tau).into_affine())
+    }
+
+    pub fn width(&self) -> usize {
+        self.powers.len()
+    }
+
+    fn domain(&self) -> Radix2EvaluationDomain<Fr> {
+        Radix2EvaluationDomain::new(self.width()).expect("the width is a power of two")
+    }
+
+    fn commit(&self, coefficients: &[Fr]) -> G1Affine {
+        G1Projective::msm_unchecked(&self.powers[..coefficients.len()], coefficients).into_affine()
+    }
+
+    // Commits to (p(X) - p(z)) / (X - z), by synthetic division
+    fn open(&self, coefficients: &[Fr], z: Fr) -> G1Affine {
+        let mut quotient = vec![Fr::zero(); coefficients.len().saturating_sub(1)];
+        let mut carry = Fr::zero();
+        for i in (1..coefficients.len()).rev() {
+            carry = coefficients[i] + carry * z;
+            quotient[i - 1] = carry;
+        }
+        self.commit(&quotient)
+    }
+
+    // e(C - [y]G1, G2) == e(proof, [tau - z]G2)
+    fn check(&self, commitment: &G1Affine, z: Fr, y: Fr, proof: &G1Affine) -> bool {
+        let shifted = (*commitment - G1Affine::generator() * y).into_affine();
+        let divisor = (self.tau_g2 - G2Affine::generator() * z).into_affine();
+        Bls12_381::multi_pairing([shifted, -*proof], [G2Affine::generator(), divisor]).is_zero()
+    }
+}
+
+struct VerkleNode {
+    // Coefficients of the polynomials through the children's sums and digests
+    sums: Vec<Fr>,
+    digests: Vec<Fr>,
+    sums_commitment: G1Affine,
+    digests_commitment: G1Affine,
+    commitment: Commitment,
+}
+
+impl VerkleNode {
+    fn new(setup: &KzgSetup, children: &[Commitment]) -> Result<Self, SumOverflow> {
+        let domain = setup.domain();
+        let mut sum_evaluations = vec![Fr::zero(); setup.width()];
+        let mut digest_evaluations = vec![Fr::zero(); setup.width()];
+        let mut sum = 0u64;
+        for (slot, child) in children.iter().enumerate() {
+            sum = sum.checked_add(child.sum).ok_or(SumOverflow)?;
+            sum_evaluations[slot] = Fr::from(child.sum);
+            digest_evaluations[slot] = to_field(&child.hash);
+        }
+        let sums = domain.ifft(&sum_evaluations);
+        let digests = domain.ifft(&digest_evaluations);
+        let sums_commitment = setup.commit(&sums);
+        let digests_commitment = setup.commit(&digests);
+        Ok(Self {
+            commitment: Commitment::new(sum, verkle_node_digest(sum, &sums_commitment, &digests_commitment)),
+            sums,
+            digests,
+            sums_commitment,
+            digests_commitment,
+        })
+    }
+}
+
+pub struct VerkleTree {
+    setup: KzgSetup,
+    values: Vec<u64>,
+    // From the nodes over the leaves up to the root
+    levels: Vec<Vec<VerkleNode>>,
+}
+
+// One level of a path: the node and three openings of its polynomials
+pub struct VerkleOpening {
+    pub sum: u64,
+    pub sums: G1Affine,
+    pub digests: G1Affine,
+    // The sum polynomial at 0, i.e. the node's sum over the width
+    pub total: G1Affine,
+    // Both polynomials at the path's slot in the node
+    pub child_sum: G1Affine,
+    pub child_digest: G1Affine,
+}
+
+pub struct VerkleProof {
+    pub position: usize,
+    pub value: u64,
+    // From the node over the leaf up to the root
+    pub openings: Vec<VerkleOpening>,
+}
+
+impl VerkleTree {
+    pub fn try_new(setup: KzgSetup, values: Vec<u64>) -> Result<Self, SumOverflow> {
+        let mut children: Vec<Commitment> = values
+            .iter()
+            .map(|value| Commitment::new(*value, verkle_leaf_digest(*value)))
+            .collect();
+        let mut levels = Vec::new();
+        loop {
+            let nodes = if children.is_empty() {
+                vec![VerkleNode::new(&setup, &[])?]
+            } else {
+                children
+                    .chunks(setup.width())
+                    .map(|chunk| VerkleNode::new(&setup, chunk))
+                    .collect::<Result<Vec<_>, _>>()?
+            };
+            children = nodes.iter().map(|node| node.commitment).collect();
+            levels.push(nodes);
+            if children.len() == 1 {
+                break;
+            }
+        }
+        Ok(Self { setup, values, levels })
+    }
+
+    pub fn len(&self) -> usize {
+        self.values.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
+
+    pub fn commit(&self) -> Commitment {
+        self.levels[self.levels.len() - 1][0].commitment
+    }
+
+    pub fn prove(&self, position: usize) -> VerkleProof {
+        assert!(position < self.len(), "position {} out of range", position);
+        let width = self.setup.width();
+        let domain = self.setup.domain();
+        let mut index = position;
+        let openings = self
+            .levels
+            .iter()
+            .map(|level| {
+                let node = &level[index / width];
+                let z = domain.element(index % width);
+                index /= width;
+                VerkleOpening {
+                    sum: node.commitment.sum,
+                    sums: node.sums_commitment,
+                    digests: node.digests_commitment,
+                    total: self.setup.open(&node.sums, Fr::zero()),
+                    child_sum: self.setup.open(&node.sums, z),
+                    child_digest: self.setup.open(&node.digests, z),
+                }
+            })
+            .collect();
+        VerkleProof {
+            position,
+            value: self.values[position],
+            openings,
+        }
+    }
+}
+
+impl VerkleProof {
+    pub fn verify(&self, setup: &KzgSetup, root_commitment: &Commitment) -> bool {
+        let width = setup.width();
+        let domain = setup.domain();
+        let width_inverse = Fr::from(width as u64).inverse().expect("the width is not zero");
+        let mut child = Commitment::new(self.value, verkle_leaf_digest(self.value));
+        let mut index = self.position;
+        for opening in &self.openings {
+            let z = domain.element(index % width);
+            let opens = setup.check(&opening.sums, z, Fr::from(child.sum), &opening.child_sum)
+                && setup.check(&opening.digests, z, to_field(&child.hash), &opening.child_digest)
+                && setup.check(
+                    &opening.sums,
+                    Fr::zero(),
+                    Fr::from(opening.sum) * width_inverse,
+                    &opening.total,
+                );
+            if child.sum > opening.sum || !opens {
+                return false;
+            }
+            child = Commitment::new(
+                opening.sum,
+                verkle_node_digest(opening.sum, &opening.sums, &opening.digests),
+            );
+            index /= width;
+        }
+        index == 0 && child == *root_commitment
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_verkle_tree() {
+        let values: Vec<u64> = (1..=40).collect();
+        let tree = VerkleTree::try_new(KzgSetup::insecure_from_secret(b"tau", 16), values).unwrap();
+        let setup = KzgSetup::insecure_from_secret(b"tau", 16);
+        let root_commitment = tree.commit();
+        assert_eq!((tree.len(), root_commitment.sum), (40, 820));
+        assert!(!tree.is_empty());
+
+        for position in [0, 17, 39] {
+            let proof = tree.prove(position);
+            assert_eq!(proof.value, position as u64 + 1);
+            // 40 leaves under 16-wide nodes are two levels deep
+            assert_eq!(proof.openings.len(), 2);
+            assert!(proof.verify(&setup, &root_commitment), "Failed position {}", position);
+        }
+
+        // Another balance, slot or setup does not open to the same root
+        let mut inflated = tree.prove(17);
+        inflated.value += 1;
+        assert!(!inflated.verify(&setup, &root_commitment));
+        let mut moved = tree.prove(17);
+        moved.position = 18;
+        assert!(!moved.verify(&setup, &root_commitment));
+        let mut understated = tree.prove(17);
+        understated.openings[1].sum -= 1;
+        assert!(!understated.verify(&setup, &root_commitment));
+        let other_setup = KzgSetup::insecure_from_secret(b"another tau", 16);
+        assert!(!tree.prove(17).verify(&other_setup, &root_commitment));
+
+        let single = VerkleTree::try_new(KzgSetup::insecure_from_secret(b"tau", 16), vec![7]).unwrap();
+        assert!(single.prove(0).verify(&setup, &single.commit()));
+        assert!(VerkleTree::try_new(KzgSetup::insecure_from_secret(b"tau", 4), vec![u64::MAX, 1]).is_err());
+    }
+}
@@ -0,0 +1,234 @@
+// Tree that keeps every past root, so an audit can be rerun against any earlier
+// epoch. Entries live in one arena that is only ever appended to: a change copies
+// the path from the leaf to the root and shares every other subtree with the
+// version before it, so each version costs one entry per level.
+
+use alloc::vec;
+use alloc::vec::Vec;
+use core::marker::PhantomData;
+
+use super::{
+    branch_entry, leaf_entry, prove_in, BuildError, Commitment, Encoding, Entry, Node, Proof, Sha256, SumCommitment,
+    SumOverflow, TreeDigest,
+};
+
+pub struct VersionedTree<D = Sha256> {
+    entries: Vec<Entry>,
+    // Root entry and leaf count of each version, the first being the tree as built
+    versions: Vec<(usize, usize)>,
+    // Entry of the all-zero subtree at each height, added as appends need them
+    zeros: Vec<usize>,
+    hasher: PhantomData<fn() -> D>,
+}
+
+impl<D: TreeDigest> VersionedTree<D> {
+    pub fn try_new(values: Vec<u64>) -> Result<Self, BuildError> {
+        Self::try_new_with_encoding(values, Encoding::default())
+    }
+
+    // Padded with zero leaves like `Node::try_new_padded`
+    pub fn try_new_with_encoding(values: Vec<u64>, encoding: Encoding) -> Result<Self, BuildError> {
+        let len = values.len();
+        let mut padded = values;
+        padded.resize(len.max(1).next_power_of_two(), 0);
+        let entries = Node::<D>::try_new_with_encoding(padded, encoding)?.entries;
+        Ok(Self {
+            versions: vec![(entries.len() - 1, len)],
+            entries,
+            zeros: Vec::new(),
+            hasher: PhantomData,
+        })
+    }
+
+    // The current version
+    pub fn version(&self) -> usize {
+        self.versions.len() - 1
+    }
+
+    pub fn len(&self) -> usize {
+        self.versions[self.version()].1
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn commit(&self) -> Commitment<D> {
+        self.root_at(self.version())
+    }
+
+    pub fn prove(&self, position: usize) -> Proof<D> {
+        self.prove_at(self.version(), position)
+    }
+
+    pub fn root_at(&self, version: usize) -> Commitment<D> {
+        let (root, _) = self.versions[version];
+        Commitment::from(&self.entries[root])
+    }
+
+    pub fn prove_at(&self, version: usize, position: usize) -> Proof<D> {
+        let (root, len) = self.versions[version];
+        assert!(
+            position < len,
+            "position {} out of range for {} leaves at version {}",
+            position,
+            len,
+            version
+        );
+        prove_in(&self.entries, root, position)
+    }
+
+    // Sets a leaf, keeping its blinding, and returns the new version
+    pub fn update(&mut self, position: usize, value: u64) -> Result<usize, SumOverflow> {
+        let (root, len) = self.versions[self.version()];
+        assert!(position < len, "position {} out of range for {} leaves", position, len);
+        let old = self.leaf(root, position);
+        // Every sum on the path is bounded by the root's
+        (self.entries[root].amount() - old.amount())
+            .checked_add(value)
+            .ok_or(SumOverflow)?;
+        let Entry::Leaf { encoding, blinding, .. } = old else {
+            unreachable!()
+        };
+        let leaf = leaf_entry::<D>(value, blinding.clone(), *encoding);
+        let root = self.replace(root, position, leaf);
+        self.versions.push((root, len));
+        Ok(self.version())
+    }
+
+    // Fills the next padding leaf, doubling the tree when there is none left, and
+    // returns the new version
+    pub fn append(&mut self, value: u64) -> Result<usize, SumOverflow> {
+        let (mut root, len) = self.versions[self.version()];
+        self.entries[root].amount().checked_add(value).ok_or(SumOverflow)?;
+        let height = self.entries[root].height();
+        if len == 1 << height {
+            let zero = self.zero(height);
+            let branch = branch_entry::<D>(&self.entries, root, zero)?;
+            self.entries.push(branch);
+            root = self.entries.len() - 1;
+        }
+        let leaf = leaf_entry::<D>(value, None, self.entries[root].encoding());
+        let root = self.replace(root, len, leaf);
+        self.versions.push((root, len + 1));
+        Ok(self.version())
+    }
+
+    // Makes `version` current again, dropping every later version
+    pub fn rollback(&mut self, version: usize) {
+        assert!(version <= self.version(), "no version {}", version);
+        self.versions.truncate(version + 1);
+        // Entries are appended children first, so nothing after the root is still in use
+        let (root, _) = self.versions[version];
+        self.entries.truncate(root + 1);
+        self.zeros.retain(|&zero| zero <= root);
+    }
+
+    fn leaf(&self, root: usize, position: usize) -> &Entry {
+        let mut current = &self.entries[root];
+        while let Entry::Branch {
+            height, left, right, ..
+        } = current
+        {
+            let child = if (position & (1usize << (height - 1))) == 0 {
+                left
+            } else {
+                right
+            };
+            current = &self.entries[*child];
+        }
+        current
+    }
+
+    // Copies the path from `index` down to the leaf at `position`, which becomes
+    // `leaf`, and returns the copy of `index`
+    fn replace(&mut self, index: usize, position: usize, leaf: Entry) -> usize {
+        if let Entry::Branch {
+            height, left, right, ..
+        } = self.entries[index]
+        {
+            let (left, right) = if (position & (1usize << (height - 1))) == 0 {
+                (self.replace(left, position, leaf), right)
+            } else {
+                (left, self.replace(right, position, leaf))
+            };
+            // The caller has already checked the new root sum
+            let branch = branch_entry::<D>(&self.entries, left, right).expect("sum checked before the update");
+            self.entries.push(branch);
+        } else {
+            self.entries.push(leaf);
+        }
+        self.entries.len() - 1
+    }
+
+    fn zero(&mut self, height: usize) -> usize {
+        while self.zeros.len() <= height {
+            let entry = match self.zeros.last() {
+                // Both children are the same entry
+                Some(&below) => branch_entry::<D>(&self.entries, below, below).expect("zero sums cannot overflow"),
+                None => leaf_entry::<D>(0, None, self.entries[self.versions[0].0].encoding()),
+            };
+            self.entries.push(entry);
+            self.zeros.push(self.entries.len() - 1);
+        }
+        self.zeros[height]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ExclusiveAllotmentProof, MerkleTree};
+
+    #[test]
+    fn test_versioned_tree() {
+        let padded = |values: Vec<u64>| Node::<Sha256>::try_new_padded(values).unwrap().commit();
+        let mut tree = VersionedTree::<Sha256>::try_new(vec![1, 2, 3]).unwrap();
+        assert_eq!(tree.version(), 0);
+        assert!(tree.commit() == padded(vec![1, 2, 3]));
+
+        assert_eq!(tree.update(1, 20), Ok(1));
+        assert_eq!(tree.append(4), Ok(2));
+        // The tree is full, so the next append doubles it
+        assert_eq!(tree.append(5), Ok(3));
+        assert_eq!(tree.len(), 5);
+        assert!(tree.commit() == padded(vec![1, 20, 3, 4, 5]));
+
+        let history = [vec![1, 2, 3], vec![1, 20, 3], vec![1, 20, 3, 4], vec![1, 20, 3, 4, 5]];
+        for (version, values) in history.iter().enumerate() {
+            let root = tree.root_at(version);
+            assert!(root == padded(values.clone()), "Failed version {}", version);
+            for (position, value) in values.iter().enumerate() {
+                let proof = tree.prove_at(version, position);
+                assert_eq!(proof.node.sum, *value);
+                assert!(proof.verify(&root));
+            }
+        }
+        // Old proofs stay valid for their own epoch only
+        assert!(!tree.prove_at(0, 1).verify(&tree.commit()));
+
+        // Only the changed path is stored again
+        let entries = tree.entries.len();
+        tree.update(0, 7).unwrap();
+        assert_eq!(tree.entries.len(), entries + 4);
+
+        tree.rollback(1);
+        assert_eq!(tree.version(), 1);
+        assert_eq!(tree.len(), 3);
+        assert!(tree.commit() == padded(vec![1, 20, 3]));
+        assert_eq!(tree.append(6), Ok(2));
+        assert_eq!(tree.append(8), Ok(3));
+        assert!(tree.commit() == padded(vec![1, 20, 3, 6, 8]));
+        assert!(tree.prove(4).verify(&tree.commit()));
+
+        assert_eq!(tree.update(0, u64::MAX), Err(SumOverflow));
+        assert_eq!(tree.append(u64::MAX), Err(SumOverflow));
+        assert_eq!(tree.version(), 3);
+
+        let mut empty = VersionedTree::<Sha256>::try_new(vec![]).unwrap();
+        assert!(empty.is_empty());
+        empty.append(9).unwrap();
+        empty.append(1).unwrap();
+        assert!(empty.commit() == padded(vec![9, 1]));
+    }
+}
@@ -0,0 +1,75 @@
+// Browser entry points for users checking their own proof. Proofs and roots
+// cross the boundary in the canonical byte encoding from codec.rs.
+
+use alloc::string::ToString;
+use alloc::vec::Vec;
+
+use wasm_bindgen::prelude::*;
+
+use super::{Commitment, DecodeError, ExclusiveAllotmentProof, Proof, SumCommitment};
+
+fn to_js_error(err: DecodeError) -> JsError {
+    JsError::new(&err.to_string())
+}
+
+// A decoded proof, so its fields can be shown before checking it
+#[wasm_bindgen]
+pub struct WasmProof(Proof);
+
+#[wasm_bindgen]
+impl WasmProof {
+    #[wasm_bindgen(js_name = fromBytes)]
+    pub fn from_bytes(bytes: &[u8]) -> Result<WasmProof, JsError> {
+        Proof::from_bytes(bytes).map(WasmProof).map_err(to_js_error)
+    }
+
+    #[wasm_bindgen(js_name = toBytes)]
+    pub fn to_bytes(&self) -> Vec<u8> {
+        self.0.to_bytes()
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn position(&self) -> usize {
+        self.0.position()
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn balance(&self) -> u64 {
+        self.0.node.amount()
+    }
+
+    pub fn verify(&self, root_bytes: &[u8]) -> Result<bool, JsError> {
+        let root_commitment = Commitment::from_bytes(root_bytes).map_err(to_js_error)?;
+        Ok(self.0.verify(&root_commitment))
+    }
+}
+
+#[wasm_bindgen(js_name = verify)]
+pub fn wasm_verify(proof_bytes: &[u8], root_bytes: &[u8]) -> Result<bool, JsError> {
+    WasmProof::from_bytes(proof_bytes)?.verify(root_bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use wasm_bindgen_test::*;
+
+    use super::*;
+    use crate::{MerkleTree, Node, Sha256};
+
+    wasm_bindgen_test_configure!(run_in_browser);
+
+    #[wasm_bindgen_test]
+    fn test_wasm_verify() {
+        let tree_root: Node = Node::new((1..=8).collect());
+        let root_bytes = tree_root.commit().to_bytes();
+        let proof_bytes = tree_root.prove(5).to_bytes();
+        assert!(wasm_verify(&proof_bytes, &root_bytes).unwrap());
+
+        let proof = WasmProof::from_bytes(&proof_bytes).unwrap();
+        assert_eq!((proof.position(), proof.balance()), (5, 6));
+        assert_eq!(proof.to_bytes(), proof_bytes);
+
+        let other_root = Node::<Sha256>::new((2..=9).collect()).commit().to_bytes();
+        assert!(!wasm_verify(&proof_bytes, &other_root).unwrap());
+    }
+}
@@ -0,0 +1,71 @@
+// Wiping of leaf secrets. A `Blinding` wipes itself when dropped, and the buffers
+// a leaf is serialized into for hashing are wiped once hashed. Proofs carry a
+// balance next to its salt, so they can be wiped as a whole, and user bundles are
+// wiped when dropped since they are the one thing handed to every user.
+
+use zeroize::Zeroize;
+
+#[cfg(feature = "std")]
+use super::liabilities::UserBundle;
+use super::{Commitment, Proof};
+
+impl<D> Zeroize for Commitment<D> {
+    fn zeroize(&mut self) {
+        self.sum.zeroize();
+        self.hash.zeroize();
+    }
+}
+
+impl<D> Zeroize for Proof<D> {
+    fn zeroize(&mut self) {
+        self.node.zeroize();
+        self.siblings.zeroize();
+        self.index.zeroize();
+        // Wipes the salt and user id and leaves `None`
+        self.blinding.zeroize();
+    }
+}
+
+#[cfg(feature = "std")]
+impl<D> Zeroize for UserBundle<D> {
+    fn zeroize(&mut self) {
+        self.proofs.zeroize();
+    }
+}
+
+#[cfg(feature = "std")]
+impl<D> Drop for UserBundle<D> {
+    fn drop(&mut self) {
+        self.zeroize();
+    }
+}
+
+#[cfg(feature = "std")]
+impl<D> zeroize::ZeroizeOnDrop for UserBundle<D> {}
+
+#[cfg(test)]
+mod tests {
+    use alloc::vec::Vec;
+
+    use super::*;
+    use crate::{Blinding, ExclusiveAllotmentProof, MerkleTree, Node, Sha256};
+
+    #[test]
+    fn test_zeroize() {
+        let mut rng = rand::thread_rng();
+        let values: Vec<(u64, Blinding)> = (1..=4).map(|value| (value, Blinding::random(&mut rng, None))).collect();
+        let tree_root = Node::<Sha256>::try_new_blinded(values).unwrap();
+
+        let mut proof = tree_root.prove(2);
+        assert!(proof.verify(&tree_root.commit()));
+        proof.zeroize();
+        assert_eq!(proof.node, Commitment::new(0, [0; 32]));
+        assert!(proof.siblings.is_empty());
+        assert!(proof.blinding.is_none());
+
+        let mut blinding = Blinding::random(&mut rng, Some([7; 32]));
+        blinding.zeroize();
+        assert_eq!(blinding.salt, [0; 32]);
+        assert_eq!(blinding.user_id, None);
+    }
+}
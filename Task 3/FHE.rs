@@ -1,46 +1,70 @@
-use tfhe::shortint::prelude::*;
+#[cfg(feature = "shortint")]
+mod shortint;
 
-fn query(key: ServerKey, mut target: Ciphertext, inventory: &[(u8, u8)]) -> Ciphertext {
-  let mut result = key.create_trivial(0);
+use tfhe::prelude::*;
+use tfhe::{FheUint16, FheUint8};
 
-  for (idx, cnt) in inventory {
-    let mut item_equality = key.smart_scalar_equal(&mut target, *idx);
-    let mut contribution = key.smart_scalar_mul(&mut item_equality, *cnt);
-    result = key.smart_add(&mut result, &mut contribution);
-  }
-  
-  result
+// Equality-times-count sum over tfhe's high-level integers, which track carries themselves and are not
+// confined to the few bits of a shortint block. Runs on the server key installed with
+// `tfhe::set_server_key` on the calling thread.
+fn query(target: &FheUint8, inventory: &[(u8, u16)]) -> FheUint16 {
+  inventory
+    .iter()
+    .map(|(idx, cnt)| FheUint16::cast_from(target.eq(*idx)) * *cnt)
+    .sum()
+}
+
+#[cfg(feature = "shortint")]
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+  shortint::cli()
 }
 
+#[cfg(not(feature = "shortint"))]
 fn main() {
-  // nothing to do here
+  eprintln!("the command-line demo runs on shortint; build with the shortint feature");
+  std::process::exit(2);
 }
 
 #[cfg(test)]
 mod tests {
-  use tfhe::shortint::prelude::*;
-  use tfhe::shortint::parameters::PARAM_MESSAGE_4_CARRY_0_KS_PBS;
+  use tfhe::prelude::*;
+  use tfhe::{generate_keys, set_server_key, ConfigBuilder, FheUint8};
 
   use crate::query;
 
   #[test]
   fn test_it() {
-    let (client_key, server_key) = gen_keys(PARAM_MESSAGE_4_CARRY_0_KS_PBS);
+    let (client_key, server_key) = generate_keys(ConfigBuilder::default());
+    set_server_key(server_key);
 
     let item_code = 0u8;
 
-    let item_code_ciphertext = client_key.encrypt(item_code as u64);
+    let item_code_ciphertext = FheUint8::encrypt(item_code, &client_key);
 
-    assert_eq!(item_code as u64, client_key.decrypt(&item_code_ciphertext));
-
-    let stock_ciphertext = query(server_key, item_code_ciphertext, &[
+    let stock_ciphertext = query(&item_code_ciphertext, &[
       (0, 2),
       (1, 1),
       (0, 1),
     ]);
 
-    let stock_count = client_key.decrypt(&stock_ciphertext);
+    let stock_count: u16 = stock_ciphertext.decrypt(&client_key);
 
     assert_eq!(stock_count, 3);
   }
+  #[test]
+  fn test_query_wide_codes() {
+    let (client_key, server_key) = generate_keys(ConfigBuilder::default());
+    set_server_key(server_key);
+
+    // Codes and totals well past what fits in one shortint block
+    let inventory = [(200, 150), (17, 3), (200, 150), (255, 1)];
+
+    let target = FheUint8::encrypt(200u8, &client_key);
+    let stock: u16 = query(&target, &inventory).decrypt(&client_key);
+    assert_eq!(stock, 300);
+
+    let target = FheUint8::encrypt(18u8, &client_key);
+    let stock: u16 = query(&target, &inventory).decrypt(&client_key);
+    assert_eq!(stock, 0);
+  }
 }
@@ -0,0 +1,2111 @@
+// The shortint implementation the high-level one in FHE.rs replaced, kept for comparison together
+// with everything built on it: the key and wire formats, the command-line and HTTP demo, and the
+// wasm bindings. Codes and counts live in blocks of a few bits, so carries are managed by hand.
+
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, BufWriter, Read, Write};
+use std::net::{TcpListener, TcpStream, ToSocketAddrs};
+use std::path::Path;
+use std::time::Duration;
+
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use tfhe::integer::block_decomposition::DecomposableInto;
+use tfhe::integer::server_key::ScalarMultiplier;
+use tfhe::integer::{self, IntegerCiphertext, RadixCiphertext};
+use tfhe::shortint::parameters::{
+  ClassicPBSParameters, PARAM_MESSAGE_1_CARRY_1_KS_PBS, PARAM_MESSAGE_2_CARRY_2_COMPACT_PK_KS_PBS,
+  PARAM_MESSAGE_2_CARRY_2_KS_PBS, PARAM_MESSAGE_3_CARRY_3_KS_PBS, PARAM_MESSAGE_4_CARRY_4_KS_PBS,
+};
+use tfhe::shortint::public_key::CompressedCompactPublicKey;
+use tfhe::shortint::prelude::*;
+use tfhe::shortint::server_key::LookupTableOwned;
+use tfhe::shortint::CompressedServerKey;
+
+// Sets supported by `Keys`, smallest first so the cheapest one that fits wins.
+// PARAM_MESSAGE_4_CARRY_0 is deliberately absent: without carry space every addition wraps.
+const STANDARD_PARAMETERS: [ClassicPBSParameters; 4] = [
+  PARAM_MESSAGE_1_CARRY_1_KS_PBS,
+  PARAM_MESSAGE_2_CARRY_2_KS_PBS,
+  PARAM_MESSAGE_3_CARRY_3_KS_PBS,
+  PARAM_MESSAGE_4_CARRY_4_KS_PBS,
+];
+
+#[derive(Debug)]
+enum DecodeError {
+  Base64(base64::DecodeError),
+  Ciphertext(bincode::Error),
+}
+
+impl fmt::Display for DecodeError {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      DecodeError::Base64(err) => write!(f, "malformed base64: {}", err),
+      DecodeError::Ciphertext(err) => write!(f, "malformed ciphertext bytes: {}", err),
+    }
+  }
+}
+
+impl std::error::Error for DecodeError {}
+
+#[derive(Debug)]
+enum FormatError {
+  Io(io::Error),
+  BadMagic,
+  UnsupportedVersion { version: u8 },
+  WrongKind { expected: u8, found: u8 },
+  Payload(bincode::Error),
+}
+
+impl fmt::Display for FormatError {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      FormatError::Io(err) => write!(f, "i/o error: {}", err),
+      FormatError::BadMagic => write!(f, "not a serialized key or ciphertext"),
+      FormatError::UnsupportedVersion { version } => write!(f, "unsupported format version {}", version),
+      FormatError::WrongKind { expected, found } => write!(f, "expected object kind {}, found {}", expected, found),
+      FormatError::Payload(err) => write!(f, "malformed payload: {}", err),
+    }
+  }
+}
+
+impl std::error::Error for FormatError {}
+
+#[derive(Debug)]
+enum HttpError {
+  Io(io::Error),
+  Malformed,
+  Status { code: u16, body: String },
+  Format(FormatError),
+}
+
+impl fmt::Display for HttpError {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      HttpError::Io(err) => write!(f, "i/o error: {}", err),
+      HttpError::Malformed => write!(f, "malformed HTTP message"),
+      HttpError::Status { code, body } => write!(f, "server answered {}: {}", code, body),
+      HttpError::Format(err) => write!(f, "{}", err),
+    }
+  }
+}
+
+impl std::error::Error for HttpError {}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+struct InventoryError {
+  // Counted from 1, like an editor would
+  line: usize,
+}
+
+impl fmt::Display for InventoryError {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(f, "inventory line {} is not a code and a count", self.line)
+  }
+}
+
+impl std::error::Error for InventoryError {}
+
+#[derive(Debug, PartialEq, Eq)]
+enum ParameterError {
+  NoStandardSetFits { required_bits: u32 },
+  NoCompactPublicKey,
+}
+
+impl fmt::Display for ParameterError {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      ParameterError::NoStandardSetFits { required_bits } => {
+        write!(f, "no standard parameter set has {} bits of message and carry space", required_bits)
+      }
+      ParameterError::NoCompactPublicKey => write!(f, "the parameter set cannot derive a compact public key"),
+    }
+  }
+}
+
+impl std::error::Error for ParameterError {}
+
+#[derive(Debug, PartialEq, Eq)]
+enum QueryError {
+  MissingTarget,
+  MissingInventory,
+  CodeOutOfRange { code: u8 },
+  ThresholdOutOfRange { threshold: u8 },
+  NoCarrySpace,
+  TooManyEntries { entries: usize },
+  ValueOverflow { code: u8 },
+  TotalOutOfRange { code: u8 },
+  CodeTooWide { code: u64, bits: u32 },
+}
+
+impl fmt::Display for QueryError {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      QueryError::MissingTarget => write!(f, "no target ciphertext was given"),
+      QueryError::MissingInventory => write!(f, "no inventory was given"),
+      QueryError::CodeOutOfRange { code } => write!(f, "item code {} does not fit in the message space", code),
+      QueryError::ThresholdOutOfRange { threshold } => {
+        write!(f, "threshold {} does not fit in the message space", threshold)
+      }
+      QueryError::NoCarrySpace => write!(f, "the parameter set has no carry space to sum counts in"),
+      QueryError::TooManyEntries { entries } => {
+        write!(f, "{} inventory entries do not all have a position in the message space", entries)
+      }
+      QueryError::ValueOverflow { code } => write!(f, "the stock value of item code {} does not fit in 8 bits", code),
+      QueryError::TotalOutOfRange { code } => {
+        write!(f, "the total stock of item code {} does not fit in the message space", code)
+      }
+      QueryError::CodeTooWide { code, bits } => {
+        write!(f, "item code {} does not fit in the target's {} bits", code, bits)
+      }
+    }
+  }
+}
+
+impl std::error::Error for QueryError {}
+
+trait Decryptor {
+  fn decrypt_count(&self, ct: &Ciphertext) -> u64;
+}
+
+impl Decryptor for ClientKey {
+  fn decrypt_count(&self, ct: &Ciphertext) -> u64 {
+    self.decrypt(ct)
+  }
+}
+
+struct Keys {
+  client_key: ClientKey,
+  server_key: ServerKey,
+}
+
+impl Keys {
+  // Codes and totals both live in the whole message+carry space of a single block,
+  // so the set is picked by the larger of the two bit widths
+  fn for_inventory(max_code: u8, max_total: u64) -> Result<Keys, ParameterError> {
+    let code_bits = u8::BITS - max_code.leading_zeros();
+    let total_bits = u64::BITS - max_total.leading_zeros();
+    let required_bits = code_bits.max(total_bits);
+
+    let parameters = STANDARD_PARAMETERS
+      .into_iter()
+      .find(|parameters| {
+        let space = parameters.message_modulus.0 * parameters.carry_modulus.0;
+        space.ilog2() >= required_bits
+      })
+      .ok_or(ParameterError::NoStandardSetFits { required_bits })?;
+
+    let (client_key, server_key) = gen_keys(parameters);
+    Ok(Keys { client_key, server_key })
+  }
+
+  fn encrypt_target(&self, code: u8) -> Ciphertext {
+    let space = self.client_key.parameters.message_modulus().0 * self.client_key.parameters.carry_modulus().0;
+    self.client_key.encrypt_with_message_modulus(code as u64, MessageModulus(space))
+  }
+
+  fn decrypt(&self, result: &Ciphertext) -> u64 {
+    self.client_key.decrypt(result)
+  }
+}
+
+// Lets anyone encrypt targets while only the client key holder can decrypt results. Sets made for it,
+// like PARAM_MESSAGE_2_CARRY_2_COMPACT_PK_KS_PBS, also keep public-key encryptions within the noise budget.
+fn compact_public_key(client_key: &ClientKey) -> Result<CompactPublicKey, ParameterError> {
+  CompactPublicKey::try_new(client_key).ok_or(ParameterError::NoCompactPublicKey)
+}
+
+// Encryption would silently reduce a code past the message space modulo its size
+fn check_code(code: u8, message_modulus: MessageModulus) -> Result<u8, QueryError> {
+  if code as usize >= message_modulus.0 {
+    return Err(QueryError::CodeOutOfRange { code });
+  }
+  Ok(code)
+}
+
+// Inventory entries sharing a code are summed, so duplicates behave like one merged entry
+// Works on a copy of the target, so the caller's ciphertext and key are left as they were
+fn query(key: &ServerKey, target: &Ciphertext, inventory: &[(u8, u8)]) -> Ciphertext {
+  let mut target = target.clone();
+  let contributions = inventory
+    .iter()
+    .map(|(idx, cnt)| {
+      let mut item_equality = key.smart_scalar_equal(&mut target, *idx);
+      key.smart_scalar_mul(&mut item_equality, *cnt)
+    })
+    .collect();
+
+  sum_balanced(key, contributions)
+}
+
+// Both encrypted, so "unknown item" and "known item with no stock" decrypt differently
+struct QueryResult {
+  exists: Ciphertext,
+  count: Ciphertext,
+}
+
+// The flag is an OR over the equalities rather than their sum, which could wrap back to 0
+fn query_with_existence(key: &ServerKey, target: &Ciphertext, inventory: &[(u8, u8)]) -> QueryResult {
+  let mut target = target.clone();
+  let (mut matches, contributions): (Vec<Ciphertext>, Vec<Ciphertext>) = inventory
+    .iter()
+    .map(|(idx, cnt)| {
+      let item_equality = key.smart_scalar_equal(&mut target, *idx);
+      let contribution = key.smart_scalar_mul(&mut item_equality.clone(), *cnt);
+      (item_equality, contribution)
+    })
+    .unzip();
+
+  while matches.len() > 1 {
+    matches = matches
+      .chunks_mut(2)
+      .map(|pair| match pair {
+        [left, right] => or_results(key, left, right),
+        [single] => single.clone(),
+        _ => unreachable!(),
+      })
+      .collect();
+  }
+
+  QueryResult {
+    exists: matches.pop().unwrap_or_else(|| key.create_trivial(0)),
+    count: sum_balanced(key, contributions),
+  }
+}
+
+// Total stock of every code in lo..=hi. A public range can be given as trivial ciphertexts
+// from `create_trivial`; an empty range (lo > hi) totals 0.
+fn query_range(key: &ServerKey, lo: &Ciphertext, hi: &Ciphertext, inventory: &[(u8, u8)]) -> Ciphertext {
+  let mut lo = lo.clone();
+  let mut hi = hi.clone();
+  let contributions = inventory
+    .iter()
+    .map(|(idx, cnt)| {
+      let above_lo = key.smart_scalar_less_or_equal(&mut lo, *idx);
+      let below_hi = key.smart_scalar_greater_or_equal(&mut hi, *idx);
+      let mut in_range = and_results(key, &above_lo, &below_hi);
+      key.smart_scalar_mul(&mut in_range, *cnt)
+    })
+    .collect();
+
+  sum_balanced(key, contributions)
+}
+
+// Counts encrypted under the same client key, so the server sees neither the stock nor the target.
+// Shortint has no full `smart_mul`; the equality flag is 0 or 1, so the low half of the product is exact.
+fn query_encrypted_counts(key: &ServerKey, target: &Ciphertext, inventory: &[(u8, Ciphertext)]) -> Ciphertext {
+  let mut target = target.clone();
+  let contributions = inventory
+    .iter()
+    .map(|(idx, cnt)| {
+      let mut item_equality = key.smart_scalar_equal(&mut target, *idx);
+      key.smart_mul_lsb(&mut item_equality, &mut cnt.clone())
+    })
+    .collect();
+
+  sum_balanced(key, contributions)
+}
+
+// Codes encrypted too, so the server computes over a database it cannot read at all.
+// It can no longer reject out-of-range codes either; those must be checked when encrypting.
+fn query_encrypted_inventory(
+  key: &ServerKey,
+  target: &Ciphertext,
+  inventory: &[(Ciphertext, Ciphertext)],
+) -> Ciphertext {
+  let mut target = target.clone();
+  let contributions = inventory
+    .iter()
+    .map(|(idx, cnt)| {
+      let mut item_equality = key.smart_equal(&mut target, &mut idx.clone());
+      key.smart_mul_lsb(&mut item_equality, &mut cnt.clone())
+    })
+    .collect();
+
+  sum_balanced(key, contributions)
+}
+
+// In-place updates to an inventory like the one of `query_encrypted_inventory`. The item, the delta and
+// every count stay encrypted, and every entry is rewritten whether it matches or not, so the server learns
+// neither which count changed nor by how much. Each entry with the item's code takes the whole delta, so
+// codes should be unique. Counts saturate instead of wrapping: a restock stops at the largest count, and a
+// sale larger than the stock empties it.
+fn restock(key: &ServerKey, inventory: &mut [(Ciphertext, Ciphertext)], item: &Ciphertext, delta: &Ciphertext) {
+  let cap = key.message_modulus.0 as u64 - 1;
+  update_counts(key, inventory, item, delta, |count, delta| (count + delta).min(cap));
+}
+
+fn sell(key: &ServerKey, inventory: &mut [(Ciphertext, Ciphertext)], item: &Ciphertext, delta: &Ciphertext) {
+  update_counts(key, inventory, item, delta, |count, delta| count.saturating_sub(delta));
+}
+
+fn update_counts<F>(
+  key: &ServerKey,
+  inventory: &mut [(Ciphertext, Ciphertext)],
+  item: &Ciphertext,
+  delta: &Ciphertext,
+  f: F,
+) where
+  F: Fn(u64, u64) -> u64,
+{
+  let update = key.generate_lookup_table_bivariate(f);
+  let mut item = item.clone();
+
+  for (code, count) in inventory.iter_mut() {
+    // The delta where the code matches and 0 elsewhere, then one bootstrap that also leaves the count fresh
+    let mut item_equality = key.smart_equal(&mut item, &mut code.clone());
+    let mut applied = key.smart_mul_lsb(&mut item_equality, &mut delta.clone());
+    key.apply_lookup_table_bivariate_assign(count, &mut applied, &update);
+  }
+}
+
+fn query_iter<I>(key: &ServerKey, target: &Ciphertext, inventory: I) -> Result<Ciphertext, QueryError>
+where
+  I: IntoIterator<Item = (u8, u8)>,
+{
+  let modulus = key.message_modulus.0 as u64;
+  let mut target = target.clone();
+  let mut tracker = CarryTracker::new(key);
+
+  // Sums of complete pairwise subtrees, merged like a binary counter so the
+  // addition depth matches `sum_balanced` without buffering the whole inventory
+  let mut partial_sums: Vec<(usize, Ciphertext)> = Vec::new();
+
+  for (idx, cnt) in inventory {
+    if idx as u64 >= modulus {
+      return Err(QueryError::CodeOutOfRange { code: idx });
+    }
+
+    let mut item_equality = key.smart_scalar_equal(&mut target, idx);
+    let mut sum = key.smart_scalar_mul(&mut item_equality, cnt);
+    let mut height = 0usize;
+    while partial_sums
+      .last()
+      .is_some_and(|(range_height, _)| &height == range_height)
+    {
+      let (_, mut sibling) = partial_sums.pop().unwrap();
+      sum = tracker.add(&mut sibling, &mut sum);
+      height += 1;
+    }
+    partial_sums.push((height, sum));
+  }
+
+  let remaining = partial_sums.into_iter().map(|(_, sum)| sum).collect();
+  Ok(tracker.sum(remaining))
+}
+
+// Answers every target in one pass over the inventory: each entry's equality and scaling is a
+// single table, generated once and applied to all targets, instead of two bootstraps per target
+fn query_many(key: &ServerKey, targets: &[Ciphertext], inventory: &[(u8, u8)]) -> Vec<Ciphertext> {
+  let mut contributions: Vec<Vec<Ciphertext>> = vec![Vec::with_capacity(inventory.len()); targets.len()];
+
+  for (idx, cnt) in inventory {
+    let contribution_lut = key.generate_lookup_table(|x| if x == *idx as u64 { *cnt as u64 } else { 0 });
+    for (target, target_contributions) in targets.iter().zip(&mut contributions) {
+      target_contributions.push(key.apply_lookup_table(target, &contribution_lut));
+    }
+  }
+
+  contributions
+    .into_iter()
+    .map(|target_contributions| sum_balanced(key, target_contributions))
+    .collect()
+}
+
+// The whole inventory folded into one table from code to total, so a lookup costs a single
+// bootstrap however many entries there are. Totals wrap at the message modulus exactly like `query`.
+fn query_lookup(key: &ServerKey, target: &Ciphertext, inventory: &[(u8, u8)]) -> Ciphertext {
+  let modulus = key.message_modulus.0 as u64;
+  let mut totals = vec![0u64; modulus as usize];
+  // Codes past the message space never match a target, as with `smart_scalar_equal`
+  for (idx, cnt) in inventory.iter().filter(|(idx, _)| (*idx as u64) < modulus) {
+    totals[*idx as usize] = (totals[*idx as usize] + *cnt as u64) % modulus;
+  }
+
+  let totals_lut = key.generate_lookup_table(|x| totals.get(x as usize).copied().unwrap_or(0));
+  key.apply_lookup_table(target, &totals_lut)
+}
+
+// Equality lookup tables for every code in the message space, built once per server key
+struct EqualityTables {
+  tables: Vec<LookupTableOwned>,
+}
+
+impl EqualityTables {
+  fn new(key: &ServerKey) -> Self {
+    let tables = (0..key.message_modulus.0 as u64)
+      .map(|code| key.generate_lookup_table(|x| (x == code) as u64))
+      .collect();
+
+    Self { tables }
+  }
+}
+
+// Same result as `query`, without regenerating an equality table per inventory entry. There is only a
+// table per code in the message space, so codes past it are refused rather than looked up.
+fn query_cached(
+  key: &ServerKey,
+  tables: &EqualityTables,
+  target: &Ciphertext,
+  inventory: &[(u8, u8)],
+) -> Result<Ciphertext, QueryError> {
+  if let Some((code, _)) = inventory.iter().find(|(code, _)| *code as usize >= tables.tables.len()) {
+    return Err(QueryError::CodeOutOfRange { code: *code });
+  }
+
+  let contributions = inventory
+    .iter()
+    .map(|(idx, cnt)| {
+      let mut item_equality = key.apply_lookup_table(target, &tables.tables[*idx as usize]);
+      key.smart_scalar_mul(&mut item_equality, *cnt)
+    })
+    .collect();
+
+  Ok(sum_balanced(key, contributions))
+}
+
+// Bootstrapping policy for sums of counts. Every ciphertext records its degree (the largest value it may
+// hold, carries included) and its noise level, and an addition is only sound while both totals stay within
+// the server key's limits. Before each addition the tracker checks that it fits and, if not, refreshes the
+// noisier operand with `message_extract`: one bootstrap that moves the carries back into the message,
+// modulo the message modulus like every total here, and resets the noise. The refreshes are counted.
+struct CarryTracker<'a> {
+  key: &'a ServerKey,
+  refreshes: usize,
+}
+
+impl<'a> CarryTracker<'a> {
+  fn new(key: &'a ServerKey) -> CarryTracker<'a> {
+    // Two refreshed operands must always fit in one addition, which needs at least one carry bit
+    assert!(key.carry_modulus.0 > 1, "sums need a parameter set with carry space");
+    CarryTracker { key, refreshes: 0 }
+  }
+
+  fn add(&mut self, left: &mut Ciphertext, right: &mut Ciphertext) -> Ciphertext {
+    // Extraction reduces modulo the key's message modulus, which would corrupt ciphertexts that
+    // use the carries as message space, like targets from `Keys::encrypt_target`
+    assert!(
+      left.message_modulus == self.key.message_modulus && right.message_modulus == self.key.message_modulus,
+      "operands must use the server key's message modulus"
+    );
+
+    let load = |ct: &Ciphertext| (ct.noise_level().get(), ct.degree.get());
+    while self.key.is_add_possible(left.noise_degree(), right.noise_degree()).is_err() {
+      let noisier = if load(left) >= load(right) {
+        &mut *left
+      } else {
+        &mut *right
+      };
+      self.key.message_extract_assign(noisier);
+      self.refreshes += 1;
+    }
+
+    self.key.unchecked_add(left, right)
+  }
+
+  fn sum(&mut self, mut level: Vec<Ciphertext>) -> Ciphertext {
+    // Pairwise-sum one level at a time so the deepest chain of additions is log2(n)
+    while level.len() > 1 {
+      level = level
+        .chunks_mut(2)
+        .map(|pair| match pair {
+          [left, right] => self.add(left, right),
+          [single] => single.clone(),
+          _ => unreachable!(),
+        })
+        .collect();
+    }
+
+    level.pop().unwrap_or_else(|| self.key.create_trivial(0))
+  }
+}
+
+fn sum_balanced(key: &ServerKey, level: Vec<Ciphertext>) -> Ciphertext {
+  CarryTracker::new(key).sum(level)
+}
+
+// Clamps after every addition so an overflowing total decrypts as the largest
+// representable count instead of wrapping. Needs carry space for one addition.
+fn query_saturating(key: &ServerKey, target: &Ciphertext, inventory: &[(u8, u8)]) -> Ciphertext {
+  let cap = key.message_modulus.0 as u64 - 1;
+  let clamp = key.generate_lookup_table(|x| x.min(cap));
+
+  let mut result = key.create_trivial(0);
+
+  for (idx, cnt) in inventory {
+    // Equality and scaling in a single bootstrap, already clamped to the cap
+    let contribution_lut = key.generate_lookup_table(|x| {
+      if x == *idx as u64 {
+        (*cnt as u64).min(cap)
+      } else {
+        0
+      }
+    });
+    let contribution = key.apply_lookup_table(target, &contribution_lut);
+    result = key.unchecked_add(&result, &contribution);
+    key.apply_lookup_table_assign(&mut result, &clamp);
+  }
+
+  result
+}
+
+// Counterpart of `query` for targets from `Keys::encrypt_target`, which use the carry
+// bits as message space. Every contribution is a single lookup over that space and
+// the running total is refreshed after each addition, so nothing wraps or overflows.
+fn query_full_space(key: &ServerKey, target: &Ciphertext, inventory: &[(u8, u8)]) -> Ciphertext {
+  let refresh = key.generate_lookup_table(|x| x);
+  let mut result = key.apply_lookup_table(target, &key.generate_lookup_table(|_| 0));
+
+  for (idx, cnt) in inventory {
+    let contribution_lut = key.generate_lookup_table(|x| if x == *idx as u64 { *cnt as u64 } else { 0 });
+    let contribution = key.apply_lookup_table(target, &contribution_lut);
+    result = key.unchecked_add(&result, &contribution);
+    key.apply_lookup_table_assign(&mut result, &refresh);
+  }
+
+  result
+}
+
+// Stock value (count times unit price) of the target, for targets from `Keys::encrypt_target`.
+// Codes without a price contribute nothing.
+fn query_value(
+  key: &ServerKey,
+  target: &Ciphertext,
+  inventory: &[(u8, u8)],
+  prices: &[(u8, u8)],
+) -> Result<Ciphertext, QueryError> {
+  Ok(query_full_space(key, target, &valued_inventory(inventory, prices)?))
+}
+
+// Each entry's count times its unit price, which has to fit in 8 bits like any count
+fn valued_inventory(inventory: &[(u8, u8)], prices: &[(u8, u8)]) -> Result<Vec<(u8, u8)>, QueryError> {
+  inventory
+    .iter()
+    .map(|(idx, cnt)| {
+      let price = prices.iter().find(|(code, _)| code == idx).map_or(0, |(_, price)| *price);
+      let value = cnt.checked_mul(price).ok_or(QueryError::ValueOverflow { code: *idx })?;
+      Ok((*idx, value))
+    })
+    .collect()
+}
+
+fn cart_total(
+  key: &ServerKey,
+  targets: &[Ciphertext],
+  inventory: &[(u8, u8)],
+  prices: &[(u8, u8)],
+) -> Result<Ciphertext, QueryError> {
+  let refresh = key.generate_lookup_table(|x| x);
+  let valued = valued_inventory(inventory, prices)?;
+
+  let mut values = targets.iter().map(|target| query_full_space(key, target, &valued));
+  let Some(mut total) = values.next() else {
+    return Ok(key.create_trivial(0));
+  };
+
+  for value in values {
+    total = key.unchecked_add(&total, &value);
+    key.apply_lookup_table_assign(&mut total, &refresh);
+  }
+
+  Ok(total)
+}
+
+// Both operands must be encrypted booleans (0 or 1), e.g. the output of a `QueryMode::MeetsTarget` query
+fn and_results(key: &ServerKey, a: &Ciphertext, b: &Ciphertext) -> Ciphertext {
+  key.smart_mul_lsb(&mut a.clone(), &mut b.clone())
+}
+
+fn or_results(key: &ServerKey, a: &Ciphertext, b: &Ciphertext) -> Ciphertext {
+  let mut sum = key.smart_add(&mut a.clone(), &mut b.clone());
+  let clamp = key.generate_lookup_table(|x| x.min(1));
+  key.apply_lookup_table_assign(&mut sum, &clamp);
+  sum
+}
+
+// Single-server PIR: a one-hot equality vector over positions, dotted with the plaintext counts
+fn pir_fetch(key: &ServerKey, enc_index: &Ciphertext, counts: &[u8]) -> Ciphertext {
+  // Positions past the message space could never be selected
+  assert!(counts.len() <= key.message_modulus.0);
+
+  let mut enc_index = enc_index.clone();
+  let selected = counts
+    .iter()
+    .enumerate()
+    .map(|(position, cnt)| {
+      let mut is_position = key.smart_scalar_equal(&mut enc_index, position as u8);
+      key.smart_scalar_mul(&mut is_position, *cnt)
+    })
+    .collect();
+
+  sum_balanced(key, selected)
+}
+
+fn combine_results(key: &ServerKey, results: &[Ciphertext]) -> Ciphertext {
+  sum_balanced(key, results.to_vec())
+}
+
+fn select_if_equal(key: &ServerKey, target: &Ciphertext, code: u8, value: &Ciphertext) -> Ciphertext {
+  // 1 if the target matches the code, 0 otherwise
+  let mut is_equal = key.smart_scalar_equal(&mut target.clone(), code);
+
+  key.smart_mul_lsb(&mut is_equal, &mut value.clone())
+}
+
+// `condition` must be an encrypted boolean (0 or 1)
+fn select(key: &ServerKey, condition: &Ciphertext, if_true: &Ciphertext, if_false: &Ciphertext) -> Ciphertext {
+  let mut chosen_true = select_if_equal(key, condition, 1, if_true);
+  let mut chosen_false = select_if_equal(key, condition, 0, if_false);
+  key.smart_add(&mut chosen_true, &mut chosen_false)
+}
+
+// Running max-and-argmax over (code, count) pairs, ties keep the earliest entry
+fn top_item(key: &ServerKey, entries: Vec<(Ciphertext, Ciphertext)>) -> (Ciphertext, Ciphertext) {
+  let mut entries = entries.into_iter();
+  let Some((mut best_code, mut best_count)) = entries.next() else {
+    return (key.create_trivial(0), key.create_trivial(0));
+  };
+
+  for (code, mut count) in entries {
+    let is_greater = key.smart_greater(&mut count, &mut best_count);
+    best_count = select(key, &is_greater, &count, &best_count);
+    best_code = select(key, &is_greater, &code, &best_code);
+  }
+
+  (best_code, best_count)
+}
+
+// Entries sharing a code are merged first, in order of first appearance, so an item competes with its
+// whole stock and ties still go to the item listed first. Every code and merged total has to fit in the
+// message space, which keeps the comparisons exact: the result is always the plaintext argmax.
+fn query_top_item(key: &ServerKey, inventory: &[(u8, u8)]) -> Result<(Ciphertext, Ciphertext), QueryError> {
+  let modulus = key.message_modulus.0 as u64;
+  let mut totals: Vec<(u8, u64)> = Vec::new();
+  for (idx, cnt) in inventory {
+    if *idx as u64 >= modulus {
+      return Err(QueryError::CodeOutOfRange { code: *idx });
+    }
+    match totals.iter_mut().find(|(code, _)| code == idx) {
+      Some((_, total)) => *total += *cnt as u64,
+      None => totals.push((*idx, *cnt as u64)),
+    }
+  }
+  if let Some((code, _)) = totals.iter().find(|(_, total)| *total >= modulus) {
+    return Err(QueryError::TotalOutOfRange { code: *code });
+  }
+
+  let entries = totals
+    .into_iter()
+    .map(|(idx, total)| (key.create_trivial(idx as u64), key.create_trivial(total)))
+    .collect();
+
+  Ok(top_item(key, entries))
+}
+
+// Duplicate codes are summed exactly like in `query`
+fn query_u8_codes(
+  key: &integer::ServerKey,
+  target: &RadixCiphertext,
+  inventory: &[(u8, u8)],
+) -> Result<RadixCiphertext, QueryError> {
+  query_radix(key, target, inventory)
+}
+
+// Codes and counts of any unsigned width up to 32 bits. A code is compared in as many blocks as the
+// target has, so codes wider than the target are refused rather than truncated onto another item.
+fn query_radix<T>(
+  key: &integer::ServerKey,
+  target: &RadixCiphertext,
+  inventory: &[(T, T)],
+) -> Result<RadixCiphertext, QueryError>
+where
+  T: ScalarMultiplier + DecomposableInto<u8> + DecomposableInto<u64> + Into<u64>,
+{
+  let block_bits = key.message_modulus().0.ilog2();
+  let code_bits = block_bits * target.blocks().len() as u32;
+  if let Some(code) = inventory
+    .iter()
+    .map(|(idx, _)| (*idx).into())
+    .find(|code: &u64| code.checked_shr(code_bits).unwrap_or(0) != 0)
+  {
+    return Err(QueryError::CodeTooWide { code, bits: code_bits });
+  }
+
+  let mut target = target.clone();
+  // Size the accumulator so that even the sum of every count cannot wrap
+  let max_total: u64 = inventory.iter().map(|(_, cnt)| (*cnt).into()).sum();
+  let total_bits = u64::BITS - max_total.leading_zeros();
+  let num_blocks = target.blocks().len().max(total_bits.div_ceil(block_bits) as usize);
+
+  let mut result: RadixCiphertext = key.create_trivial_zero_radix(num_blocks);
+
+  for (idx, cnt) in inventory {
+    let mut code: RadixCiphertext = key.create_trivial_radix(*idx, target.blocks().len());
+    let item_equality = key.smart_eq(&mut target, &mut code);
+    let mut item_equality: RadixCiphertext = item_equality.into_radix(num_blocks, key);
+    let mut contribution = key.smart_scalar_mul(&mut item_equality, *cnt);
+    result = key.smart_add(&mut result, &mut contribution);
+  }
+
+  Ok(result)
+}
+
+fn result_to_base64(result: &Ciphertext) -> String {
+  let bytes = bincode::serialize(result).expect("Ciphertext serialization cannot fail");
+  BASE64.encode(bytes)
+}
+
+fn result_from_base64(encoded: &str) -> Result<Ciphertext, DecodeError> {
+  let bytes = BASE64.decode(encoded).map_err(DecodeError::Base64)?;
+  bincode::deserialize(&bytes).map_err(DecodeError::Ciphertext)
+}
+
+fn save_compressed<W: Write>(key: &CompressedServerKey, writer: W) -> bincode::Result<()> {
+  bincode::serialize_into(writer, key)
+}
+
+// Decompression happens here, so callers only ever hold the compressed form on disk or on the wire
+fn load_compressed<R: Read>(reader: R) -> bincode::Result<ServerKey> {
+  let compressed: CompressedServerKey = bincode::deserialize_from(reader)?;
+  Ok(ServerKey::from(compressed))
+}
+
+// Header: magic (4 bytes) | format version (u8) | object kind (u8), followed by the bincode payload.
+// The kind keeps a ciphertext from being loaded where a key was expected, and the other way round.
+const FORMAT_MAGIC: [u8; 4] = *b"FHEI";
+const FORMAT_VERSION: u8 = 1;
+
+trait Versioned: Serialize + DeserializeOwned {
+  const KIND: u8;
+}
+
+impl Versioned for ClientKey {
+  const KIND: u8 = 1;
+}
+
+impl Versioned for ServerKey {
+  const KIND: u8 = 2;
+}
+
+impl Versioned for Ciphertext {
+  const KIND: u8 = 3;
+}
+
+// Only fresh encryptions compress, so results always travel in full
+impl Versioned for CompressedCiphertext {
+  const KIND: u8 = 4;
+}
+
+impl Versioned for CompressedServerKey {
+  const KIND: u8 = 5;
+}
+
+impl Versioned for CompactPublicKey {
+  const KIND: u8 = 6;
+}
+
+impl Versioned for CompressedCompactPublicKey {
+  const KIND: u8 = 7;
+}
+
+fn write_versioned<T: Versioned, W: Write>(value: &T, mut writer: W) -> Result<(), FormatError> {
+  writer.write_all(&FORMAT_MAGIC).map_err(FormatError::Io)?;
+  writer.write_all(&[FORMAT_VERSION, T::KIND]).map_err(FormatError::Io)?;
+  bincode::serialize_into(&mut writer, value).map_err(FormatError::Payload)?;
+  writer.flush().map_err(FormatError::Io)
+}
+
+fn read_versioned<T: Versioned, R: Read>(mut reader: R) -> Result<T, FormatError> {
+  let mut header = [0u8; 6];
+  reader.read_exact(&mut header).map_err(FormatError::Io)?;
+  if header[..4] != FORMAT_MAGIC {
+    return Err(FormatError::BadMagic);
+  }
+  if header[4] != FORMAT_VERSION {
+    return Err(FormatError::UnsupportedVersion { version: header[4] });
+  }
+  if header[5] != T::KIND {
+    return Err(FormatError::WrongKind { expected: T::KIND, found: header[5] });
+  }
+  bincode::deserialize_from(reader).map_err(FormatError::Payload)
+}
+
+fn to_versioned_bytes<T: Versioned>(value: &T) -> Vec<u8> {
+  let mut bytes = Vec::new();
+  write_versioned(value, &mut bytes).expect("Writing to a vector cannot fail");
+  bytes
+}
+
+fn from_versioned_bytes<T: Versioned>(bytes: &[u8]) -> Result<T, FormatError> {
+  read_versioned(bytes)
+}
+
+// Takes either the full form or the compressed one and decompresses the latter, so senders
+// can pick whichever suits their bandwidth
+fn from_maybe_compressed_bytes<T, C>(bytes: &[u8]) -> Result<T, FormatError>
+where
+  T: Versioned,
+  C: Versioned + Into<T>,
+{
+  match bytes.get(FORMAT_MAGIC.len() + 1) {
+    Some(&kind) if kind == C::KIND => from_versioned_bytes::<C>(bytes).map(Into::into),
+    _ => from_versioned_bytes(bytes),
+  }
+}
+
+fn save_to_file<T: Versioned, P: AsRef<Path>>(value: &T, path: P) -> Result<(), FormatError> {
+  let file = File::create(path).map_err(FormatError::Io)?;
+  write_versioned(value, BufWriter::new(file))
+}
+
+fn load_from_file<T: Versioned, P: AsRef<Path>>(path: P) -> Result<T, FormatError> {
+  let file = File::open(path).map_err(FormatError::Io)?;
+  read_versioned(BufReader::new(file))
+}
+
+fn is_in_stock(decryptor: &dyn Decryptor, result: &Ciphertext) -> bool {
+  decryptor.decrypt_count(result) > 0
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+enum QueryMode {
+  Count,
+  SaturatingCount,
+  InStock,
+  MeetsTarget(u8),
+  // Reorder alert: 1 while the stock is under the threshold, without the server learning the stock itself
+  BelowThreshold(u8),
+  // The target is an encrypted position in the inventory rather than a code, answered with `pir_fetch`
+  Index,
+}
+
+struct FheQuery<'a> {
+  key: &'a ServerKey,
+  target: Option<&'a Ciphertext>,
+  inventory: Option<&'a [(u8, u8)]>,
+  mode: QueryMode,
+}
+
+impl<'a> FheQuery<'a> {
+  fn new(key: &'a ServerKey) -> Self {
+    Self {
+      key,
+      target: None,
+      inventory: None,
+      mode: QueryMode::Count,
+    }
+  }
+
+  fn target(mut self, target: &'a Ciphertext) -> Self {
+    self.target = Some(target);
+    self
+  }
+
+  fn inventory(mut self, inventory: &'a [(u8, u8)]) -> Self {
+    self.inventory = Some(inventory);
+    self
+  }
+
+  fn mode(mut self, mode: QueryMode) -> Self {
+    self.mode = mode;
+    self
+  }
+
+  fn run(self) -> Result<Ciphertext, QueryError> {
+    let target = self.target.ok_or(QueryError::MissingTarget)?;
+    let inventory = self.inventory.ok_or(QueryError::MissingInventory)?;
+
+    // Every mode sums counts, and `CarryTracker` cannot fit even one addition without a carry bit
+    if self.key.carry_modulus.0 <= 1 {
+      return Err(QueryError::NoCarrySpace);
+    }
+
+    // A code outside the message space could never match, which is almost certainly a caller bug
+    let modulus = self.key.message_modulus.0 as u64;
+    if let Some((code, _)) = inventory.iter().find(|(code, _)| *code as u64 >= modulus) {
+      return Err(QueryError::CodeOutOfRange { code: *code });
+    }
+
+    // Saturating at the largest count keeps every comparison exact while the threshold is representable
+    match self.mode {
+      QueryMode::Count => Ok(query(self.key, target, inventory)),
+      QueryMode::SaturatingCount => Ok(query_saturating(self.key, target, inventory)),
+      QueryMode::InStock => {
+        Ok(self.key.smart_scalar_greater_or_equal(&mut query_saturating(self.key, target, inventory), 1))
+      }
+      QueryMode::MeetsTarget(threshold) | QueryMode::BelowThreshold(threshold) if threshold as u64 >= modulus => {
+        Err(QueryError::ThresholdOutOfRange { threshold })
+      }
+      QueryMode::MeetsTarget(threshold) => {
+        Ok(self.key.smart_scalar_greater_or_equal(&mut query_saturating(self.key, target, inventory), threshold))
+      }
+      QueryMode::BelowThreshold(threshold) => {
+        Ok(self.key.smart_scalar_less(&mut query_saturating(self.key, target, inventory), threshold))
+      }
+      // Positions past the message space could never be selected
+      QueryMode::Index if inventory.len() as u64 > modulus => {
+        Err(QueryError::TooManyEntries { entries: inventory.len() })
+      }
+      QueryMode::Index => {
+        let counts: Vec<u8> = inventory.iter().map(|(_, cnt)| *cnt).collect();
+        Ok(pir_fetch(self.key, target, &counts))
+      }
+    }
+  }
+}
+
+// One "code count" pair per line; blank lines and lines starting with # are skipped
+fn parse_inventory(text: &str) -> Result<Vec<(u8, u8)>, InventoryError> {
+  text
+    .lines()
+    .enumerate()
+    .map(|(number, line)| (number + 1, line.trim()))
+    .filter(|(_, line)| !line.is_empty() && !line.starts_with('#'))
+    .map(|(line_number, line)| {
+      let error = InventoryError { line: line_number };
+      let mut fields = line.split_whitespace();
+      let code = fields.next().and_then(|code| code.parse().ok()).ok_or(error)?;
+      let count = fields.next().and_then(|count| count.parse().ok()).ok_or(error)?;
+      match fields.next() {
+        Some(_) => Err(error),
+        None => Ok((code, count)),
+      }
+    })
+    .collect()
+}
+
+// Content-Length comes from the peer, so bodies are capped well above any ciphertext before allocating
+const MAX_BODY_LEN: usize = 1 << 20;
+// The start line and headers share one cap, so neither a newline-free stream nor endless headers grow unbounded
+const MAX_HEAD_LEN: u64 = 8 << 10;
+// A silent peer would otherwise hold the single-threaded server forever
+const IO_TIMEOUT: Duration = Duration::from_secs(30);
+
+// Just enough HTTP/1.1 for the demo: a start line, headers, and a body sized by Content-Length
+fn read_http_message<R: BufRead>(reader: &mut R) -> Result<(String, Vec<u8>), HttpError> {
+  // A line cut short by the end of the stream or by the cap never ends in a newline
+  let mut head = reader.by_ref().take(MAX_HEAD_LEN);
+  let mut read_head_line = || {
+    let mut line = String::new();
+    head.read_line(&mut line).map_err(HttpError::Io)?;
+    if line.ends_with('\n') {
+      Ok(line)
+    } else {
+      Err(HttpError::Malformed)
+    }
+  };
+
+  let start_line = read_head_line()?;
+
+  let mut content_length = 0;
+  loop {
+    let header = read_head_line()?;
+    let header = header.trim_end();
+    if header.is_empty() {
+      break;
+    }
+    let (name, value) = header.split_once(':').ok_or(HttpError::Malformed)?;
+    if name.eq_ignore_ascii_case("content-length") {
+      content_length = value.trim().parse().map_err(|_| HttpError::Malformed)?;
+    }
+  }
+  if content_length > MAX_BODY_LEN {
+    return Err(HttpError::Malformed);
+  }
+
+  let mut body = vec![0u8; content_length];
+  reader.read_exact(&mut body).map_err(HttpError::Io)?;
+  Ok((start_line.trim_end().to_string(), body))
+}
+
+fn write_http_response<W: Write>(mut writer: W, status: &str, body: &[u8]) -> io::Result<()> {
+  write!(writer, "HTTP/1.1 {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n", status, body.len())?;
+  writer.write_all(body)?;
+  writer.flush()
+}
+
+// Answers one `POST /query` whose body is a versioned target ciphertext with the versioned count
+fn handle_query_request<S: Read + Write>(
+  mut stream: S,
+  key: &ServerKey,
+  inventory: &[(u8, u8)],
+) -> Result<(), HttpError> {
+  let (request_line, body) = read_http_message(&mut BufReader::new(&mut stream))?;
+  let mut parts = request_line.split_whitespace();
+  if (parts.next(), parts.next()) != (Some("POST"), Some("/query")) {
+    return write_http_response(stream, "404 Not Found", b"only POST /query is served").map_err(HttpError::Io);
+  }
+
+  let answer = from_maybe_compressed_bytes::<Ciphertext, CompressedCiphertext>(&body)
+    .map_err(|err| err.to_string())
+    .and_then(|target| {
+      FheQuery::new(key)
+        .target(&target)
+        .inventory(inventory)
+        .run()
+        .map_err(|err| err.to_string())
+    });
+  match answer {
+    Ok(result) => write_http_response(stream, "200 OK", &to_versioned_bytes(&result)),
+    Err(message) => write_http_response(stream, "400 Bad Request", message.as_bytes()),
+  }
+  .map_err(HttpError::Io)
+}
+
+// Serves one connection at a time until the process is stopped; failed requests are only logged
+fn serve<A: ToSocketAddrs>(addr: A, key: &ServerKey, inventory: &[(u8, u8)]) -> io::Result<()> {
+  let listener = TcpListener::bind(addr)?;
+  for stream in listener.incoming() {
+    let stream = stream.and_then(|stream| {
+      stream.set_read_timeout(Some(IO_TIMEOUT))?;
+      stream.set_write_timeout(Some(IO_TIMEOUT))?;
+      Ok(stream)
+    });
+    if let Err(err) = stream.map_err(HttpError::Io).and_then(|stream| handle_query_request(stream, key, inventory)) {
+      eprintln!("request failed: {}", err);
+    }
+  }
+  Ok(())
+}
+
+// The target may be a `Ciphertext` or a `CompressedCiphertext`; the answer is always a full ciphertext
+fn send_query<S: Read + Write, T: Versioned>(mut stream: S, host: &str, target: &T) -> Result<Ciphertext, HttpError> {
+  let body = to_versioned_bytes(target);
+  write!(
+    stream,
+    "POST /query HTTP/1.1\r\nHost: {}\r\nContent-Type: application/octet-stream\r\nContent-Length: {}\r\n\r\n",
+    host,
+    body.len()
+  )
+  .map_err(HttpError::Io)?;
+  stream.write_all(&body).map_err(HttpError::Io)?;
+  stream.flush().map_err(HttpError::Io)?;
+
+  let (status_line, body) = read_http_message(&mut BufReader::new(stream))?;
+  let code: u16 = status_line
+    .split_whitespace()
+    .nth(1)
+    .and_then(|code| code.parse().ok())
+    .ok_or(HttpError::Malformed)?;
+  if code != 200 {
+    let body = String::from_utf8_lossy(&body).into_owned();
+    return Err(HttpError::Status { code, body });
+  }
+  from_versioned_bytes(&body).map_err(HttpError::Format)
+}
+
+#[cfg(feature = "wasm")]
+mod wasm {
+  use tfhe::shortint::parameters::PARAM_MESSAGE_2_CARRY_2_KS_PBS;
+  use tfhe::shortint::prelude::*;
+  use wasm_bindgen::prelude::*;
+
+  fn to_js_error(err: bincode::Error) -> JsError {
+    JsError::new(&err.to_string())
+  }
+
+  #[wasm_bindgen]
+  pub fn wasm_generate_client_key() -> Result<Vec<u8>, JsError> {
+    let client_key = ClientKey::new(PARAM_MESSAGE_2_CARRY_2_KS_PBS);
+    bincode::serialize(&client_key).map_err(to_js_error)
+  }
+
+  #[wasm_bindgen]
+  pub fn wasm_server_key(client_key: &[u8]) -> Result<Vec<u8>, JsError> {
+    let client_key: ClientKey = bincode::deserialize(client_key).map_err(to_js_error)?;
+    let server_key = ServerKey::new(&client_key);
+    bincode::serialize(&server_key).map_err(to_js_error)
+  }
+
+  #[wasm_bindgen]
+  pub fn wasm_encrypt_target(client_key: &[u8], code: u8) -> Result<Vec<u8>, JsError> {
+    let client_key: ClientKey = bincode::deserialize(client_key).map_err(to_js_error)?;
+    let target = client_key.encrypt(code as u64);
+    bincode::serialize(&target).map_err(to_js_error)
+  }
+
+  #[wasm_bindgen]
+  pub fn wasm_decrypt_result(client_key: &[u8], result: &[u8]) -> Result<u64, JsError> {
+    let client_key: ClientKey = bincode::deserialize(client_key).map_err(to_js_error)?;
+    let result: Ciphertext = bincode::deserialize(result).map_err(to_js_error)?;
+    Ok(client_key.decrypt(&result))
+  }
+
+  #[cfg(test)]
+  mod tests {
+    use wasm_bindgen_test::*;
+
+    use super::*;
+
+    wasm_bindgen_test_configure!(run_in_browser);
+
+    #[wasm_bindgen_test]
+    fn test_wasm_encrypt_decrypt() {
+      let client_key = wasm_generate_client_key().unwrap();
+
+      // A freshly encrypted target doubles as a result to decrypt
+      let result = wasm_encrypt_target(&client_key, 3).unwrap();
+      assert_eq!(wasm_decrypt_result(&client_key, &result).unwrap(), 3);
+    }
+  }
+}
+
+const USAGE: &str = "usage:
+  FHE keygen CLIENT_KEY SERVER_KEY PUBLIC_KEY   write fresh client, server and public keys
+  FHE serve SERVER_KEY INVENTORY ADDR           answer queries over HTTP on ADDR
+  FHE query CLIENT_KEY ADDR CODE                ask the server at ADDR for the stock of CODE
+  FHE submit PUBLIC_KEY ADDR CODE               same, printing the still encrypted result as base64
+  FHE decrypt CLIENT_KEY RESULT                 decrypt a result printed by submit";
+
+// The server and the clients of the demo; they share nothing but the key files and HTTP.
+// With `submit`, any number of parties holding only the public key can query, while the
+// results stay readable by the client key holder alone.
+pub(crate) fn cli() -> Result<(), Box<dyn std::error::Error>> {
+  let args: Vec<String> = std::env::args().skip(1).collect();
+  let args: Vec<&str> = args.iter().map(String::as_str).collect();
+
+  match args[..] {
+    ["keygen", client_key_path, server_key_path, public_key_path] => {
+      let client_key = ClientKey::new(PARAM_MESSAGE_2_CARRY_2_COMPACT_PK_KS_PBS);
+      save_to_file(&client_key, client_key_path)?;
+      save_to_file(&CompressedServerKey::new(&client_key), server_key_path)?;
+      save_to_file(&CompressedCompactPublicKey::new(&client_key), public_key_path)?;
+    }
+    ["serve", server_key_path, inventory_path, addr] => {
+      let server_key_bytes = std::fs::read(server_key_path)?;
+      let server_key = from_maybe_compressed_bytes::<ServerKey, CompressedServerKey>(&server_key_bytes)?;
+      let inventory = parse_inventory(&std::fs::read_to_string(inventory_path)?)?;
+      eprintln!("serving {} inventory entries on {}", inventory.len(), addr);
+      serve(addr, &server_key, &inventory)?;
+    }
+    ["query", client_key_path, addr, code] => {
+      let client_key: ClientKey = load_from_file(client_key_path)?;
+      let code = check_code(code.parse()?, client_key.parameters.message_modulus())?;
+      let target = client_key.encrypt_compressed(code as u64);
+      let result = send_query(TcpStream::connect(addr)?, addr, &target)?;
+      println!("{}", client_key.decrypt(&result));
+    }
+    ["submit", public_key_path, addr, code] => {
+      let public_key_bytes = std::fs::read(public_key_path)?;
+      let public_key = from_maybe_compressed_bytes::<CompactPublicKey, CompressedCompactPublicKey>(&public_key_bytes)?;
+      let code = check_code(code.parse()?, public_key.parameters.message_modulus())?;
+      let result = send_query(TcpStream::connect(addr)?, addr, &public_key.encrypt(code as u64))?;
+      println!("{}", result_to_base64(&result));
+    }
+    ["decrypt", client_key_path, result] => {
+      let client_key: ClientKey = load_from_file(client_key_path)?;
+      println!("{}", client_key.decrypt(&result_from_base64(result)?));
+    }
+    _ => {
+      eprintln!("{}", USAGE);
+      std::process::exit(2);
+    }
+  }
+  Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+  use tfhe::shortint::prelude::*;
+  use tfhe::shortint::CompressedServerKey;
+  use tfhe::shortint::parameters::PARAM_MESSAGE_4_CARRY_0_KS_PBS;
+
+  use tfhe::shortint::parameters::PARAM_MESSAGE_2_CARRY_2_KS_PBS;
+  use tfhe::shortint::parameters::{
+    PARAM_MESSAGE_2_CARRY_2_COMPACT_PK_KS_PBS, PARAM_MESSAGE_2_CARRY_2_PBS_KS, PARAM_MESSAGE_3_CARRY_3_KS_PBS,
+  };
+  use tfhe::shortint::public_key::CompressedCompactPublicKey;
+
+  use tfhe::integer::gen_keys_radix;
+
+  use std::io::{BufReader, Write};
+  use std::time::Instant;
+
+  use super::{
+    and_results, cart_total, check_code, combine_results, compact_public_key, from_maybe_compressed_bytes,
+    from_versioned_bytes, handle_query_request, is_in_stock, load_compressed, load_from_file, or_results,
+    parse_inventory, pir_fetch, query, query_cached, query_encrypted_counts, query_encrypted_inventory,
+    query_full_space, query_iter, query_lookup, query_many, query_radix, query_range, query_saturating, query_top_item,
+    query_u8_codes, query_value, query_with_existence, read_http_message, restock, result_from_base64, result_to_base64,
+    save_compressed, save_to_file, select_if_equal, sell, send_query, sum_balanced, to_versioned_bytes, top_item,
+    CarryTracker, DecodeError, Decryptor, EqualityTables, FheQuery, FormatError, HttpError, InventoryError, Keys,
+    MAX_BODY_LEN, MAX_HEAD_LEN, ParameterError, QueryError, QueryMode,
+  };
+
+  struct MockDecryptor(u64);
+
+  impl Decryptor for MockDecryptor {
+    fn decrypt_count(&self, _ct: &Ciphertext) -> u64 {
+      self.0
+    }
+  }
+
+  fn sum_linear(key: &ServerKey, contributions: &mut [Ciphertext]) -> Ciphertext {
+    let mut result = key.create_trivial(0);
+    for contribution in contributions {
+      result = key.smart_add(&mut result, contribution);
+    }
+    result
+  }
+
+  #[test]
+  fn test_it() {
+    let (client_key, server_key) = gen_keys(PARAM_MESSAGE_2_CARRY_2_KS_PBS);
+
+    let item_code = 0u8;
+
+    let item_code_ciphertext = client_key.encrypt(item_code as u64);
+
+    assert_eq!(item_code as u64, client_key.decrypt(&item_code_ciphertext));
+
+    let stock_ciphertext = query(&server_key, &item_code_ciphertext, &[
+      (0, 2),
+      (1, 1),
+      (0, 1),
+    ]);
+
+    let stock_count = client_key.decrypt(&stock_ciphertext);
+
+    assert_eq!(stock_count, 3);
+  }
+
+  #[test]
+  fn test_select_if_equal() {
+    let (client_key, server_key) = gen_keys(PARAM_MESSAGE_2_CARRY_2_KS_PBS);
+
+    let target = client_key.encrypt(1);
+    let value = client_key.encrypt(3);
+
+    let selected = select_if_equal(&server_key, &target, 1, &value);
+    assert_eq!(client_key.decrypt(&selected), 3);
+
+    let selected = select_if_equal(&server_key, &target, 2, &value);
+    assert_eq!(client_key.decrypt(&selected), 0);
+  }
+  #[test]
+  fn test_query_borrows_inputs() {
+    let (client_key, server_key) = gen_keys(PARAM_MESSAGE_2_CARRY_2_KS_PBS);
+    let target = client_key.encrypt(1);
+
+    // The same key and target serve one request after another
+    let first = query(&server_key, &target, &[(1, 2), (0, 1)]);
+    let second = query(&server_key, &target, &[(1, 1), (1, 1), (2, 3)]);
+    assert_eq!(client_key.decrypt(&first), 2);
+    assert_eq!(client_key.decrypt(&second), 2);
+    assert_eq!(client_key.decrypt(&target), 1);
+  }
+  #[test]
+  fn test_query_u8_codes() {
+    let (client_key, server_key) = gen_keys_radix(PARAM_MESSAGE_2_CARRY_2_KS_PBS, 4);
+
+    let inventory = [
+      (200, 7),
+      (255, 9),
+      (200, 5),
+      (15, 1),
+    ];
+
+    let target = client_key.encrypt(200u8);
+    let stock_ciphertext = query_u8_codes(&server_key, &target, &inventory).unwrap();
+    assert_eq!(client_key.decrypt::<u64>(&stock_ciphertext), 12);
+
+    let target = client_key.encrypt(255u8);
+    let stock_ciphertext = query_u8_codes(&server_key, &target, &inventory).unwrap();
+    assert_eq!(client_key.decrypt::<u64>(&stock_ciphertext), 9);
+  }
+  #[test]
+  fn test_balanced_sum_large_inventory() {
+    let (client_key, server_key) = gen_keys(PARAM_MESSAGE_2_CARRY_2_KS_PBS);
+
+    // 32 non-matching entries around two matching ones
+    let mut inventory: Vec<(u8, u8)> = (0..32).map(|i| (i % 3, 1)).collect();
+    inventory.insert(5, (3, 2));
+    inventory.insert(20, (3, 1));
+
+    let target = client_key.encrypt(3);
+    let contributions: Vec<Ciphertext> = inventory
+      .iter()
+      .map(|(idx, cnt)| {
+        let mut item_equality = server_key.smart_scalar_equal(&mut target.clone(), *idx);
+        server_key.smart_scalar_mul(&mut item_equality, *cnt)
+      })
+      .collect();
+
+    let linear = sum_linear(&server_key, &mut contributions.clone());
+    let balanced = sum_balanced(&server_key, contributions);
+    assert_eq!(client_key.decrypt(&balanced), client_key.decrypt(&linear));
+
+    let stock_ciphertext = query(&server_key, &target, &inventory);
+    assert_eq!(client_key.decrypt(&stock_ciphertext), 3);
+  }
+  #[test]
+  fn test_carry_tracker_refreshes() {
+    for parameters in [PARAM_MESSAGE_2_CARRY_2_KS_PBS, PARAM_MESSAGE_3_CARRY_3_KS_PBS] {
+      let (client_key, server_key) = gen_keys(parameters);
+      let modulus = server_key.message_modulus.0 as u8;
+
+      // 50 entries are more additions than either set's carries and noise budget allow without a refresh
+      let inventory: Vec<(u8, u8)> = (0..50).map(|i| (i % modulus, 1)).collect();
+      let expected = inventory.iter().filter(|(code, _)| *code == 1).count() as u64 % modulus as u64;
+
+      let target = client_key.encrypt(1);
+      let contributions = inventory
+        .iter()
+        .map(|(idx, cnt)| {
+          let mut item_equality = server_key.smart_scalar_equal(&mut target.clone(), *idx);
+          server_key.smart_scalar_mul(&mut item_equality, *cnt)
+        })
+        .collect();
+
+      let mut tracker = CarryTracker::new(&server_key);
+      let total = tracker.sum(contributions);
+      assert!(tracker.refreshes > 0);
+      assert_eq!(client_key.decrypt(&total), expected);
+
+      let streamed = query_iter(&server_key, &target, inventory.iter().copied()).unwrap();
+      assert_eq!(client_key.decrypt(&streamed), expected);
+    }
+  }
+
+  #[test]
+  #[ignore = "benchmark, run with --ignored --nocapture"]
+  fn bench_balanced_sum() {
+    let (client_key, server_key) = gen_keys(PARAM_MESSAGE_2_CARRY_2_KS_PBS);
+
+    let contributions: Vec<Ciphertext> = (0..256).map(|i| client_key.encrypt(i % 2)).collect();
+
+    let start = Instant::now();
+    let _ = sum_linear(&server_key, &mut contributions.clone());
+    println!("linear:   255 adds deep, {:?}", start.elapsed());
+
+    let start = Instant::now();
+    let _ = sum_balanced(&server_key, contributions);
+    println!("balanced:   8 adds deep, {:?}", start.elapsed());
+  }
+  #[test]
+  fn test_combine_shard_results() {
+    let (client_key, server_key) = gen_keys(PARAM_MESSAGE_2_CARRY_2_KS_PBS);
+
+    let inventory = [(0, 1), (1, 2), (0, 1), (2, 1), (0, 1), (3, 2)];
+    let (shard_a, shard_b) = inventory.split_at(3);
+
+    let target = client_key.encrypt(0);
+    let shard_results = [
+      query(&server_key, &target, shard_a),
+      query(&server_key, &target, shard_b),
+    ];
+    let combined = combine_results(&server_key, &shard_results);
+
+    let whole = query(&server_key, &target, &inventory);
+    assert_eq!(client_key.decrypt(&combined), client_key.decrypt(&whole));
+    assert_eq!(client_key.decrypt(&combined), 3);
+  }
+  #[test]
+  fn test_result_base64_round_trip() {
+    let (client_key, server_key) = gen_keys(PARAM_MESSAGE_2_CARRY_2_KS_PBS);
+
+    let target = client_key.encrypt(1);
+    let stock_ciphertext = query(&server_key, &target, &[(1, 2), (0, 3)]);
+
+    let encoded = result_to_base64(&stock_ciphertext);
+    let decoded = result_from_base64(&encoded).unwrap();
+    assert_eq!(client_key.decrypt(&decoded), 2);
+
+    assert!(matches!(result_from_base64("not base64!"), Err(DecodeError::Base64(_))));
+
+    let truncated = &encoded[..encoded.len() / 2];
+    let truncated = &truncated[..truncated.len() - truncated.len() % 4];
+    assert!(matches!(result_from_base64(truncated), Err(DecodeError::Ciphertext(_))));
+  }
+  #[test]
+  fn test_mock_decryptor() {
+    // Any ciphertext will do, the mock never looks at it
+    let client_key = ClientKey::new(PARAM_MESSAGE_2_CARRY_2_KS_PBS);
+    let result = client_key.encrypt(0);
+
+    assert!(is_in_stock(&MockDecryptor(4), &result));
+    assert!(!is_in_stock(&MockDecryptor(0), &result));
+    assert!(!is_in_stock(&client_key, &result));
+  }
+  #[test]
+  fn test_duplicate_codes_are_summed() {
+    let inventory = [(0, 2), (1, 1), (0, 1)];
+
+    let (client_key, server_key) = gen_keys(PARAM_MESSAGE_2_CARRY_2_KS_PBS);
+    let target = client_key.encrypt(0);
+
+    let stock_ciphertext = query(&server_key, &target, &inventory);
+    assert_eq!(client_key.decrypt(&stock_ciphertext), 3);
+
+    // Splitting the duplicates across shards must not change the total
+    let shard_results = [
+      query(&server_key, &target, &inventory[..1]),
+      query(&server_key, &target, &inventory[1..]),
+    ];
+    assert_eq!(client_key.decrypt(&combine_results(&server_key, &shard_results)), 3);
+
+    let (client_key, server_key) = gen_keys_radix(PARAM_MESSAGE_2_CARRY_2_KS_PBS, 4);
+    let target = client_key.encrypt(0u8);
+    let stock_ciphertext = query_u8_codes(&server_key, &target, &inventory).unwrap();
+    assert_eq!(client_key.decrypt::<u64>(&stock_ciphertext), 3);
+  }
+  #[test]
+  fn test_duplicate_codes_across_modes() {
+    let (client_key, server_key) = gen_keys(PARAM_MESSAGE_2_CARRY_2_KS_PBS);
+    let tables = EqualityTables::new(&server_key);
+    // Item 0 is split over two entries, item 2 only reaches 4 once its entries are summed
+    let inventory = [(0, 2), (1, 1), (0, 1), (2, 3), (2, 1)];
+
+    for code in 0..4u8 {
+      let total = inventory.iter().filter(|(idx, _)| *idx == code).map(|(_, cnt)| *cnt as u64).sum::<u64>();
+      let saturated = total.min(3);
+      let target = client_key.encrypt(code as u64);
+
+      // Every mode sees one merged entry per code: counts wrap, saturated counts and flags clamp first
+      for (mode, expected) in [
+        (QueryMode::Count, total % 4),
+        (QueryMode::SaturatingCount, saturated),
+        (QueryMode::InStock, (saturated >= 1) as u64),
+        (QueryMode::MeetsTarget(2), (saturated >= 2) as u64),
+        (QueryMode::BelowThreshold(2), (saturated < 2) as u64),
+      ] {
+        let result = FheQuery::new(&server_key)
+          .target(&target)
+          .inventory(&inventory)
+          .mode(mode)
+          .run()
+          .unwrap();
+        assert_eq!(client_key.decrypt(&result), expected, "Failed code {} in mode {:?}", code, mode);
+      }
+
+      for (path, result) in [
+        ("lookup", query_lookup(&server_key, &target, &inventory)),
+        ("cached", query_cached(&server_key, &tables, &target, &inventory).unwrap()),
+        ("iter", query_iter(&server_key, &target, inventory).unwrap()),
+      ] {
+        assert_eq!(client_key.decrypt(&result), total % 4, "Failed code {} via {}", code, path);
+      }
+    }
+
+    // Index addresses entries, not items, so duplicates are never merged and each position keeps its count
+    let entries = &inventory[..4];
+    for (position, (_, cnt)) in entries.iter().enumerate() {
+      let target = client_key.encrypt(position as u64);
+      let result = FheQuery::new(&server_key)
+        .target(&target)
+        .inventory(entries)
+        .mode(QueryMode::Index)
+        .run()
+        .unwrap();
+      assert_eq!(client_key.decrypt(&result), *cnt as u64, "Failed position {}", position);
+    }
+
+    // The top item compares merged totals: item 0 beats the single larger entry of item 1
+    let (code, count) = query_top_item(&server_key, &[(0, 2), (1, 2), (0, 1)]).unwrap();
+    assert_eq!((client_key.decrypt(&code), client_key.decrypt(&count)), (0, 3));
+
+    // Ties keep the item listed first, whichever of its entries comes later
+    for (inventory, expected) in [
+      ([(1, 1), (3, 3), (1, 2)], (1, 3)),
+      ([(3, 3), (1, 1), (1, 2)], (3, 3)),
+      // Item 0 only overtakes item 2 with its last entry
+      ([(2, 2), (0, 1), (0, 2)], (0, 3)),
+    ] {
+      let (code, count) = query_top_item(&server_key, &inventory).unwrap();
+      let decrypted = (client_key.decrypt(&code), client_key.decrypt(&count));
+      assert_eq!(decrypted, expected, "Failed inventory {:?}", inventory);
+    }
+
+    // Item 0 holds 4, which no 2-bit count can carry, so there is no exact answer to give
+    let overflowing = query_top_item(&server_key, &[(2, 3), (0, 3), (0, 1)]);
+    assert_eq!(overflowing.err(), Some(QueryError::TotalOutOfRange { code: 0 }));
+    let out_of_range = query_top_item(&server_key, &[(1, 1), (4, 1)]);
+    assert_eq!(out_of_range.err(), Some(QueryError::CodeOutOfRange { code: 4 }));
+  }
+  #[test]
+  fn test_query_saturating() {
+    let (client_key, server_key) = gen_keys(PARAM_MESSAGE_2_CARRY_2_KS_PBS);
+    let inventory = [(1, 2), (0, 1), (1, 3), (2, 1)];
+
+    let target = client_key.encrypt(0);
+    let stock_ciphertext = query_saturating(&server_key, &target, &inventory);
+    assert_eq!(client_key.decrypt(&stock_ciphertext), 1);
+
+    // The true total of 5 does not fit in 2 message bits
+    let target = client_key.encrypt(1);
+    let stock_ciphertext = query_saturating(&server_key, &target, &inventory);
+    assert_eq!(client_key.decrypt(&stock_ciphertext), 3);
+  }
+  #[test]
+  fn test_query_meets_target() {
+    let (client_key, server_key) = gen_keys(PARAM_MESSAGE_2_CARRY_2_KS_PBS);
+    let inventory = [(1, 1), (0, 3), (1, 1)];
+    let target = client_key.encrypt(1);
+
+    let meets_target = |min_required| {
+      let result = FheQuery::new(&server_key)
+        .target(&target)
+        .inventory(&inventory)
+        .mode(QueryMode::MeetsTarget(min_required))
+        .run()
+        .unwrap();
+      client_key.decrypt(&result)
+    };
+    assert_eq!(meets_target(3), 0);
+    assert_eq!(meets_target(2), 1);
+    assert_eq!(meets_target(1), 1);
+  }
+  #[test]
+  fn test_keys_for_inventory() {
+    // Totals up to 100 need 7 bits, which only PARAM_MESSAGE_4_CARRY_4 provides
+    let keys = Keys::for_inventory(30, 100).unwrap();
+    let inventory = [(30, 60), (7, 3), (30, 40)];
+
+    let target = keys.encrypt_target(30);
+    let stock_ciphertext = query_full_space(&keys.server_key, &target, &inventory);
+    assert_eq!(keys.decrypt(&stock_ciphertext), 100);
+
+    let target = keys.encrypt_target(7);
+    let stock_ciphertext = query_full_space(&keys.server_key, &target, &inventory);
+    assert_eq!(keys.decrypt(&stock_ciphertext), 3);
+
+    assert_eq!(
+      Keys::for_inventory(30, 1000).err(),
+      Some(ParameterError::NoStandardSetFits { required_bits: 10 })
+    );
+  }
+  #[test]
+  fn test_builder_modes() {
+    let (client_key, server_key) = gen_keys(PARAM_MESSAGE_2_CARRY_2_KS_PBS);
+    let inventory = [(2, 1), (1, 3), (2, 1)];
+    let target = client_key.encrypt(2);
+
+    // Item 2 has 2 in stock; as a position, 2 is the last entry with its count of 1
+    for (mode, expected) in [
+      (QueryMode::Count, 2),
+      (QueryMode::SaturatingCount, 2),
+      (QueryMode::InStock, 1),
+      (QueryMode::MeetsTarget(3), 0),
+      (QueryMode::BelowThreshold(3), 1),
+      (QueryMode::Index, 1),
+    ] {
+      let result = FheQuery::new(&server_key)
+        .target(&target)
+        .inventory(&inventory)
+        .mode(mode)
+        .run()
+        .unwrap();
+      assert_eq!(client_key.decrypt(&result), expected, "Failed mode {:?}", mode);
+    }
+
+    // Count is the default mode
+    let count = FheQuery::new(&server_key).target(&target).inventory(&inventory).run().unwrap();
+    assert_eq!(client_key.decrypt(&count), 2);
+
+    let missing_target = FheQuery::new(&server_key).inventory(&inventory).run();
+    assert_eq!(missing_target.err(), Some(QueryError::MissingTarget));
+
+    let missing_inventory = FheQuery::new(&server_key).target(&target).run();
+    assert_eq!(missing_inventory.err(), Some(QueryError::MissingInventory));
+
+    let out_of_range = FheQuery::new(&server_key).target(&target).inventory(&[(4, 1)]).run();
+    assert_eq!(out_of_range.err(), Some(QueryError::CodeOutOfRange { code: 4 }));
+
+    let out_of_range = FheQuery::new(&server_key)
+      .target(&target)
+      .inventory(&inventory)
+      .mode(QueryMode::MeetsTarget(4))
+      .run();
+    assert_eq!(out_of_range.err(), Some(QueryError::ThresholdOutOfRange { threshold: 4 }));
+
+    let too_many = FheQuery::new(&server_key)
+      .target(&target)
+      .inventory(&[(0, 1); 5])
+      .mode(QueryMode::Index)
+      .run();
+    assert_eq!(too_many.err(), Some(QueryError::TooManyEntries { entries: 5 }));
+
+    let (client_key, server_key) = gen_keys(PARAM_MESSAGE_4_CARRY_0_KS_PBS);
+    let target = client_key.encrypt(2);
+    let carry_less = FheQuery::new(&server_key).target(&target).inventory(&inventory).run();
+    assert_eq!(carry_less.err(), Some(QueryError::NoCarrySpace));
+  }
+  #[test]
+  fn test_query_top_item() {
+    let (client_key, server_key) = gen_keys(PARAM_MESSAGE_2_CARRY_2_KS_PBS);
+    let inventory = [(0, 1), (1, 3), (2, 2), (3, 3)];
+
+    let (code, count) = query_top_item(&server_key, &inventory).unwrap();
+    assert_eq!(client_key.decrypt(&code), 1);
+    assert_eq!(client_key.decrypt(&count), 3);
+
+    // Same fold over freshly encrypted entries
+    let entries = inventory
+      .iter()
+      .map(|(idx, cnt)| (client_key.encrypt(*idx as u64), client_key.encrypt(*cnt as u64)))
+      .collect();
+    let (code, count) = top_item(&server_key, entries);
+    assert_eq!(client_key.decrypt(&code), 1);
+    assert_eq!(client_key.decrypt(&count), 3);
+  }
+  #[test]
+  fn test_compressed_server_key() {
+    let (client_key, server_key) = gen_keys(PARAM_MESSAGE_2_CARRY_2_KS_PBS);
+    let compressed = CompressedServerKey::new(&client_key);
+
+    let mut compressed_bytes = Vec::new();
+    save_compressed(&compressed, &mut compressed_bytes).unwrap();
+    let full_bytes = bincode::serialize(&server_key).unwrap();
+    assert!(compressed_bytes.len() < full_bytes.len());
+
+    let decompressed_key = load_compressed(compressed_bytes.as_slice()).unwrap();
+
+    let inventory = [(1, 2), (2, 1), (1, 1)];
+    let target = client_key.encrypt(1);
+    let direct = query(&server_key, &target, &inventory);
+    let decompressed = query(&decompressed_key, &target, &inventory);
+    assert_eq!(client_key.decrypt(&decompressed), client_key.decrypt(&direct));
+    assert_eq!(client_key.decrypt(&decompressed), 3);
+  }
+  #[test]
+  fn test_query_cached() {
+    let (client_key, server_key) = gen_keys(PARAM_MESSAGE_2_CARRY_2_KS_PBS);
+    let tables = EqualityTables::new(&server_key);
+    let inventory = [(3, 1), (0, 2), (3, 2), (1, 1)];
+
+    for code in 0..4 {
+      let target = client_key.encrypt(code);
+      let cached = query_cached(&server_key, &tables, &target, &inventory).unwrap();
+      let uncached = query(&server_key, &target, &inventory);
+      assert_eq!(client_key.decrypt(&cached), client_key.decrypt(&uncached), "Failed code {}", code);
+    }
+
+    let target = client_key.encrypt(0);
+    let out_of_range = query_cached(&server_key, &tables, &target, &[(0, 1), (4, 1)]);
+    assert_eq!(out_of_range.err(), Some(QueryError::CodeOutOfRange { code: 4 }));
+  }
+
+  #[test]
+  #[ignore = "benchmark, run with --ignored --nocapture"]
+  fn bench_query_cached() {
+    let (client_key, server_key) = gen_keys(PARAM_MESSAGE_2_CARRY_2_KS_PBS);
+    let inventory: Vec<(u8, u8)> = (0..64).map(|i| (i % 4, 1)).collect();
+    let target = client_key.encrypt(2);
+
+    let start = Instant::now();
+    let _ = query(&server_key, &target, &inventory);
+    println!("cold:        {:?}", start.elapsed());
+
+    let start = Instant::now();
+    let tables = EqualityTables::new(&server_key);
+    println!("table setup: {:?}", start.elapsed());
+
+    let start = Instant::now();
+    let _ = query_cached(&server_key, &tables, &target, &inventory).unwrap();
+    println!("warm:        {:?}", start.elapsed());
+  }
+  #[test]
+  fn test_boolean_combinations() {
+    let (client_key, server_key) = gen_keys(PARAM_MESSAGE_2_CARRY_2_KS_PBS);
+
+    for (a, b) in [(0, 0), (0, 1), (1, 0), (1, 1)] {
+      let enc_a = client_key.encrypt(a);
+      let enc_b = client_key.encrypt(b);
+
+      let and = and_results(&server_key, &enc_a, &enc_b);
+      assert_eq!(client_key.decrypt(&and), a & b, "Failed {} AND {}", a, b);
+
+      let or = or_results(&server_key, &enc_a, &enc_b);
+      assert_eq!(client_key.decrypt(&or), a | b, "Failed {} OR {}", a, b);
+    }
+  }
+  #[test]
+  fn test_query_iter() {
+    let (client_key, server_key) = gen_keys(PARAM_MESSAGE_2_CARRY_2_KS_PBS);
+    let entries = || (0..10u8).filter(|i| i % 3 != 0).map(|i| (i % 4, 1));
+    let inventory: Vec<(u8, u8)> = entries().collect();
+
+    let target = client_key.encrypt(1);
+    let streamed = query_iter(&server_key, &target, entries()).unwrap();
+    let sliced = query(&server_key, &target, &inventory);
+    assert_eq!(client_key.decrypt(&streamed), client_key.decrypt(&sliced));
+    assert_eq!(client_key.decrypt(&streamed), 2);
+
+    let out_of_range = query_iter(&server_key, &target, [(1, 1), (9, 1)]);
+    assert_eq!(out_of_range.err(), Some(QueryError::CodeOutOfRange { code: 9 }));
+  }
+  #[test]
+  fn test_message_2_carry_2_totals() {
+    let keys = Keys::for_inventory(3, 10).unwrap();
+    assert_eq!(keys.client_key.parameters.message_modulus(), PARAM_MESSAGE_2_CARRY_2_KS_PBS.message_modulus);
+    assert_eq!(keys.client_key.parameters.carry_modulus(), PARAM_MESSAGE_2_CARRY_2_KS_PBS.carry_modulus);
+
+    let inventory = [(3, 4), (1, 2), (3, 6)];
+    let target = keys.encrypt_target(3);
+    let stock_ciphertext = query_full_space(&keys.server_key, &target, &inventory);
+    assert_eq!(keys.decrypt(&stock_ciphertext), 10);
+
+    // Confined to the 2 message bits the same total wraps, like any set without carry space would
+    let target = keys.client_key.encrypt(3);
+    let stock_ciphertext = query(&keys.server_key, &target, &inventory);
+    assert_eq!(keys.client_key.decrypt(&stock_ciphertext), 10 % 4);
+
+    // The remaining query paths under the same set
+    let target = keys.client_key.encrypt(3);
+    let saturated = query_saturating(&keys.server_key, &target, &inventory);
+    assert_eq!(keys.client_key.decrypt(&saturated), 3);
+    let streamed = query_iter(&keys.server_key, &target, [(3, 1), (1, 2), (3, 1)]).unwrap();
+    assert_eq!(keys.client_key.decrypt(&streamed), 2);
+  }
+  #[test]
+  fn test_pir_fetch() {
+    let (client_key, server_key) = gen_keys(PARAM_MESSAGE_2_CARRY_2_KS_PBS);
+    let counts = [3, 1, 0, 2];
+
+    for (position, count) in counts.iter().enumerate() {
+      let enc_index = client_key.encrypt(position as u64);
+      let fetched = pir_fetch(&server_key, &enc_index, &counts);
+      assert_eq!(client_key.decrypt(&fetched), *count as u64, "Failed position {}", position);
+    }
+  }
+  #[test]
+  fn test_cart_total() {
+    let keys = Keys::for_inventory(3, 15).unwrap();
+    let inventory = [(1, 2), (2, 3), (3, 1)];
+    let prices = [(1, 2), (2, 1), (3, 5)];
+
+    let cart = [keys.encrypt_target(1), keys.encrypt_target(2)];
+    let total = cart_total(&keys.server_key, &cart, &inventory, &prices).unwrap();
+
+    // 2 items at 2 plus 3 items at 1
+    assert_eq!(keys.decrypt(&total), 7);
+
+    let value = query_value(&keys.server_key, &cart[0], &inventory, &prices).unwrap();
+    assert_eq!(keys.decrypt(&value), 4);
+
+    // 200 items at 2 is worth more than 8 bits hold, even if no cart holds that item
+    let overflowing = [(1, 2), (2, 200)];
+    let value = query_value(&keys.server_key, &cart[0], &overflowing, &[(2, 2)]);
+    assert_eq!(value.err(), Some(QueryError::ValueOverflow { code: 2 }));
+    let total = cart_total(&keys.server_key, &cart, &overflowing, &[(2, 2)]);
+    assert_eq!(total.err(), Some(QueryError::ValueOverflow { code: 2 }));
+  }
+  #[test]
+  fn test_query_encrypted_counts() {
+    let (client_key, server_key) = gen_keys(PARAM_MESSAGE_2_CARRY_2_KS_PBS);
+    let plain = [(1, 2), (0, 3), (1, 1), (2, 1)];
+    let inventory: Vec<(u8, Ciphertext)> =
+      plain.iter().map(|(idx, cnt)| (*idx, client_key.encrypt(*cnt as u64))).collect();
+
+    for code in 0..4 {
+      let target = client_key.encrypt(code);
+      let hidden = query_encrypted_counts(&server_key, &target, &inventory);
+      let visible = query(&server_key, &target, &plain);
+      assert_eq!(client_key.decrypt(&hidden), client_key.decrypt(&visible), "Failed code {}", code);
+    }
+  }
+  #[test]
+  fn test_query_encrypted_inventory() {
+    let (client_key, server_key) = gen_keys(PARAM_MESSAGE_2_CARRY_2_KS_PBS);
+    let plain = [(3, 1), (0, 2), (3, 2), (1, 1)];
+    let inventory: Vec<(Ciphertext, Ciphertext)> = plain
+      .iter()
+      .map(|(idx, cnt)| (client_key.encrypt(*idx as u64), client_key.encrypt(*cnt as u64)))
+      .collect();
+
+    for code in 0..4 {
+      let target = client_key.encrypt(code);
+      let hidden = query_encrypted_inventory(&server_key, &target, &inventory);
+      let visible = query(&server_key, &target, &plain);
+      assert_eq!(client_key.decrypt(&hidden), client_key.decrypt(&visible), "Failed code {}", code);
+    }
+  }
+  #[test]
+  fn test_restock_and_sell() {
+    let (client_key, server_key) = gen_keys(PARAM_MESSAGE_2_CARRY_2_KS_PBS);
+    let mut inventory: Vec<(Ciphertext, Ciphertext)> = [(0, 1), (1, 2), (2, 0)]
+      .iter()
+      .map(|(idx, cnt)| (client_key.encrypt(*idx), client_key.encrypt(*cnt)))
+      .collect();
+
+    restock(&server_key, &mut inventory, &client_key.encrypt(2), &client_key.encrypt(3));
+    sell(&server_key, &mut inventory, &client_key.encrypt(1), &client_key.encrypt(1));
+    // Selling more than the stock empties it, restocking past the largest count stops there
+    sell(&server_key, &mut inventory, &client_key.encrypt(0), &client_key.encrypt(3));
+    restock(&server_key, &mut inventory, &client_key.encrypt(1), &client_key.encrypt(3));
+    // No entry has code 3, so nothing changes
+    restock(&server_key, &mut inventory, &client_key.encrypt(3), &client_key.encrypt(2));
+
+    let counts: Vec<u64> = inventory.iter().map(|(_, cnt)| client_key.decrypt(cnt)).collect();
+    assert_eq!(counts, [0, 3, 3]);
+
+    let stock_ciphertext = query_encrypted_inventory(&server_key, &client_key.encrypt(2), &inventory);
+    assert_eq!(client_key.decrypt(&stock_ciphertext), 3);
+  }
+  #[test]
+  fn test_query_with_existence() {
+    let (client_key, server_key) = gen_keys(PARAM_MESSAGE_2_CARRY_2_KS_PBS);
+    // Code 2 is listed but sold out, code 3 is not listed at all, and the count of code 0 wraps like in `query`
+    let inventory = [(0, 1), (2, 0), (0, 1), (1, 3), (0, 1), (0, 1)];
+
+    for (code, exists, count) in [(0, 1, 4 % 4), (1, 1, 3), (2, 1, 0), (3, 0, 0)] {
+      let result = query_with_existence(&server_key, &client_key.encrypt(code), &inventory);
+      assert_eq!(client_key.decrypt(&result.exists), exists, "Failed code {}", code);
+      assert_eq!(client_key.decrypt(&result.count), count, "Failed code {}", code);
+    }
+
+    // Four matches would wrap a summed flag to 0
+    let result = query_with_existence(&server_key, &client_key.encrypt(0), &[(0, 0); 4]);
+    assert_eq!(client_key.decrypt(&result.exists), 1);
+  }
+  #[test]
+  fn test_query_range() {
+    let (client_key, server_key) = gen_keys(PARAM_MESSAGE_2_CARRY_2_KS_PBS);
+    let inventory = [(0, 1), (1, 1), (2, 1), (3, 3), (1, 1)];
+
+    for (lo, hi, total) in [(1, 2, 3), (0, 0, 1), (3, 3, 3), (2, 1, 0)] {
+      let stock = query_range(&server_key, &client_key.encrypt(lo), &client_key.encrypt(hi), &inventory);
+      assert_eq!(client_key.decrypt(&stock), total, "Failed range {}..={}", lo, hi);
+    }
+
+    let public = query_range(&server_key, &server_key.create_trivial(0), &server_key.create_trivial(1), &inventory);
+    assert_eq!(client_key.decrypt(&public), 3);
+  }
+  #[test]
+  fn test_query_below_threshold() {
+    let (client_key, server_key) = gen_keys(PARAM_MESSAGE_2_CARRY_2_KS_PBS);
+    let inventory = [(1, 1), (0, 3), (1, 1), (2, 3), (2, 2)];
+
+    for (code, threshold, alert) in [(1, 3, 1), (1, 2, 0), (0, 3, 0), (3, 1, 1)] {
+      let target = client_key.encrypt(code);
+      let flag = FheQuery::new(&server_key)
+        .target(&target)
+        .inventory(&inventory)
+        .mode(QueryMode::BelowThreshold(threshold))
+        .run()
+        .unwrap();
+      assert_eq!(client_key.decrypt(&flag), alert, "Failed code {} below {}", code, threshold);
+    }
+
+    // A true total of 5 saturates at 3, which still is not below 3
+    let target = client_key.encrypt(2);
+    let flag = FheQuery::new(&server_key)
+      .target(&target)
+      .inventory(&inventory)
+      .mode(QueryMode::BelowThreshold(3))
+      .run()
+      .unwrap();
+    assert_eq!(client_key.decrypt(&flag), 0);
+  }
+  #[test]
+  fn test_query_radix() {
+    // 8 blocks of 2 bits hold any 16-bit code
+    let (client_key, server_key) = gen_keys_radix(PARAM_MESSAGE_2_CARRY_2_KS_PBS, 8);
+    let inventory: [(u16, u16); 3] = [(40000, 60000), (16, 1), (40000, 10000)];
+
+    let target = client_key.encrypt(40000u16);
+    let stock_ciphertext = query_radix(&server_key, &target, &inventory).unwrap();
+    assert_eq!(client_key.decrypt::<u64>(&stock_ciphertext), 70000);
+
+    let target = client_key.encrypt(16u16);
+    let stock_ciphertext = query_radix(&server_key, &target, &inventory).unwrap();
+    assert_eq!(client_key.decrypt::<u64>(&stock_ciphertext), 1);
+
+    let (client_key, server_key) = gen_keys_radix(PARAM_MESSAGE_2_CARRY_2_KS_PBS, 16);
+    let inventory: [(u32, u32); 2] = [(3_000_000_000, 5), (17, 2_000_000)];
+
+    let target = client_key.encrypt(3_000_000_000u32);
+    let stock_ciphertext = query_radix(&server_key, &target, &inventory).unwrap();
+    assert_eq!(client_key.decrypt::<u64>(&stock_ciphertext), 5);
+
+    // 4 blocks hold 8 bits, and truncated to them code 256 would be counted as code 0
+    let (client_key, server_key) = gen_keys_radix(PARAM_MESSAGE_2_CARRY_2_KS_PBS, 4);
+    let inventory: [(u16, u16); 2] = [(0, 1), (256, 5)];
+
+    let target = client_key.encrypt(0u16);
+    let stock = query_radix(&server_key, &target, &inventory);
+    assert_eq!(stock.err(), Some(QueryError::CodeTooWide { code: 256, bits: 8 }));
+  }
+  #[test]
+  fn test_query_many() {
+    let (client_key, server_key) = gen_keys(PARAM_MESSAGE_2_CARRY_2_KS_PBS);
+    let inventory = [(3, 1), (0, 2), (3, 2), (1, 1), (2, 3), (1, 1)];
+    let targets: Vec<Ciphertext> = [2, 0, 3, 1, 3].into_iter().map(|code| client_key.encrypt(code)).collect();
+
+    let batched = query_many(&server_key, &targets, &inventory);
+    assert_eq!(batched.len(), targets.len());
+    for (target, stock_ciphertext) in targets.iter().zip(&batched) {
+      let single = query(&server_key, target, &inventory);
+      assert_eq!(client_key.decrypt(stock_ciphertext), client_key.decrypt(&single));
+    }
+
+    assert!(query_many(&server_key, &[], &inventory).is_empty());
+  }
+  #[test]
+  fn test_query_lookup() {
+    let (client_key, server_key) = gen_keys(PARAM_MESSAGE_2_CARRY_2_KS_PBS);
+    // Code 3 wraps, code 9 lies outside the message space
+    let inventory = [(3, 1), (0, 2), (3, 2), (1, 1), (9, 1), (3, 3)];
+
+    for code in 0..4 {
+      let target = client_key.encrypt(code);
+      let looked_up = query_lookup(&server_key, &target, &inventory);
+      let expected = query(&server_key, &target, &inventory);
+      assert_eq!(client_key.decrypt(&looked_up), client_key.decrypt(&expected), "Failed code {}", code);
+    }
+  }
+
+  #[test]
+  #[ignore = "benchmark, run with --ignored --nocapture"]
+  fn bench_query_lookup() {
+    let (client_key, server_key) = gen_keys(PARAM_MESSAGE_2_CARRY_2_KS_PBS);
+    let inventory: Vec<(u8, u8)> = (0..64).map(|i| (i % 4, 1)).collect();
+    let target = client_key.encrypt(2);
+
+    let start = Instant::now();
+    let _ = query(&server_key, &target, &inventory);
+    println!("equality + mul: {:?}", start.elapsed());
+
+    let start = Instant::now();
+    let _ = query_many(&server_key, std::slice::from_ref(&target), &inventory);
+    println!("one per entry:  {:?}", start.elapsed());
+
+    let start = Instant::now();
+    let _ = query_lookup(&server_key, &target, &inventory);
+    println!("one in total:   {:?}", start.elapsed());
+  }
+  #[test]
+  fn test_versioned_serialization() {
+    let (client_key, server_key) = gen_keys(PARAM_MESSAGE_2_CARRY_2_KS_PBS);
+
+    // Client and server only share bytes and files
+    let client_key: ClientKey = from_versioned_bytes(&to_versioned_bytes(&client_key)).unwrap();
+    let path = std::env::temp_dir().join(format!("fhe-server-key-{}.bin", std::process::id()));
+    save_to_file(&server_key, &path).unwrap();
+    let server_key: ServerKey = load_from_file(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+
+    let target_bytes = to_versioned_bytes(&client_key.encrypt(1));
+    let target: Ciphertext = from_versioned_bytes(&target_bytes).unwrap();
+    let result_bytes = to_versioned_bytes(&query(&server_key, &target, &[(1, 2), (0, 1)]));
+    let result: Ciphertext = from_versioned_bytes(&result_bytes).unwrap();
+    assert_eq!(client_key.decrypt(&result), 2);
+
+    assert!(matches!(
+      from_versioned_bytes::<ServerKey>(&target_bytes),
+      Err(FormatError::WrongKind { expected: 2, found: 3 })
+    ));
+    let mut future = target_bytes.clone();
+    future[4] = 2;
+    assert!(matches!(
+      from_versioned_bytes::<Ciphertext>(&future),
+      Err(FormatError::UnsupportedVersion { version: 2 })
+    ));
+    assert!(matches!(from_versioned_bytes::<Ciphertext>(b"not a ciphertext"), Err(FormatError::BadMagic)));
+    assert!(matches!(from_versioned_bytes::<Ciphertext>(&target_bytes[..3]), Err(FormatError::Io(_))));
+    assert!(matches!(
+      from_versioned_bytes::<Ciphertext>(&target_bytes[..target_bytes.len() / 2]),
+      Err(FormatError::Payload(_))
+    ));
+  }
+  #[test]
+  fn test_http_demo() {
+    let (client_key, server_key) = gen_keys(PARAM_MESSAGE_2_CARRY_2_KS_PBS);
+    let inventory = parse_inventory("# code count\n1 2\n\n0 1\n1 1\n").unwrap();
+    assert_eq!(inventory, [(1, 2), (0, 1), (1, 1)]);
+    assert_eq!(parse_inventory("1 2\n1 two\n"), Err(InventoryError { line: 2 }));
+    assert_eq!(parse_inventory("1 2 3\n"), Err(InventoryError { line: 1 }));
+
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap().to_string();
+    std::thread::scope(|scope| {
+      scope.spawn(|| {
+        for stream in listener.incoming().take(2) {
+          handle_query_request(stream.unwrap(), &server_key, &inventory).unwrap();
+        }
+      });
+
+      let stream = std::net::TcpStream::connect(&addr).unwrap();
+      let result = send_query(stream, &addr, &client_key.encrypt(1)).unwrap();
+      assert_eq!(client_key.decrypt(&result), 3);
+
+      // A body that is not a target comes back as an error, not a ciphertext
+      let mut stream = std::net::TcpStream::connect(&addr).unwrap();
+      stream.write_all(b"POST /query HTTP/1.1\r\nContent-Length: 16\r\n\r\nnot a ciphertext").unwrap();
+      let (status_line, body) = read_http_message(&mut BufReader::new(stream)).unwrap();
+      assert_eq!(status_line, "HTTP/1.1 400 Bad Request");
+      assert_eq!(String::from_utf8(body).unwrap(), FormatError::BadMagic.to_string());
+    });
+
+    // An oversized Content-Length is refused before anything is allocated for it
+    for length in [(MAX_BODY_LEN + 1).to_string(), u64::MAX.to_string()] {
+      let request = format!("POST /query HTTP/1.1\r\nContent-Length: {}\r\n\r\n", length);
+      let result = read_http_message(&mut BufReader::new(request.as_bytes()));
+      assert!(matches!(result, Err(HttpError::Malformed)), "Failed length {}", length);
+    }
+
+    // So is a head past MAX_HEAD_LEN, whether one endless line or many short headers
+    let long_header = format!("POST /query HTTP/1.1\r\nX-Padding: {}\r\n\r\n", "a".repeat(MAX_HEAD_LEN as usize));
+    let many_headers = format!("POST /query HTTP/1.1\r\n{}\r\n", "X-Padding: a\r\n".repeat(MAX_HEAD_LEN as usize / 8));
+    let no_newline = "a".repeat(2 * MAX_HEAD_LEN as usize);
+    for request in [long_header, many_headers, no_newline] {
+      let result = read_http_message(&mut BufReader::new(request.as_bytes()));
+      assert!(matches!(result, Err(HttpError::Malformed)), "Failed request of {} bytes", request.len());
+    }
+  }
+  #[test]
+  fn test_compressed_on_the_wire() {
+    let client_key = ClientKey::new(PARAM_MESSAGE_2_CARRY_2_KS_PBS);
+    let inventory = [(1, 2), (0, 1), (1, 1)];
+
+    let full_key_bytes = to_versioned_bytes(&ServerKey::new(&client_key));
+    let compressed_key_bytes = to_versioned_bytes(&CompressedServerKey::new(&client_key));
+    assert!(compressed_key_bytes.len() < full_key_bytes.len());
+    let server_key = from_maybe_compressed_bytes::<ServerKey, CompressedServerKey>(&compressed_key_bytes).unwrap();
+
+    let full_target_bytes = to_versioned_bytes(&client_key.encrypt(1));
+    let compressed_target_bytes = to_versioned_bytes(&client_key.encrypt_compressed(1));
+    assert!(compressed_target_bytes.len() < full_target_bytes.len());
+
+    // The server takes either form of the target
+    for target_bytes in [full_target_bytes, compressed_target_bytes] {
+      let target = from_maybe_compressed_bytes::<Ciphertext, CompressedCiphertext>(&target_bytes).unwrap();
+      let stock_ciphertext = query(&server_key, &target, &inventory);
+      assert_eq!(client_key.decrypt(&stock_ciphertext), 3);
+    }
+
+    assert!(matches!(
+      from_maybe_compressed_bytes::<ServerKey, CompressedServerKey>(&to_versioned_bytes(&client_key)),
+      Err(FormatError::WrongKind { expected: 2, found: 1 })
+    ));
+  }
+  #[test]
+  fn test_compact_public_key() {
+    let (client_key, server_key) = gen_keys(PARAM_MESSAGE_2_CARRY_2_COMPACT_PK_KS_PBS);
+    let inventory = [(2, 1), (1, 3), (2, 2)];
+
+    // Encrypting parties only ever see the public key, here after a round trip through bytes
+    let public_key_bytes = to_versioned_bytes(&CompressedCompactPublicKey::new(&client_key));
+    let public_key: CompactPublicKey =
+      from_maybe_compressed_bytes::<_, CompressedCompactPublicKey>(&public_key_bytes).unwrap();
+    assert_eq!(public_key.parameters, compact_public_key(&client_key).unwrap().parameters);
+
+    for code in 0..4 {
+      let target = public_key.encrypt(check_code(code, public_key.parameters.message_modulus()).unwrap() as u64);
+      let stock_ciphertext = query(&server_key, &target, &inventory);
+      let expected = inventory.iter().filter(|(idx, _)| *idx == code).map(|(_, cnt)| *cnt as u64).sum::<u64>();
+      assert_eq!(client_key.decrypt(&stock_ciphertext), expected, "Failed code {}", code);
+    }
+    assert_eq!(
+      check_code(4, public_key.parameters.message_modulus()),
+      Err(QueryError::CodeOutOfRange { code: 4 })
+    );
+
+    // Bootstrap-first sets encrypt under a secret key whose dimension is not a power of two
+    let client_key = ClientKey::new(PARAM_MESSAGE_2_CARRY_2_PBS_KS);
+    assert_eq!(compact_public_key(&client_key).err(), Some(ParameterError::NoCompactPublicKey));
+  }
+}